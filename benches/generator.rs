@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use nostr_vanity::generator::NostrKeyPair;
+use secp256k1::rand::rng;
+use std::hint::black_box;
+
+const KEYS: usize = 1_000;
+
+fn bench_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_1k");
+    group.bench_function("per_key_rng", |b| {
+        b.iter(|| {
+            for _ in 0..KEYS {
+                black_box(NostrKeyPair::generate().unwrap());
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        let mut rng = rng();
+        b.iter(|| black_box(NostrKeyPair::generate_batch(&mut rng, KEYS).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_generation);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+use aho_corasick::{AhoCorasick, Input};
+use criterion::{criterion_group, criterion_main, Criterion};
+use nostr_vanity::generator::{seeded_rng, NostrKeyPair};
+use nostr_vanity::matcher::{MatchOptions, MatchType, PatternMatcher};
+use secp256k1::rand::Rng;
+use std::hint::black_box;
+
+const CHARSET: &[u8] = b"023456789acdefghjklmnpqrstuvwxyz";
+
+fn dictionary(size: usize, len: usize) -> Vec<String> {
+    let mut rng = seeded_rng(7, 0);
+    (0..size)
+        .map(|_| {
+            (0..len)
+                .map(|_| CHARSET[rng.random_range(0..CHARSET.len())] as char)
+                .collect()
+        })
+        .collect()
+}
+
+fn keypairs(count: usize) -> Vec<NostrKeyPair> {
+    let mut rng = seeded_rng(11, 0);
+    (0..count)
+        .map(|_| NostrKeyPair::generate_with_rng(&mut rng).unwrap())
+        .collect()
+}
+
+fn bench_large_dictionary(c: &mut Criterion) {
+    let keys = keypairs(100);
+    
+    for (size, match_type) in [
+        (1_000, MatchType::Prefix),
+        (10_000, MatchType::Prefix),
+        (10_000, MatchType::Contains),
+    ] {
+        let words = dictionary(size, 6);
+        let matcher = PatternMatcher::from_strings(
+            words.clone(),
+            match_type.clone(),
+            MatchOptions::default(),
+        )
+        .unwrap();
+        
+        let mut group = c.benchmark_group(format!("{}k_{:?}", size / 1000, match_type).to_lowercase());
+        group.bench_function("naive", |b| {
+            b.iter(|| {
+                for keypair in &keys {
+                    black_box(
+                        matcher
+                            .patterns()
+                            .iter()
+                            .find(|p| p.matches(&keypair.npub)),
+                    );
+                }
+            })
+        });
+        // Prefix trie for prefixes, Aho-Corasick for contains
+        group.bench_function("matcher", |b| {
+            b.iter(|| {
+                for keypair in &keys {
+                    black_box(matcher.find_match(keypair));
+                }
+            })
+        });
+        
+        // Aho-Corasick restricted to the longest prefix and filtered to
+        // matches at the start, for comparison with the trie
+        if match_type == MatchType::Prefix {
+            let automaton = AhoCorasick::new(&words).unwrap();
+            group.bench_function("aho_corasick", |b| {
+                b.iter(|| {
+                    for keypair in &keys {
+                        let body = &keypair.npub[5..];
+                        black_box(
+                            automaton
+                                .find_overlapping_iter(Input::new(body).range(..6))
+                                .filter(|m| m.start() == 0)
+                                .map(|m| m.pattern())
+                                .min(),
+                        );
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_large_dictionary);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+use std::path::Path;
+use thiserror::Error;
+
+// Everything the library can fail with. The binary reports these through
+// anyhow; embedders can match on the kind instead of parsing messages.
+#[derive(Debug, Error)]
+pub enum VanityError {
+    // A pattern, or a pattern option such as a substitution or look-alike
+    // group, that can't be searched for
+    #[error("{0}")]
+    InvalidPattern(String),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    // Other unusable input: settings, relay URLs, files in the wrong shape
+    #[error("{0}")]
+    InvalidInput(String),
+    // Encoding a key, or decoding an npub/nsec that isn't one
+    #[error("{0}")]
+    Bech32(String),
+    #[error(transparent)]
+    Key(#[from] secp256k1::Error),
+    // A key pair whose nsec or phrase doesn't give back its npub
+    #[error("{0}")]
+    Mismatch(String),
+    #[error(transparent)]
+    Mnemonic(#[from] bip39::Error),
+    #[error("{0}")]
+    Rng(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    // An I/O error with the file or directory it concerns
+    #[error("{context}: {source}")]
+    File {
+        context: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Qr(#[from] qrcode::types::QrError),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    // A malformed or oversized message between worker and coordinator
+    #[error("{0}")]
+    Protocol(String),
+    #[cfg(feature = "native")]
+    #[error(transparent)]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+pub type Result<T, E = VanityError> = std::result::Result<T, E>;
+
+impl VanityError {
+    // `File` with a context like "cannot write to <path>"
+    pub fn file(action: &str, path: &Path, source: std::io::Error) -> Self {
+        VanityError::File {
+            context: format!("{} {}", action, path.display()),
+            source,
+        }
+    }
+}
+
+impl From<bech32::primitives::hrp::Error> for VanityError {
+    fn from(e: bech32::primitives::hrp::Error) -> Self {
+        VanityError::Bech32(e.to_string())
+    }
+}
+
+impl From<bech32::EncodeError> for VanityError {
+    fn from(e: bech32::EncodeError) -> Self {
+        VanityError::Bech32(e.to_string())
+    }
+}
+
+impl From<bech32::DecodeError> for VanityError {
+    fn from(e: bech32::DecodeError) -> Self {
+        VanityError::Bech32(e.to_string())
+    }
+}
+
+impl From<hex::FromHexError> for VanityError {
+    fn from(e: hex::FromHexError) -> Self {
+        VanityError::InvalidInput(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::NostrKeyPair;
+    use crate::matcher::{MatchOptions, MatchType, Pattern};
+    
+    #[test]
+    fn test_error_kinds() {
+        let pattern = |value: &str, match_type| Pattern::new(value.to_string(), match_type, MatchOptions::default());
+        assert!(matches!(pattern("a:0", MatchType::Repeat), Err(VanityError::InvalidPattern(_))));
+        assert!(matches!(pattern("(", MatchType::Regex), Err(VanityError::Regex(_))));
+        
+        let keypair = NostrKeyPair::generate().unwrap();
+        assert!(matches!(NostrKeyPair::from_nsec(&keypair.npub), Err(VanityError::Bech32(_))));
+        assert!(matches!(NostrKeyPair::from_nsec("nsec1"), Err(VanityError::Bech32(_))));
+        
+        let missing = Path::new("/nonexistent/nostr_vanity");
+        let error = crate::utils::read_char_classes(missing).unwrap_err();
+        assert!(matches!(error, VanityError::File { .. }));
+        assert!(error.to_string().starts_with("failed to read /nonexistent/nostr_vanity: "), "{}", error);
+    }
+}
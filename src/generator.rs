@@ -1,32 +1,557 @@
-use anyhow::Result;
 use bech32::{self, Hrp, Bech32};
-use secp256k1::{PublicKey, SecretKey, SECP256K1};
-use secp256k1::rand::rng;
+use bip39::Mnemonic;
+use hmac::{Hmac, KeyInit, Mac};
+use secp256k1::constants::{GENERATOR_X, GENERATOR_Y};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, SignOnly};
+use sha2::Sha512;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use secp256k1::rand::rngs::OsRng;
+use secp256k1::rand::{rng, TryRngCore};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use zeroize::{Zeroize, Zeroizing};
 
-#[derive(Debug, Clone)]
+use crate::error::{Result, VanityError};
+use crate::matcher::{
+    parse_bookend, parse_charclass, parse_positions, parse_repeat, MatchOptions, MatchType, Target, WILDCARD_ANY, WILDCARD_ONE,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NostrKeyPair {
     pub npub: String,
     pub nsec: String,
     pub hex_pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mnemonic: Option<String>,
 }
 
-impl NostrKeyPair {
-    pub fn generate() -> Result<Self> {
-        let mut rng = rng();
-        let secret_key = SecretKey::new(&mut rng);
-        let public_key = PublicKey::from_secret_key(SECP256K1, &secret_key);
+// Wipes the nsec and phrase when a pair is dropped, clones included
+impl Drop for NostrKeyPair {
+    fn drop(&mut self) {
+        self.nsec.zeroize();
+        self.mnemonic.zeroize();
+    }
+}
+
+// A freshly generated key with only the npub encoded, which is all the search
+// loop needs for the common case. Call `finalize` once a key has matched to
+// fill in the remaining encodings.
+#[derive(Debug, Clone)]
+pub struct CandidateKey {
+    secret_key: SecretKey,
+    // The full public point, kept so `next` can step from it
+    point: PublicKey,
+    public_key: [u8; 32],
+    pub npub: String,
+    pub mnemonic: Option<String>,
+}
+
+// Most candidates are discarded a moment after generation, so each one erases
+// its secret key (and phrase) as soon as it goes out of scope.
+impl Drop for CandidateKey {
+    fn drop(&mut self) {
+        self.secret_key.non_secure_erase();
+        self.mnemonic.zeroize();
+    }
+}
+
+// Text forms of a key that patterns can be matched against. Candidates encode
+// the nsec and hex pubkey on demand, full keypairs just borrow them.
+pub trait KeyText {
+    fn npub(&self) -> Cow<'_, str>;
+    fn nsec(&self) -> Cow<'_, str>;
+    fn hex_pubkey(&self) -> Cow<'_, str>;
+}
+
+// Characters after the `npub1`/`nsec1` prefix: 52 data characters encoding
+// 32 bytes, followed by a 6 character checksum.
+pub const BECH32_BODY_LEN: usize = 58;
+pub const BECH32_DATA_LEN: usize = 52;
+pub const BECH32_CHECKSUM_LEN: usize = 6;
+
+// 256 bits fill 51 data characters plus one more bit, so the last data
+// character is that bit followed by four zero padding bits: `q` or `s`.
+pub const BECH32_PADDED_CHARS: [char; 2] = ['q', 's'];
+
+// Body characters kept at each end by `short_display`, as clients commonly
+// truncate npubs
+pub const SHORT_HEAD_LEN: usize = 8;
+pub const SHORT_TAIL_LEN: usize = 4;
+
+thread_local! {
+    // Deriving public keys only needs a signing context. Each thread builds
+    // and blinds its own once, so workers never share context state.
+    static SECP: Secp256k1<SignOnly> = {
+        let mut secp = Secp256k1::signing_only();
+        secp.randomize(&mut rng());
+        secp
+    };
+}
+
+fn public_key(secret_key: &SecretKey) -> PublicKey {
+    SECP.with(|secp| PublicKey::from_secret_key(secp, secret_key))
+}
+
+// The curve's generator point G, the public key of the secret 1
+fn generator() -> &'static PublicKey {
+    static GENERATOR: OnceLock<PublicKey> = OnceLock::new();
+    GENERATOR.get_or_init(|| {
+        let mut bytes = [0u8; 65];
+        bytes[0] = 0x04;
+        bytes[1..33].copy_from_slice(&GENERATOR_X);
+        bytes[33..].copy_from_slice(&GENERATOR_Y);
+        PublicKey::from_byte_array_uncompressed(bytes).expect("the generator is a valid point")
+    })
+}
+
+// Length of generated BIP-39 phrases: 12 words hold 128 bits of entropy, 24
+// words 256. Either way the key is derived by NIP-06 from the phrase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MnemonicLength {
+    #[default]
+    Words12,
+    Words24,
+}
+
+impl MnemonicLength {
+    pub fn words(&self) -> usize {
+        match self {
+            MnemonicLength::Words12 => 12,
+            MnemonicLength::Words24 => 24,
+        }
+    }
+    
+    fn entropy_bytes(&self) -> usize {
+        match self {
+            MnemonicLength::Words12 => 16,
+            MnemonicLength::Words24 => 32,
+        }
+    }
+}
+
+// Draws in a row that may give an invalid key before the random source is
+// assumed broken. A working source gives one about once in 2^128 draws.
+pub const MAX_KEY_RETRIES: u32 = 8;
+
+// Runs `draw` until it gives a valid key, at most MAX_KEY_RETRIES times,
+// adding the draws that had to be redone to `retries`. Only invalid keys are
+// retried; any other error is returned at once.
+fn retry_invalid<T>(retries: &mut u64, mut draw: impl FnMut() -> Result<T>) -> Result<T> {
+    for _ in 0..MAX_KEY_RETRIES {
+        match draw() {
+            Err(VanityError::Key(_)) => *retries += 1,
+            result => return result,
+        }
+    }
+    Err(VanityError::Rng(format!(
+        "the random source gave {} invalid keys in a row",
+        MAX_KEY_RETRIES
+    )))
+}
+
+// NIP-06 derivation path m/44'/1237'/0'/0/0
+const NIP06_PATH: [u32; 5] = [44 | HARDENED, 1237 | HARDENED, HARDENED, 0, 0];
+const HARDENED: u32 = 0x8000_0000;
+
+impl CandidateKey {
+    pub fn generate_with_rng<R: TryRngCore + ?Sized>(rng: &mut R) -> Result<Self> {
+        Self::generate_with_retries(rng, &mut 0)
+    }
+    
+    // Like `generate_with_rng`, adding the number of draws that gave an
+    // invalid secret and had to be redone to `retries`
+    pub fn generate_with_retries<R: TryRngCore + ?Sized>(rng: &mut R, retries: &mut u64) -> Result<Self> {
+        let secret_key = retry_invalid(retries, || {
+            let mut bytes = Zeroizing::new([0u8; 32]);
+            rng.try_fill_bytes(bytes.as_mut()).map_err(rng_error)?;
+            Ok(SecretKey::from_byte_array(*bytes)?)
+        })?;
+        Self::from_secret_key(secret_key)
+    }
+    
+    pub fn generate_mnemonic_with_rng<R: TryRngCore + ?Sized>(rng: &mut R) -> Result<Self> {
+        Self::generate_mnemonic_with_length(rng, MnemonicLength::default())
+    }
+    
+    pub fn generate_mnemonic_with_length<R: TryRngCore + ?Sized>(rng: &mut R, length: MnemonicLength) -> Result<Self> {
+        Self::generate_mnemonic_with_retries(rng, length, &mut 0)
+    }
+    
+    // Like `generate_mnemonic_with_length`, counting phrases that derived no
+    // valid key in `retries`
+    pub fn generate_mnemonic_with_retries<R: TryRngCore + ?Sized>(
+        rng: &mut R,
+        length: MnemonicLength,
+        retries: &mut u64,
+    ) -> Result<Self> {
+        retry_invalid(retries, || {
+            let mut entropy = Zeroizing::new([0u8; 32]);
+            let entropy = &mut entropy[..length.entropy_bytes()];
+            rng.try_fill_bytes(entropy).map_err(rng_error)?;
+            let mnemonic = Mnemonic::from_entropy(entropy)?;
+            Self::from_mnemonic(&Zeroizing::new(mnemonic.to_string()))
+        })
+    }
+    
+    pub fn from_mnemonic(phrase: &str) -> Result<Self> {
+        let mnemonic = Mnemonic::parse(phrase)?;
+        let seed = Zeroizing::new(mnemonic.to_seed(""));
+        let secret_key = derive_nip06_key(seed.as_ref())?;
         
-        let npub = encode_bech32("npub", &public_key.serialize()[1..])?;
-        let nsec = encode_bech32("nsec", &secret_key.secret_bytes())?;
-        let hex_pubkey = hex::encode(&public_key.serialize()[1..]);
+        let mut candidate = Self::from_secret_key(secret_key)?;
+        candidate.mnemonic = Some(mnemonic.to_string());
+        Ok(candidate)
+    }
+    
+    fn from_secret_key(secret_key: SecretKey) -> Result<Self> {
+        Self::from_point(secret_key, public_key(&secret_key))
+    }
+    
+    fn from_point(secret_key: SecretKey, point: PublicKey) -> Result<Self> {
+        let public_key = point.x_only_public_key().0.serialize();
+        let npub = encode_bech32("npub", &public_key)?;
         
-        Ok(NostrKeyPair {
+        Ok(CandidateKey {
+            secret_key,
+            point,
+            public_key,
             npub,
-            nsec,
-            hex_pubkey,
+            mnemonic: None,
         })
     }
     
+    // The key whose secret is one more than this one's. Its public key is
+    // this key's point plus G, a point addition rather than the scalar
+    // multiplication a fresh key costs. The new key has no phrase, and is
+    // only as secret as this one: anyone holding either secret can step to
+    // the other.
+    pub fn next(&self) -> Result<Self> {
+        let secret_key = self.secret_key.add_tweak(&Scalar::ONE)?;
+        let point = self.point.combine(generator())?;
+        Self::from_point(secret_key, point)
+    }
+    
+    pub fn finalize(mut self) -> NostrKeyPair {
+        NostrKeyPair {
+            nsec: self.nsec().into_owned(),
+            hex_pubkey: hex::encode(self.public_key),
+            npub: std::mem::take(&mut self.npub),
+            mnemonic: self.mnemonic.take(),
+        }
+    }
+}
+
+impl KeyText for CandidateKey {
+    fn npub(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.npub)
+    }
+    
+    fn nsec(&self) -> Cow<'_, str> {
+        Cow::Owned(
+            encode_bech32("nsec", Zeroizing::new(self.secret_key.secret_bytes()).as_ref())
+                .expect("32 bytes always encode under a valid hrp"),
+        )
+    }
+    
+    fn hex_pubkey(&self) -> Cow<'_, str> {
+        Cow::Owned(hex::encode(self.public_key))
+    }
+}
+
+impl KeyText for NostrKeyPair {
+    fn npub(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.npub)
+    }
+    
+    fn nsec(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.nsec)
+    }
+    
+    fn hex_pubkey(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.hex_pubkey)
+    }
+}
+
+impl NostrKeyPair {
+    pub fn generate() -> Result<Self> {
+        Self::generate_with_rng(&mut rng())
+    }
+    
+    pub fn generate_with_rng<R: TryRngCore + ?Sized>(rng: &mut R) -> Result<Self> {
+        Ok(CandidateKey::generate_with_rng(rng)?.finalize())
+    }
+    
+    // Generates `n` keys from one RNG. Every key uses the thread's secp256k1
+    // context, so nothing is set up per key.
+    pub fn generate_batch<R: TryRngCore + ?Sized>(rng: &mut R, n: usize) -> Result<Vec<Self>> {
+        (0..n).map(|_| Self::generate_with_rng(rng)).collect()
+    }
+    
+    pub fn generate_mnemonic_with_rng<R: TryRngCore + ?Sized>(rng: &mut R) -> Result<Self> {
+        Ok(CandidateKey::generate_mnemonic_with_rng(rng)?.finalize())
+    }
+    
+    pub fn generate_mnemonic_with_length<R: TryRngCore + ?Sized>(rng: &mut R, length: MnemonicLength) -> Result<Self> {
+        Ok(CandidateKey::generate_mnemonic_with_length(rng, length)?.finalize())
+    }
+    
+    pub fn from_mnemonic(phrase: &str) -> Result<Self> {
+        Ok(CandidateKey::from_mnemonic(phrase)?.finalize())
+    }
+    
+    // Rebuilds the full pair from an nsec, recomputing the npub and hex key
+    pub fn from_nsec(nsec: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(decode_bech32_key("nsec", nsec)?);
+        Self::from_secret_bytes(&bytes)
+    }
+    
+    // The pair for a raw 32-byte secret key. Zero and values past the curve
+    // order aren't keys and are rejected.
+    pub fn from_secret_bytes(bytes: &[u8; 32]) -> Result<Self> {
+        Ok(CandidateKey::from_secret_key(SecretKey::from_byte_array(*bytes)?)?.finalize())
+    }
+    
+    // The pair whose secret is one more than this one's, see
+    // `CandidateKey::next`. A pair doesn't keep its public point, so this
+    // recomputes it first; walking many keys is cheaper with `CandidateKey`.
+    pub fn next(&self) -> Result<Self> {
+        let bytes = Zeroizing::new(decode_bech32_key("nsec", &self.nsec)?);
+        let secret_key = SecretKey::from_byte_array(*bytes)?;
+        Ok(CandidateKey::from_secret_key(secret_key)?.next()?.finalize())
+    }
+    
+    // Re-derives the key from the nsec, and from the phrase when there is
+    // one, and checks it gives this npub and hex key. Every match is checked
+    // before it's shown or written, so a bug that paired a key with the wrong
+    // secret can't hand out a key nobody controls.
+    pub fn verify(&self) -> Result<()> {
+        let derived = Self::from_nsec(&self.nsec)?;
+        if derived.npub != self.npub || derived.hex_pubkey != self.hex_pubkey {
+            return Err(VanityError::Mismatch(format!(
+                "{} doesn't belong to its nsec, which derives {}",
+                self.npub,
+                derived.npub
+            )));
+        }
+        if let Some(phrase) = &self.mnemonic {
+            if Self::from_mnemonic(phrase)?.nsec != self.nsec {
+                return Err(VanityError::Mismatch(format!(
+                    "the mnemonic for {} derives a different key",
+                    self.npub
+                )));
+            }
+        }
+        Ok(())
+    }
+    
+    // The npub truncated the way clients show it, e.g. `npub1satxyz2q…w3kd`.
+    // An npub too short to truncate is shown whole.
+    pub fn short_display(&self) -> String {
+        let parts = self
+            .npub
+            .get("npub1".len()..)
+            .filter(|body| body.len() > SHORT_HEAD_LEN + SHORT_TAIL_LEN)
+            .and_then(|body| Some((body.get(..SHORT_HEAD_LEN)?, body.get(body.len() - SHORT_TAIL_LEN..)?)));
+        match parts {
+            Some((head, tail)) => format!("npub1{}…{}", head, tail),
+            None => self.npub.clone(),
+        }
+    }
+    
+    // NIP-19 nprofile: TLV type 0 holds the 32-byte pubkey, type 1 a relay URL
+    pub fn nprofile(&self, relays: &[String]) -> Result<String> {
+        let mut tlv = vec![0, 32];
+        tlv.extend(hex::decode(&self.hex_pubkey)?);
+        
+        for relay in relays {
+            let length = u8::try_from(relay.len())
+                .map_err(|_| VanityError::InvalidInput(format!("relay URL too long: {}", relay)))?;
+            tlv.push(1);
+            tlv.push(length);
+            tlv.extend(relay.as_bytes());
+        }
+        
+        encode_bech32("nprofile", &tlv)
+    }
+}
+
+// BIP-32 derivation of the NIP-06 account key from a BIP-39 seed
+fn derive_nip06_key(seed: &[u8]) -> Result<SecretKey> {
+    let (mut key, mut chain_code) = hmac_sha512(b"Bitcoin seed", &[seed]);
+    let mut secret_key = SecretKey::from_byte_array(*key)?;
+    
+    for index in NIP06_PATH {
+        let index_bytes = index.to_be_bytes();
+        (key, chain_code) = if index & HARDENED != 0 {
+            let parent = Zeroizing::new(secret_key.secret_bytes());
+            hmac_sha512(chain_code.as_ref(), &[&[0], parent.as_ref(), &index_bytes])
+        } else {
+            hmac_sha512(chain_code.as_ref(), &[&public_key(&secret_key).serialize(), &index_bytes])
+        };
+        let tweak = Scalar::from_be_bytes(*key).map_err(|_| secp256k1::Error::InvalidTweak)?;
+        let child = secret_key.add_tweak(&tweak)?;
+        secret_key.non_secure_erase();
+        secret_key = child;
+    }
+    
+    Ok(secret_key)
+}
+
+// Both halves are secret (a key and a chain code), so they come back wrapped
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    let mut output = mac.finalize().into_bytes();
+    
+    let mut left = Zeroizing::new([0u8; 32]);
+    let mut right = Zeroizing::new([0u8; 32]);
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    output.as_mut_slice().zeroize();
+    (left, right)
+}
+
+// Where key material comes from. The README's Security section covers the
+// tradeoffs between them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RngSource {
+    // Every key read straight from the operating system's CSPRNG
+    Os,
+    // ChaCha20 seeded once from the operating system, or from a fixed seed
+    #[default]
+    Chacha,
+    // The CPU's RDRAND instruction, with no software in between
+    Hardware,
+}
+
+// A generator as `new_rng` gives it out
+pub type KeySource = Box<dyn KeyRng>;
+
+// Any generator, boxable. Drawing can fail, as the operating system and
+// RDRAND can, and failures come back as `Rng` errors for the search to stop
+// on. `dyn KeyRng` is itself a `TryRngCore`, so it can be passed to the
+// `generate_*` functions.
+pub trait KeyRng {
+    fn fill_key_bytes(&mut self, dest: &mut [u8]) -> Result<()>;
+}
+
+impl<R: TryRngCore> KeyRng for R {
+    fn fill_key_bytes(&mut self, dest: &mut [u8]) -> Result<()> {
+        self.try_fill_bytes(dest).map_err(rng_error)
+    }
+}
+
+impl TryRngCore for dyn KeyRng {
+    type Error = VanityError;
+    
+    fn try_next_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.fill_key_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+    
+    fn try_next_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        self.fill_key_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+    
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<()> {
+        self.fill_key_bytes(dest)
+    }
+}
+
+// A generator for `source`. `seed` and `stream` select a reproducible ChaCha
+// stream as in `seeded_rng`, so they only apply to the chacha source.
+pub fn new_rng(source: RngSource, seed: Option<u64>, stream: u64) -> Result<KeySource> {
+    match (source, seed) {
+        (RngSource::Chacha, Some(seed)) => Ok(Box::new(seeded_rng(seed, stream))),
+        (RngSource::Chacha, None) => Ok(Box::new(ChaCha20Rng::from_rng(&mut rng()))),
+        (_, Some(_)) => Err(VanityError::Rng("a seed can only be used with the chacha RNG".to_string())),
+        (RngSource::Os, None) => Ok(Box::new(OsRng)),
+        (RngSource::Hardware, None) => Ok(Box::new(HardwareRng::new()?)),
+    }
+}
+
+fn rng_error(error: impl std::fmt::Display) -> VanityError {
+    VanityError::Rng(error.to_string())
+}
+
+// Attempts per value before giving up, as Intel recommends for RDRAND
+const RDRAND_RETRIES: usize = 10;
+
+// Reads RDRAND directly. Some AMD CPUs have returned all ones while
+// reporting success, so that value is treated as a failure and retried.
+#[derive(Debug)]
+pub struct HardwareRng(());
+
+impl HardwareRng {
+    pub fn new() -> Result<Self> {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("rdrand") {
+            return Ok(HardwareRng(()));
+        }
+        Err(VanityError::Rng("this CPU has no RDRAND instruction".to_string()))
+    }
+}
+
+// A CPU whose RDRAND keeps failing gives an error rather than a panic, so a
+// search stops cleanly instead of losing a worker thread
+impl TryRngCore for HardwareRng {
+    type Error = VanityError;
+    
+    fn try_next_u32(&mut self) -> Result<u32> {
+        Ok(self.try_next_u64()? as u32)
+    }
+    
+    fn try_next_u64(&mut self) -> Result<u64> {
+        rdrand64()
+    }
+    
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<()> {
+        for chunk in dest.chunks_mut(8) {
+            let mut value = Zeroizing::new(self.try_next_u64()?.to_le_bytes());
+            chunk.copy_from_slice(&value[..chunk.len()]);
+            value.zeroize();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rdrand64() -> Result<u64> {
+    #[target_feature(enable = "rdrand")]
+    fn step(value: &mut u64) -> i32 {
+        std::arch::x86_64::_rdrand64_step(value)
+    }
+    
+    for _ in 0..RDRAND_RETRIES {
+        let mut value = 0;
+        // SAFETY: HardwareRng::new has checked that the CPU supports RDRAND
+        if unsafe { step(&mut value) } == 1 && value != u64::MAX {
+            return Ok(value);
+        }
+    }
+    Err(VanityError::Rng(format!("RDRAND failed {} times in a row", RDRAND_RETRIES)))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn rdrand64() -> Result<u64> {
+    unreachable!("HardwareRng::new fails without RDRAND")
+}
+
+// One of the ChaCha streams for `seed`. Streams never overlap, so keys drawn
+// from different ones can't repeat, and each always yields the same sequence.
+// Seeded searches give every key its own stream, numbered by its place in the
+// run.
+pub fn seeded_rng(seed: u64, stream: u64) -> ChaCha20Rng {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    rng.set_stream(stream);
+    rng
 }
 
 fn encode_bech32(hrp_str: &str, data: &[u8]) -> Result<String> {
@@ -35,14 +560,187 @@ fn encode_bech32(hrp_str: &str, data: &[u8]) -> Result<String> {
     Ok(encoded)
 }
 
-pub fn validate_bech32_chars(pattern: &str) -> bool {
-    const VALID_CHARS: &str = "023456789acdefghjklmnpqrstuvwxyz";
-    pattern.chars().all(|c| VALID_CHARS.contains(c))
+// Inverse of `encode_bech32` for 32-byte keys, checking the hrp
+fn decode_bech32_key(expected_hrp: &str, encoded: &str) -> Result<[u8; 32]> {
+    let (hrp, data) = bech32::decode(encoded.trim())?;
+    let data = Zeroizing::new(data);
+    if hrp.as_str() != expected_hrp {
+        return Err(VanityError::Bech32(format!("expected an {}, got a '{}' key", expected_hrp, hrp)));
+    }
+    data.as_slice()
+        .try_into()
+        .map_err(|_| VanityError::Bech32(format!("{} does not hold 32 bytes", expected_hrp)))
+}
+
+// The x-only public key bytes an npub encodes
+pub fn decode_npub(npub: &str) -> Result<[u8; 32]> {
+    decode_bech32_key("npub", npub)
+}
+
+pub const BECH32_CHARS: &str = "023456789acdefghjklmnpqrstuvwxyz";
+
+// Names every character bech32 can't hold along with the closest legal
+// stand-in, e.g. `'o' -> '0'`, and spells out the corrected pattern when
+// every character has one.
+pub fn validate_bech32_chars(pattern: &str) -> Result<()> {
+    check_bech32_chars(pattern, false)
+}
+
+// Like `validate_bech32_chars`, also accepting the `?` and `*` wildcards of
+// prefix, suffix and contains patterns
+pub fn validate_bech32_wildcard_chars(pattern: &str) -> Result<()> {
+    check_bech32_chars(pattern, true)
+}
+
+fn check_bech32_chars(pattern: &str, wildcards: bool) -> Result<()> {
+    let valid = |c: char| BECH32_CHARS.contains(c) || (wildcards && (c == WILDCARD_ONE || c == WILDCARD_ANY));
+    let mut invalid: Vec<char> = Vec::new();
+    for c in pattern.chars() {
+        if !valid(c) && !invalid.contains(&c) {
+            invalid.push(c);
+        }
+    }
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    
+    let described: Vec<String> = invalid
+        .iter()
+        .map(|&c| {
+            let shown = describe_char(c);
+            match bech32_stand_ins(c) {
+                Some(stand_ins) => {
+                    let options: Vec<String> = stand_ins.chars().map(|s| format!("'{}'", s)).collect();
+                    format!("{} -> {}", shown, options.join(" or "))
+                }
+                None => format!("{} (no stand-in)", shown),
+            }
+        })
+        .collect();
+    let mut message = format!(
+        "pattern '{}' has characters bech32 can't hold: {}",
+        pattern,
+        described.join(", ")
+    );
+    let corrected: Option<String> = pattern
+        .chars()
+        .map(|c| match valid(c) {
+            true => Some(c),
+            false => bech32_stand_ins(c).and_then(|s| s.chars().next()),
+        })
+        .collect();
+    if let Some(corrected) = corrected {
+        message.push_str(&format!(". Try '{}'", corrected));
+    }
+    if wildcards {
+        message.push_str(&format!(". Valid: {} and the wildcards ? and *", BECH32_CHARS));
+    } else {
+        message.push_str(&format!(". Valid: {}", BECH32_CHARS));
+    }
+    Err(VanityError::InvalidPattern(message))
+}
+
+// Quotes a character, adding its code point when it isn't plain ASCII so
+// look-alikes such as a Cyrillic `а` are visibly different from `a`
+fn describe_char(c: char) -> String {
+    if c.is_ascii_graphic() {
+        format!("'{}'", c)
+    } else {
+        format!("'{}' (U+{:04X})", c, c as u32)
+    }
+}
+
+// Legal characters that read like `c`, best first. Mirrors the --leet table
+// for the four characters bech32 leaves out, lowercases ASCII letters and
+// folds full-width forms and common Greek and Cyrillic look-alikes.
+fn bech32_stand_ins(c: char) -> Option<&'static str> {
+    let c = match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0)?,
+        _ => c,
+    };
+    let c = match c {
+        'а' | 'α' => 'a',
+        'с' | 'ϲ' => 'c',
+        'е' | 'ε' => 'e',
+        'һ' => 'h',
+        'і' | 'ι' => 'i',
+        'ј' => 'j',
+        'κ' | 'к' => 'k',
+        'м' => 'm',
+        'η' | 'п' => 'n',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ν' => 'v',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        _ => c.to_ascii_lowercase(),
+    };
+    const LOOK_ALIKES: [(char, &str); 4] = [('b', "8"), ('i', "jl"), ('o', "0"), ('1', "l")];
+    if let Some((_, stand_ins)) = LOOK_ALIKES.iter().find(|(from, _)| *from == c) {
+        return Some(stand_ins);
+    }
+    BECH32_CHARS.find(c).map(|index| &BECH32_CHARS[index..index + 1])
+}
+
+// Rejects patterns that need more characters than the matched text has, which
+// could never match. That's the target's body less any excluded checksum and
+// skipped characters, plus the prefix with `include_prefix`. Prefix, suffix and
+// contains patterns are bounded by their length, bookends by both ends
+// together, repeats and charclasses by their minimum run and positions by their
+// largest index. Regexes and custom patterns aren't checked.
+pub fn validate_pattern_length(pattern: &str, match_type: &MatchType, options: &MatchOptions) -> Result<()> {
+    let needed = match match_type {
+        // A `*` may match nothing, a `?` takes one character
+        MatchType::Prefix | MatchType::Suffix | MatchType::Contains | MatchType::Either => {
+            pattern.chars().filter(|&c| c != WILDCARD_ANY).count()
+        }
+        MatchType::Repeat => parse_repeat(pattern)?.1,
+        MatchType::Charclass => parse_charclass(pattern)?.1,
+        MatchType::Bookend => {
+            let (prefix, suffix) = parse_bookend(pattern)?;
+            prefix.chars().count() + suffix.chars().count()
+        }
+        MatchType::Position => parse_positions(pattern)?
+            .iter()
+            .map(|(index, _)| index + 1)
+            .max()
+            .unwrap_or(0),
+        MatchType::Regex | MatchType::Custom => return Ok(()),
+    };
+    let target = options.target;
+    let available = options.body_len() + if options.include_prefix { target.prefix_len() } else { 0 };
+    if needed > available {
+        let mut region = match (target, options.include_prefix) {
+            (Target::Hex, _) => String::new(),
+            (_, true) => " including the prefix".to_string(),
+            (_, false) => " after the prefix".to_string(),
+        };
+        if options.exclude_checksum && target != Target::Hex {
+            region.push_str(" outside the checksum");
+        }
+        if options.skip > 0 {
+            let joiner = if region.is_empty() { "after" } else { "and" };
+            region.push_str(&format!(" {} the {} skipped", joiner, options.skip));
+        }
+        return Err(VanityError::InvalidPattern(format!(
+            "pattern '{}' needs {} characters, but keys only have {}{}",
+            pattern, needed, available, region
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_hex_chars(pattern: &str) -> bool {
+    pattern.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use secp256k1::rand::RngCore;
     
     #[test]
     fn test_key_generation() {
@@ -52,12 +750,407 @@ mod tests {
         assert_eq!(keypair.hex_pubkey.len(), 64);
     }
     
+    #[test]
+    fn test_seeded_generation() {
+        let a = NostrKeyPair::generate_with_rng(&mut seeded_rng(42, 0)).unwrap();
+        let b = NostrKeyPair::generate_with_rng(&mut seeded_rng(42, 0)).unwrap();
+        let c = NostrKeyPair::generate_with_rng(&mut seeded_rng(42, 1)).unwrap();
+        assert_eq!(a.nsec, b.nsec);
+        assert_ne!(a.nsec, c.nsec);
+        
+        let candidate = CandidateKey::generate_with_rng(&mut seeded_rng(42, 0)).unwrap();
+        assert_eq!(candidate.nsec(), a.nsec);
+        assert_eq!(candidate.hex_pubkey(), a.hex_pubkey);
+        assert_eq!(candidate.finalize().npub, a.npub);
+        
+        let batch = NostrKeyPair::generate_batch(&mut seeded_rng(42, 0), 3).unwrap();
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch[0].nsec, a.nsec);
+        assert_ne!(batch[1].nsec, a.nsec);
+    }
+    
+    // Gives `zeros` all-zero values, never a valid secret, then real ones
+    struct FailingRng {
+        zeros: usize,
+        inner: ChaCha20Rng,
+    }
+    
+    impl RngCore for FailingRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+        
+        fn next_u64(&mut self) -> u64 {
+            let mut bytes = [0u8; 8];
+            self.fill_bytes(&mut bytes);
+            u64::from_le_bytes(bytes)
+        }
+        
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            if self.zeros > 0 {
+                self.zeros -= 1;
+                dest.fill(0);
+            } else {
+                self.inner.fill_bytes(dest);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_key_retries() {
+        let mut retries = 0;
+        let mut rng = FailingRng { zeros: 2, inner: seeded_rng(42, 0) };
+        let candidate = CandidateKey::generate_with_retries(&mut rng, &mut retries).unwrap();
+        assert_eq!(retries, 2);
+        // The retried draws are skipped, not mixed into the key
+        let expected = NostrKeyPair::generate_with_rng(&mut seeded_rng(42, 0)).unwrap();
+        assert_eq!(candidate.finalize().nsec, expected.nsec);
+        
+        // A source that never gives a valid key is an error, not a hang
+        let mut retries = 0;
+        let mut rng = FailingRng { zeros: usize::MAX, inner: seeded_rng(42, 0) };
+        let error = CandidateKey::generate_with_retries(&mut rng, &mut retries).unwrap_err();
+        assert!(matches!(error, VanityError::Rng(_)));
+        assert_eq!(retries, u64::from(MAX_KEY_RETRIES));
+    }
+    
+    // Fails every draw, as RDRAND does when it runs dry
+    struct BrokenRng;
+    
+    impl TryRngCore for BrokenRng {
+        type Error = VanityError;
+        
+        fn try_next_u32(&mut self) -> Result<u32> {
+            Err(VanityError::Rng("no entropy".to_string()))
+        }
+        
+        fn try_next_u64(&mut self) -> Result<u64> {
+            Err(VanityError::Rng("no entropy".to_string()))
+        }
+        
+        fn try_fill_bytes(&mut self, _dest: &mut [u8]) -> Result<()> {
+            Err(VanityError::Rng("no entropy".to_string()))
+        }
+    }
+    
+    #[test]
+    fn test_failing_source() {
+        // A failed draw is an error straight away, not a retry or a panic
+        let mut retries = 0;
+        let error = CandidateKey::generate_with_retries(&mut BrokenRng, &mut retries).unwrap_err();
+        assert!(matches!(error, VanityError::Rng(_)));
+        assert_eq!(retries, 0);
+        
+        let mut source: KeySource = Box::new(BrokenRng);
+        assert!(matches!(NostrKeyPair::generate_with_rng(source.as_mut()), Err(VanityError::Rng(_))));
+        assert!(NostrKeyPair::generate_mnemonic_with_rng(source.as_mut()).is_err());
+    }
+    
+    #[test]
+    fn test_rng_sources() {
+        for source in [RngSource::Os, RngSource::Chacha] {
+            let mut rng = new_rng(source, None, 0).unwrap();
+            let a = NostrKeyPair::generate_with_rng(rng.as_mut()).unwrap();
+            let b = NostrKeyPair::generate_with_rng(rng.as_mut()).unwrap();
+            assert_ne!(a.nsec, b.nsec);
+        }
+        
+        let seeded = NostrKeyPair::generate_with_rng(new_rng(RngSource::Chacha, Some(42), 1).unwrap().as_mut());
+        let expected = NostrKeyPair::generate_with_rng(&mut seeded_rng(42, 1)).unwrap();
+        assert_eq!(seeded.unwrap().nsec, expected.nsec);
+        assert!(new_rng(RngSource::Os, Some(42), 0).is_err());
+        assert!(new_rng(RngSource::Hardware, Some(42), 0).is_err());
+        
+        // Only exercised on machines that have RDRAND
+        if let Ok(mut rng) = new_rng(RngSource::Hardware, None, 0) {
+            let mut bytes = [0u8; 13];
+            rng.try_fill_bytes(&mut bytes).unwrap();
+            assert_ne!(bytes, [0u8; 13]);
+            assert_ne!(rng.try_next_u64().unwrap(), rng.try_next_u64().unwrap());
+        }
+    }
+    
+    #[test]
+    fn test_nip06_derivation() {
+        let keypair = NostrKeyPair::from_mnemonic(
+            "leader monkey parrot ring guide accident before fence cannon height naive bean",
+        ).unwrap();
+        assert_eq!(
+            keypair.nsec,
+            "nsec10allq0gjx7fddtzef0ax00mdps9t2kmtrldkyjfs8l5xruwvh2dq0lhhkp"
+        );
+        assert_eq!(
+            keypair.npub,
+            "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu"
+        );
+        
+        let keypair = NostrKeyPair::generate_mnemonic_with_rng(&mut seeded_rng(1, 0)).unwrap();
+        let phrase = keypair.mnemonic.clone().unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 12);
+        assert_eq!(NostrKeyPair::from_mnemonic(&phrase).unwrap().nsec, keypair.nsec);
+        
+        // The 24-word vector from NIP-06
+        let keypair = NostrKeyPair::from_mnemonic(
+            "what bleak badge arrange retreat wolf trade produce cricket blur garlic valid proud rude \
+            strong choose busy staff weather area salt hollow arm fade",
+        ).unwrap();
+        assert_eq!(
+            keypair.nsec,
+            "nsec1c9wh8xy5eqdzln7n5t0ctgxjcrdug73gp5yj0x03gntn67h83twssdfhel"
+        );
+        
+        let keypair = NostrKeyPair::generate_mnemonic_with_length(&mut seeded_rng(1, 0), MnemonicLength::Words24).unwrap();
+        let phrase = keypair.mnemonic.clone().unwrap();
+        assert_eq!(phrase.split_whitespace().count(), MnemonicLength::Words24.words());
+        assert_eq!(NostrKeyPair::from_mnemonic(&phrase).unwrap().nsec, keypair.nsec);
+    }
+    
+    #[test]
+    fn test_from_nsec() {
+        let keypair = NostrKeyPair::from_nsec(
+            "nsec10allq0gjx7fddtzef0ax00mdps9t2kmtrldkyjfs8l5xruwvh2dq0lhhkp",
+        ).unwrap();
+        assert_eq!(
+            keypair.npub,
+            "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu"
+        );
+        
+        assert!(NostrKeyPair::from_nsec(&keypair.npub).is_err());
+        assert!(NostrKeyPair::from_nsec("nsec1qqqq").is_err());
+        
+        assert_eq!(keypair.short_display(), "npub1zutzeysa…x7nu");
+        
+        let short = NostrKeyPair {
+            npub: "npub1zutzeysa".to_string(),
+            nsec: String::new(),
+            hex_pubkey: String::new(),
+            mnemonic: None,
+        };
+        assert_eq!(short.short_display(), "npub1zutzeysa");
+    }
+    
+    // Published vectors, so an encoding change in bech32 or secp256k1 can't
+    // slip through as a round trip that agrees with itself
+    #[test]
+    fn test_known_vectors() {
+        let secret = |hex_key: &str| -> [u8; 32] { hex::decode(hex_key).unwrap().try_into().unwrap() };
+        
+        // NIP-06: the key of the 12-word test mnemonic
+        let keypair = NostrKeyPair::from_secret_bytes(&secret(
+            "7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a",
+        )).unwrap();
+        assert_eq!(keypair.nsec, "nsec10allq0gjx7fddtzef0ax00mdps9t2kmtrldkyjfs8l5xruwvh2dq0lhhkp");
+        assert_eq!(keypair.hex_pubkey, "17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917");
+        assert_eq!(keypair.npub, "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu");
+        
+        // NIP-19: the nsec and npub examples, which aren't a pair
+        let keypair = NostrKeyPair::from_secret_bytes(&secret(
+            "67dea2ed018072d675f5415ecfaed7d2597555e202d85b3d65ea4e58d2d92ffa",
+        )).unwrap();
+        assert_eq!(keypair.nsec, "nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5");
+        let public_key = secret("7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e");
+        assert_eq!(
+            encode_bech32("npub", &public_key).unwrap(),
+            "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg"
+        );
+        assert_eq!(
+            decode_npub("npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg").unwrap(),
+            public_key
+        );
+        
+        // A secret key of 1 has the curve's generator point as its public key
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let keypair = NostrKeyPair::from_secret_bytes(&one).unwrap();
+        assert_eq!(keypair.hex_pubkey, "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        
+        assert!(NostrKeyPair::from_secret_bytes(&[0; 32]).is_err());
+        assert!(NostrKeyPair::from_secret_bytes(&[0xff; 32]).is_err());
+    }
+    
+    #[test]
+    fn test_verify() {
+        let keypair = NostrKeyPair::generate().unwrap();
+        keypair.verify().unwrap();
+        let phrased = NostrKeyPair::generate_mnemonic_with_rng(&mut seeded_rng(7, 0)).unwrap();
+        phrased.verify().unwrap();
+        
+        // Any part taken from another key is caught
+        let other = NostrKeyPair::generate_mnemonic_with_rng(&mut seeded_rng(8, 0)).unwrap();
+        let mut swapped = keypair.clone();
+        swapped.npub = other.npub.clone();
+        assert!(matches!(swapped.verify(), Err(VanityError::Mismatch(_))));
+        let mut swapped = keypair.clone();
+        swapped.hex_pubkey = other.hex_pubkey.clone();
+        assert!(matches!(swapped.verify(), Err(VanityError::Mismatch(_))));
+        let mut swapped = phrased.clone();
+        swapped.mnemonic = other.mnemonic.clone();
+        assert!(matches!(swapped.verify(), Err(VanityError::Mismatch(_))));
+    }
+    
+    #[test]
+    fn test_next_key() {
+        // Stepping by point addition lands on the same keys as deriving each
+        // secret from scratch
+        let mut rng = seeded_rng(3, 0);
+        let mut candidate = CandidateKey::generate_with_rng(&mut rng).unwrap();
+        for _ in 0..5 {
+            let next = candidate.next().unwrap();
+            let expected = candidate.secret_key.add_tweak(&Scalar::ONE).unwrap();
+            assert_eq!(next.npub, CandidateKey::from_secret_key(expected).unwrap().npub);
+            candidate = next;
+        }
+        
+        // 1 + 1 = 2
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let mut two = [0u8; 32];
+        two[31] = 2;
+        let next = NostrKeyPair::from_secret_bytes(&one).unwrap().next().unwrap();
+        assert_eq!(next.nsec, NostrKeyPair::from_secret_bytes(&two).unwrap().nsec);
+        assert_eq!(next.npub, NostrKeyPair::from_secret_bytes(&two).unwrap().npub);
+        
+        // Stepped keys have no phrase
+        let keypair = NostrKeyPair::generate_mnemonic_with_rng(&mut rng).unwrap();
+        assert_eq!(keypair.next().unwrap().mnemonic, None);
+    }
+    
+    #[test]
+    fn test_bech32_round_trip() {
+        let mut rng = seeded_rng(5, 0);
+        for _ in 0..10 {
+            let keypair = NostrKeyPair::generate_with_rng(&mut rng).unwrap();
+            let decoded = NostrKeyPair::from_nsec(&keypair.nsec).unwrap();
+            assert_eq!(decoded.npub, keypair.npub);
+            assert_eq!(decoded.nsec, keypair.nsec);
+            assert_eq!(decoded.hex_pubkey, keypair.hex_pubkey);
+            
+            let public_key = decode_npub(&keypair.npub).unwrap();
+            assert_eq!(hex::encode(public_key), keypair.hex_pubkey);
+            assert_eq!(encode_bech32("npub", &public_key).unwrap(), keypair.npub);
+        }
+        
+        let keypair = NostrKeyPair::generate().unwrap();
+        assert!(decode_npub(&keypair.nsec).is_err());
+        let mut corrupted = keypair.npub.clone();
+        corrupted.replace_range(10..11, if &keypair.npub[10..11] == "q" { "p" } else { "q" });
+        assert!(decode_npub(&corrupted).is_err());
+    }
+    
+    #[test]
+    fn test_nprofile_encoding() {
+        let keypair = NostrKeyPair {
+            npub: String::new(),
+            nsec: String::new(),
+            hex_pubkey: "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d".to_string(),
+            mnemonic: None,
+        };
+        let relays = vec!["wss://r.x.com".to_string(), "wss://djbas.sadkb.com".to_string()];
+        assert_eq!(
+            keypair.nprofile(&relays).unwrap(),
+            "nprofile1qqsrhuxx8l9ex335q7he0f09aej04zpazpl0ne2cgukyawd24mayt8gpp4mhxue69uhhytnc9e3k7mgpz4mhxue69uhkg6nzv9ejuumpv34kytnrdaksjlyr9p"
+        );
+    }
+    
     #[test]
     fn test_validate_bech32_chars() {
-        assert!(validate_bech32_chars("test"));
-        assert!(validate_bech32_chars("023"));
-        assert!(!validate_bech32_chars("test1")); 
-        assert!(!validate_bech32_chars("TEST"));
-        assert!(!validate_bech32_chars("bio"));
+        assert!(validate_bech32_chars("test").is_ok());
+        assert!(validate_bech32_chars("023").is_ok());
+        assert!(validate_bech32_chars("test1").is_err()); 
+        assert!(validate_bech32_chars("TEST").is_err());
+        assert!(validate_bech32_chars("bio").is_err());
+    }
+    
+    #[test]
+    fn test_validate_bech32_chars_suggestions() {
+        let message = validate_bech32_chars("bio").unwrap_err().to_string();
+        assert!(message.contains("'b' -> '8', 'i' -> 'j' or 'l', 'o' -> '0'"), "{}", message);
+        assert!(message.contains("Try '8j0'"), "{}", message);
+        
+        // Repeated characters are only listed once
+        let message = validate_bech32_chars("sat1o1").unwrap_err().to_string();
+        assert!(message.contains("'1' -> 'l', 'o' -> '0'."), "{}", message);
+        assert!(message.contains("Try 'satl0l'"), "{}", message);
+        
+        let message = validate_bech32_chars("Sat").unwrap_err().to_string();
+        assert!(message.contains("'S' -> 's'"), "{}", message);
+        
+        // Cyrillic and full-width look-alikes show their code points
+        let message = validate_bech32_chars("nоstr").unwrap_err().to_string();
+        assert!(message.contains("'о' (U+043E) -> '0'"), "{}", message);
+        assert!(message.contains("Try 'n0str'"), "{}", message);
+        let message = validate_bech32_chars("ｓａｔ").unwrap_err().to_string();
+        assert!(message.contains("Try 'sat'"), "{}", message);
+        
+        // Nothing reads like '!', so there is no corrected pattern
+        let message = validate_bech32_chars("sat!").unwrap_err().to_string();
+        assert!(message.contains("'!' (no stand-in)"), "{}", message);
+        assert!(!message.contains("Try"), "{}", message);
+        
+        assert!(validate_bech32_chars("sat?sh").is_err());
+        assert!(validate_bech32_wildcard_chars("sat?sh*").is_ok());
+        let message = validate_bech32_wildcard_chars("b?t").unwrap_err().to_string();
+        assert!(message.contains("Try '8?t'"), "{}", message);
+        assert!(message.ends_with("and the wildcards ? and *"), "{}", message);
+    }
+    
+    #[test]
+    fn test_validate_hex_chars() {
+        assert!(validate_hex_chars("00ff"));
+        assert!(validate_hex_chars("dead"));
+        assert!(!validate_hex_chars("xyz"));
+        assert!(!validate_hex_chars("DEAD"));
+    }
+    
+    #[test]
+    fn test_validate_pattern_length() {
+        let longest = "q".repeat(BECH32_BODY_LEN);
+        let too_long = "q".repeat(BECH32_BODY_LEN + 1);
+        let npub = MatchOptions::default();
+        for match_type in [MatchType::Prefix, MatchType::Suffix, MatchType::Contains] {
+            assert!(validate_pattern_length(&longest, &match_type, &npub).is_ok());
+            assert!(validate_pattern_length(&too_long, &match_type, &npub).is_err());
+        }
+        assert!(validate_pattern_length("q:58", &MatchType::Repeat, &npub).is_ok());
+        assert!(validate_pattern_length("q:59", &MatchType::Repeat, &npub).is_err());
+        assert!(validate_pattern_length("alpha:58", &MatchType::Charclass, &npub).is_ok());
+        assert!(validate_pattern_length("alpha:59", &MatchType::Charclass, &npub).is_err());
+        assert!(validate_pattern_length(&format!("{}...{}", "q".repeat(29), "s".repeat(29)), &MatchType::Bookend, &npub).is_ok());
+        assert!(validate_pattern_length(&format!("{}...{}", "q".repeat(30), "s".repeat(29)), &MatchType::Bookend, &npub).is_err());
+        assert!(validate_pattern_length("sat", &MatchType::Bookend, &npub).is_err());
+        assert!(validate_pattern_length("0:q,57:s", &MatchType::Position, &npub).is_ok());
+        assert!(validate_pattern_length("58:q", &MatchType::Position, &npub).is_err());
+        assert!(validate_pattern_length(&too_long, &MatchType::Regex, &npub).is_ok());
+        
+        // Skipped characters can't be matched
+        let skip2 = MatchOptions { skip: 2, ..MatchOptions::default() };
+        let skip100 = MatchOptions { skip: 100, ..MatchOptions::default() };
+        assert!(validate_pattern_length(&longest[2..], &MatchType::Prefix, &skip2).is_ok());
+        assert!(validate_pattern_length(&longest[1..], &MatchType::Prefix, &skip2).is_err());
+        assert!(validate_pattern_length("55:q", &MatchType::Position, &skip2).is_ok());
+        assert!(validate_pattern_length("56:q", &MatchType::Position, &skip2).is_err());
+        assert!(validate_pattern_length("q", &MatchType::Prefix, &skip100).is_err());
+        
+        // Stars may match nothing, question marks take a character each
+        assert!(validate_pattern_length(&format!("{}*", longest), &MatchType::Prefix, &npub).is_ok());
+        assert!(validate_pattern_length(&format!("{}?", longest), &MatchType::Prefix, &npub).is_err());
+        
+        // Hex keys are 64 characters long, and --include-prefix adds the `npub1`
+        let hex = MatchOptions { target: Target::Hex, ..MatchOptions::default() };
+        assert!(validate_pattern_length(&"0".repeat(64), &MatchType::Prefix, &hex).is_ok());
+        assert!(validate_pattern_length(&"0".repeat(65), &MatchType::Prefix, &hex).is_err());
+        assert!(validate_pattern_length(&"0".repeat(70), &MatchType::Contains, &hex).is_err());
+        assert!(validate_pattern_length("63:0", &MatchType::Position, &hex).is_ok());
+        assert!(validate_pattern_length("64:0", &MatchType::Position, &hex).is_err());
+        let full = MatchOptions { include_prefix: true, ..MatchOptions::default() };
+        assert!(validate_pattern_length(&format!("npub1{}", longest), &MatchType::Prefix, &full).is_ok());
+        assert!(validate_pattern_length(&format!("npub1{}", too_long), &MatchType::Prefix, &full).is_err());
+        assert!(validate_pattern_length("62:q", &MatchType::Position, &full).is_ok());
+        assert!(validate_pattern_length("63:q", &MatchType::Position, &full).is_err());
+        
+        // An excluded checksum leaves fewer characters to match
+        let no_checksum = MatchOptions { exclude_checksum: true, ..MatchOptions::default() };
+        assert!(validate_pattern_length(&longest, &MatchType::Suffix, &no_checksum).is_err());
+        assert!(validate_pattern_length(&longest[..no_checksum.body_len()], &MatchType::Suffix, &no_checksum).is_ok());
     }
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+pub mod error;
+pub mod generator;
+pub mod matcher;
+pub mod network;
+#[cfg(feature = "native")]
+pub mod search;
+pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::VanityError;
+pub use generator::{CandidateKey, NostrKeyPair};
+pub use matcher::{MatchOptions, MatchType, Pattern, PatternMatcher, Target};
+#[cfg(feature = "native")]
+pub use search::{SearchConfig, VanitySearch};
+pub use utils::VanityResult;
@@ -1,23 +1,86 @@
-mod generator;
-mod matcher;
-mod utils;
-
-use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use crossbeam_channel::unbounded;
-use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::*;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-
-use crate::generator::{validate_bech32_chars, NostrKeyPair};
-use crate::matcher::{MatchType, PatternMatcher};
-use crate::utils::{
-    estimate_time, parse_patterns_string, read_patterns_from_file, 
-    write_csv_result, write_result_to_file, VanityResult
+use anyhow::{anyhow, bail, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use console::Term;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use nostr_vanity::error::VanityError;
+use nostr_vanity::generator::{
+    new_rng, seeded_rng, validate_bech32_chars, validate_bech32_wildcard_chars, validate_hex_chars, validate_pattern_length,
+    MnemonicLength, NostrKeyPair, RngSource,
+};
+use nostr_vanity::matcher::{
+    dedupe_patterns, parse_bookend, parse_charclass, parse_positions, parse_repeat, strip_wildcards, CharClasses, MatchOptions,
+    MatchType, Pattern, PatternMatcher, Substitutions, Target,
 };
+use nostr_vanity::network::{read_message, serve_metrics, Coordinator, Message, Metrics, Reporter};
+use nostr_vanity::search::{benchmark, SearchConfig, VanitySearch};
+use nostr_vanity::utils::{
+    check_fifo, check_writable, check_writable_dir, estimate_all, estimate_combined, estimate_seconds, estimate_time,
+    expected_attempts, format_duration, format_keypair, format_keypair_csv, format_keypair_json, highlight_matches,
+    keypair_csv_header, meta_path, parse_patterns_string, pattern_output_path, pattern_probability, qr_terminal, read_char_classes,
+    read_checkpoint, read_denylist, read_job_config, read_patterns_from_file, read_patterns_from_stdin, write_checkpoint, write_json_file,
+    write_keypair, write_keypair_json_file, write_meta_file, write_qr_pngs, Checkpoint, Estimate, JobConfig, OutputFormat, PatternSpec, ProgressStats,
+    ResultWriter, RunSummary, SecretOutput, DEFAULT_MAX_PATTERNS,
+};
+
+// Patterns named in the banner before the rest are only counted
+const BANNER_PATTERNS: usize = 20;
+// Above this many patterns checked one by one, every key is noticeably slower
+const SLOW_UNINDEXED_PATTERNS: usize = 1000;
+const BENCHMARK_DURATION: Duration = Duration::from_secs(5);
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+// How often --balance prints its per-pattern breakdown
+const BALANCE_INTERVAL: Duration = Duration::from_secs(10);
+// Time between plain progress lines when stderr isn't a terminal
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+// Longest the progress loop waits before noticing the search has ended
+const PROGRESS_POLL: Duration = Duration::from_millis(100);
+// How often a --connect worker sends its attempt count to the coordinator
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+// How often --server prints the combined stats of its workers
+const SERVER_STATS_INTERVAL: Duration = Duration::from_secs(10);
+// Patterns expected to take longer than this need --force
+const IMPOSSIBLE_AFTER_SECS: f64 = 100.0 * 365.25 * 24.0 * 3600.0;
+// ChaCha stream for --shuffle-patterns, one no worker thread draws keys from
+const SHUFFLE_STREAM: u64 = u64::MAX;
+// Assumed rate when nothing has been measured
+const KEYS_PER_SEC_PER_THREAD: f64 = 100_000.0;
+
+// Exit codes for scripts. Success is 0 and any other error exits with 1.
+const EXIT_NO_MATCH: i32 = 2;
+const EXIT_INVALID_PATTERN: i32 = 3;
+const EXIT_OUTPUT_FAILED: i32 = 4;
+const EXIT_INTERRUPTED: i32 = 130;
+
+// How a search ended, which decides the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SearchOutcome {
+    Found,
+    NoMatch,
+    Interrupted,
+    OutputFailed,
+}
+
+impl SearchOutcome {
+    fn exit_code(self) -> i32 {
+        match self {
+            SearchOutcome::Found => 0,
+            SearchOutcome::NoMatch => EXIT_NO_MATCH,
+            SearchOutcome::Interrupted => EXIT_INTERRUPTED,
+            SearchOutcome::OutputFailed => EXIT_OUTPUT_FAILED,
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Nostr vanity npub address generator", long_about = None)]
@@ -25,32 +88,228 @@ struct Args {
     #[arg(short, long, help = "Comma-separated list of patterns to search for")]
     patterns: Option<String>,
     
-    #[arg(short = 'f', long, help = "Path to CSV file containing patterns")]
+    #[arg(long, value_name = "LIST", help = "Comma-separated substrings a matching key must not contain")]
+    exclude: Option<String>,
+    
+    #[arg(long, value_name = "PATH", help = "File of npubs, one per line, never to report even if they match")]
+    denylist: Option<PathBuf>,
+    
+    #[arg(long, value_name = "CHAR", default_value_t = ',', help = "Character separating the --patterns and --exclude lists")]
+    pattern_delimiter: char,
+    
+    #[arg(short = 'f', long, help = "Path to CSV file containing patterns, or - to read them from stdin")]
     file: Option<PathBuf>,
     
-    #[arg(short, long, help = "Output file path (optional)")]
-    output: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", help = "Read patterns and search settings from a TOML job file; flags on the command line override its settings")]
+    config: Option<PathBuf>,
+    
+    // Patterns from the --config file, filled in after parsing
+    #[arg(skip)]
+    config_patterns: Vec<PatternSpec>,
+    
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_PATTERNS, help = "Refuse pattern files with more than N patterns")]
+    max_patterns: usize,
     
-    #[arg(long, help = "Output in CSV format")]
+    #[arg(short, long, env = "NOSTR_VANITY_OUTPUT", help = "Output file path, repeatable; a .txt, .csv or .json extension picks the file's format")]
+    output: Vec<PathBuf>,
+    
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["generate", "server"], help = "Append each match to a file in this directory named after its pattern, e.g. sat.txt; --csv and --json pick the format")]
+    output_dir: Option<PathBuf>,
+    
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["generate", "server"], help = "Stream matches into a named pipe as they're found, waiting for a reader before the search starts; a .txt, .csv or .json extension picks the format")]
+    output_fifo: Option<PathBuf>,
+    
+    #[arg(long, requires = "output", conflicts_with_all = ["generate", "server"], help = "Record how each key was found (pattern, attempts, time, threads, version) in a <output>.meta.json file next to each output")]
+    meta: bool,
+    
+    #[arg(long, conflicts_with_all = ["quiet", "generate", "server"], help = "Leave the nsec and mnemonic out of --output, --output-dir, --output-fifo and --json-file files and --qr-dir PNGs; they're still printed to the terminal")]
+    no_nsec: bool,
+    
+    #[arg(long, conflicts_with_all = ["quiet", "generate", "server"], help = "Write a short SHA-256 fingerprint of the nsec to output files in place of the nsec and mnemonic, and no nsec PNG to --qr-dir")]
+    fingerprint: bool,
+    
+    #[arg(long, conflicts_with = "json", help = "Output in CSV format")]
     csv: bool,
     
-    #[arg(short, long, default_value = "prefix", help = "Match type")]
+    #[arg(long, alias = "append-timestamp", help = "Add match time (UTC), match type, case sensitivity and thread count columns to CSV output")]
+    csv_extended: bool,
+    
+    #[arg(long, help = "Output results as newline-delimited JSON")]
+    json: bool,
+    
+    #[arg(long, value_name = "PATH", help = "Also keep all results in this file as one JSON array")]
+    json_file: Option<PathBuf>,
+    
+    #[arg(short, long, default_value = "prefix", env = "NOSTR_VANITY_MATCH_TYPE", help = "Match type")]
     match_type: MatchTypeArg,
     
+    #[arg(long, default_value = "npub", help = "Key field to match against")]
+    target: TargetArg,
+    
+    #[arg(long, conflicts_with = "target", help = "Match against the hex pubkey (same as --target hex)")]
+    hex: bool,
+    
+    #[arg(long, help = "Give up after this many generated keys")]
+    max_attempts: Option<u64>,
+    
+    #[arg(long, value_name = "N", help = "Keys generated between collecting matches (default: 1000 per thread)")]
+    batch_size: Option<usize>,
+    
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, help = "Stop searching after this much time, e.g. 90s, 10m or 2h30m")]
+    timeout: Option<Duration>,
+    
+    #[arg(long, value_name = "INDEX:CHAR", help = "Require a character at a zero-based body index (repeatable, all must hold)")]
+    at: Vec<String>,
+    
+    #[arg(long, value_name = "CHAR:MIN", help = "Match keys starting with at least MIN copies of CHAR (repeatable)")]
+    repeat: Vec<String>,
+    
+    #[arg(long, value_name = "CLASS:LEN", help = "Match keys whose first LEN body characters are all letters (alpha) or all digits (numeric), repeatable")]
+    charclass: Vec<String>,
+    
+    #[arg(long, help = "Also match leetspeak stand-ins for characters bech32 lacks (b=8, i=l|j, o=0, 1=l)")]
+    leet: bool,
+    
+    #[arg(long, value_name = "FROM=TO,...", help = "Override or add substitutions, e.g. o=0,e=e3 (implies --leet)")]
+    leet_map: Option<String>,
+    
+    #[arg(long, help = "Treat look-alike characters (u/v, s/5, z/2, g/9/q) as the same when matching")]
+    fuzzy_chars: bool,
+    
+    #[arg(long, value_name = "PATH", help = "Read look-alike groups from a file, one group per line such as uv (implies --fuzzy-chars)")]
+    fuzzy_chars_file: Option<PathBuf>,
+    
+    #[arg(long, help = "Treat characters that sound alike when read aloud (f/s/x, m/n, d/t, p/v, c/z, a/j/k/8, q/u/2) as the same when matching")]
+    speakable: bool,
+    
+    #[arg(long, value_name = "PATH", help = "Read sound-alike groups from a file, one group per line such as mn (implies --speakable)")]
+    speakable_file: Option<PathBuf>,
+    
     #[arg(short = 'c', long, help = "Case sensitive matching")]
     case_sensitive: bool,
     
-    #[arg(short = 't', long, help = "Number of CPU threads (default: all cores)")]
+    #[arg(long, help = "Match against the full string including the npub1/nsec1 prefix")]
+    include_prefix: bool,
+    
+    #[arg(long, help = "Ignore the bech32 checksum so suffixes match the end of the key data")]
+    before_checksum: bool,
+    
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "include_prefix", help = "Ignore the first N characters after the npub1/nsec1 prefix when matching")]
+    skip: usize,
+    
+    #[arg(long, value_name = "CHAR", help = "Require contains matches to have CHAR (or the start or end of the key) on each side")]
+    boundary: Option<char>,
+    
+    #[arg(short = 't', long, env = "NOSTR_VANITY_THREADS", help = "Number of CPU threads (default, or 0: all cores)")]
     threads: Option<usize>,
     
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100), help = "Keep the worker threads idle part of the time so they use at most this share of their cores, e.g. 50 to run cooler on battery")]
+    max_cpu: Option<u8>,
+    
     #[arg(long, help = "Continue searching after finding first match")]
     continuous: bool,
     
+    #[arg(long, conflicts_with = "continuous", help = "Stop once every pattern has been matched N times")]
+    count: Option<u64>,
+    
+    #[arg(long, conflicts_with = "continuous", help = "Find every pattern (--count times, default once) and show progress per pattern")]
+    balance: bool,
+    
+    #[arg(long, conflicts_with = "balance", help = "Only accept keys that match every pattern, not just one")]
+    match_all: bool,
+    
+    #[arg(long, help = "Check patterns in a random order, so a key matching several isn't always credited to the first listed")]
+    shuffle_patterns: bool,
+    
+    #[arg(long, value_name = "DURATION", default_value = "100ms", value_parser = parse_duration, help = "Time between progress updates, e.g. 250ms or 1s")]
+    progress_interval: Duration,
+    
+    #[arg(long, value_enum, default_value = "auto", help = "When to draw the progress spinner: auto (on a terminal, otherwise a plain line every 10s), always, or never")]
+    progress: ProgressArg,
+    
+    #[arg(long, help = "Write progress as JSON lines (attempts, rate, elapsed) to stderr instead of the spinner")]
+    progress_json: bool,
+    
+    #[arg(long, value_name = "PATH", help = "Append a timestamped progress line to this file every --progress-log-interval")]
+    progress_log: Option<PathBuf>,
+    
+    #[arg(long, value_name = "DURATION", default_value = "1m", value_parser = parse_duration, help = "Time between --progress-log lines")]
+    progress_log_interval: Duration,
+    
+    #[arg(long, help = "Print how many keys each worker thread generated when the search ends")]
+    per_thread_stats: bool,
+    
     #[arg(short = 'q', long, help = "Quiet mode (less output)")]
     quiet: bool,
     
+    #[arg(long, help = "Never color the matched part of keys, even on a terminal")]
+    no_color: bool,
+    
     #[arg(long, help = "Estimate time for patterns and exit")]
     estimate: bool,
+    
+    #[arg(long, help = "Check every pattern, report which are invalid with estimates for the rest, and exit")]
+    validate_only: bool,
+    
+    #[arg(long, help = "Search even for patterns expected to take over 100 years")]
+    force: bool,
+    
+    #[arg(long, help = "Measure key generation speed on this machine and exit")]
+    benchmark: bool,
+    
+    #[arg(long, value_name = "N", help = "Generate N random keys without matching any pattern and exit")]
+    generate: Option<usize>,
+    
+    #[arg(long, value_name = "N", conflicts_with_all = ["generate", "server", "benchmark", "check", "check_file"], help = "Print N random npubs (no nsecs) with any pattern highlighted where it appears, and exit")]
+    sample: Option<usize>,
+    
+    #[arg(long, help = "Seed for deterministic, reproducible key generation (testing only: anyone with the seed can regenerate the keys)")]
+    seed: Option<u64>,
+    
+    #[arg(long, requires = "seed", help = "Acknowledge that --seed keys are insecure and skip the warning")]
+    insecure_seed: bool,
+    
+    #[arg(long, value_enum, default_value_t = RngArg::Chacha, help = "Random source for keys: os (OS CSPRNG per key), chacha (ChaCha20 seeded from the OS per thread) or hardware (CPU RDRAND)")]
+    rng: RngArg,
+    
+    #[arg(long, help = "Derive keys from a BIP-39 mnemonic (NIP-06) and include it in the output")]
+    mnemonic: bool,
+    
+    #[arg(long, value_enum, requires = "mnemonic", help = "Mnemonic length: 12 words (128-bit) or 24 words (256-bit) [default: 12]")]
+    words: Option<WordsArg>,
+    
+    #[arg(long, conflicts_with_all = ["mnemonic", "generate"], help = "Experimental: step each thread's key up by one secret at a time instead of drawing every key, which is cheaper per key (see README for the security implications)")]
+    sequential: bool,
+    
+    #[arg(long = "relay", value_name = "URL", help = "Relay hint for nprofile output (repeatable)")]
+    relays: Vec<String>,
+    
+    #[arg(long, help = "Print a QR code of the npub for each match")]
+    qr: bool,
+    
+    #[arg(long, help = "Also render the nsec with --qr and --qr-dir")]
+    qr_nsec: bool,
+    
+    #[arg(long, value_name = "DIR", conflicts_with = "generate", help = "Write a QR code PNG per match into this directory")]
+    qr_dir: Option<PathBuf>,
+    
+    #[arg(long, value_name = "NSEC", help = "Check existing keys against the patterns instead of searching (repeatable)")]
+    check: Vec<String>,
+    
+    #[arg(long, value_name = "PATH", help = "Check every nsec in this file (one per line) against the patterns")]
+    check_file: Option<PathBuf>,
+    
+    #[arg(long, help = "Save and resume attempt/time statistics from this JSON file")]
+    checkpoint: Option<PathBuf>,
+    
+    #[arg(long, value_name = "ADDR", conflicts_with = "connect", help = "Coordinate workers: listen on ADDR (e.g. 0.0.0.0:7777) and combine their matches and attempt counts")]
+    server: Option<String>,
+    
+    #[arg(long, value_name = "ADDR", help = "Search as a worker, reporting matches and attempt counts to the --server at ADDR")]
+    connect: Option<String>,
+    
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["generate", "sample", "check", "check_file", "benchmark", "estimate", "validate_only"], help = "Serve Prometheus metrics (attempts, matches, keys/sec) at http://ADDR/metrics, e.g. 127.0.0.1:9184")]
+    metrics: Option<String>,
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
@@ -58,6 +317,67 @@ enum MatchTypeArg {
     Prefix,
     Suffix,
     Contains,
+    Regex,
+    Either,
+    Bookend,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TargetArg {
+    Npub,
+    Nsec,
+    Hex,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RngArg {
+    Os,
+    Chacha,
+    Hardware,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ProgressArg {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum WordsArg {
+    #[value(name = "12")]
+    Twelve,
+    #[value(name = "24")]
+    TwentyFour,
+}
+
+impl From<WordsArg> for MnemonicLength {
+    fn from(arg: WordsArg) -> Self {
+        match arg {
+            WordsArg::Twelve => MnemonicLength::Words12,
+            WordsArg::TwentyFour => MnemonicLength::Words24,
+        }
+    }
+}
+
+impl From<RngArg> for RngSource {
+    fn from(arg: RngArg) -> Self {
+        match arg {
+            RngArg::Os => RngSource::Os,
+            RngArg::Chacha => RngSource::Chacha,
+            RngArg::Hardware => RngSource::Hardware,
+        }
+    }
+}
+
+impl From<TargetArg> for Target {
+    fn from(arg: TargetArg) -> Self {
+        match arg {
+            TargetArg::Npub => Target::Npub,
+            TargetArg::Nsec => Target::Nsec,
+            TargetArg::Hex => Target::Hex,
+        }
+    }
 }
 
 impl From<MatchTypeArg> for MatchType {
@@ -66,208 +386,1585 @@ impl From<MatchTypeArg> for MatchType {
             MatchTypeArg::Prefix => MatchType::Prefix,
             MatchTypeArg::Suffix => MatchType::Suffix,
             MatchTypeArg::Contains => MatchType::Contains,
+            MatchTypeArg::Regex => MatchType::Regex,
+            MatchTypeArg::Either => MatchType::Either,
+            MatchTypeArg::Bookend => MatchType::Bookend,
         }
     }
 }
 
+// The groups of characters matched as one: look-alikes, sound-alikes, or
+// both joined together
+fn char_classes(args: &Args) -> Result<Option<CharClasses>> {
+    let lookalikes = match &args.fuzzy_chars_file {
+        Some(path) => Some(read_char_classes(path)?),
+        None => args.fuzzy_chars.then(CharClasses::lookalikes),
+    };
+    let soundalikes = match &args.speakable_file {
+        Some(path) => Some(read_char_classes(path)?),
+        None => args.speakable.then(CharClasses::soundalikes),
+    };
+    Ok(match (lookalikes, soundalikes) {
+        (Some(lookalikes), Some(soundalikes)) => Some(lookalikes.merge(&soundalikes)),
+        (lookalikes, soundalikes) => lookalikes.or(soundalikes),
+    })
+}
+
+// Fills in the settings a --config file sets, except those given on the
+// command line. A config value also beats one from the environment.
+fn apply_config(args: &mut Args, matches: &ArgMatches, config: JobConfig) -> Result<(), VanityError> {
+    let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+    let invalid = |message: String| VanityError::InvalidInput(format!("{}: {}", args.config.as_ref().unwrap().display(), message));
+    
+    if let Some(match_type) = config.match_type.filter(|_| unset("match_type")) {
+        args.match_type = MatchTypeArg::from_str(&match_type.to_string(), true)
+            .map_err(|_| invalid(format!("match_type '{}' can only be set on a [[pattern]]", match_type)))?;
+    }
+    if let Some(target) = config.target.filter(|_| unset("target") && unset("hex")) {
+        args.target = match target {
+            Target::Npub => TargetArg::Npub,
+            Target::Nsec => TargetArg::Nsec,
+            Target::Hex => TargetArg::Hex,
+        };
+    }
+    if let Some(timeout) = config.timeout.filter(|_| unset("timeout")) {
+        args.timeout = Some(parse_duration(&timeout).map_err(|e| invalid(format!("timeout '{}': {}", timeout, e)))?);
+    }
+    if config.threads.is_some() && unset("threads") {
+        args.threads = config.threads;
+    }
+    // A stopping rule on the command line replaces the config's
+    if config.count.is_some() && unset("count") && unset("continuous") {
+        args.count = config.count;
+    }
+    if config.max_attempts.is_some() && unset("max_attempts") {
+        args.max_attempts = config.max_attempts;
+    }
+    if let Some(case_sensitive) = config.case_sensitive.filter(|_| unset("case_sensitive")) {
+        args.case_sensitive = case_sensitive;
+    }
+    if let Some(continuous) = config.continuous.filter(|_| unset("continuous") && unset("count") && unset("balance")) {
+        args.continuous = continuous;
+    }
+    if let Some(match_all) = config.match_all.filter(|_| unset("match_all") && unset("balance")) {
+        args.match_all = match_all;
+    }
+    if !config.exclude.is_empty() && unset("exclude") {
+        args.exclude = Some(config.exclude.join(&args.pattern_delimiter.to_string()));
+    }
+    if !config.output.is_empty() && unset("output") {
+        args.output = config.output;
+    }
+    if args.continuous && (args.count.is_some() || args.balance) {
+        return Err(invalid("continuous can't be combined with count".to_string()));
+    }
+    args.config_patterns = config.patterns;
+    Ok(())
+}
+
+// Parses every duration flag the same way: humantime units, which may be
+// combined, e.g. `250ms`, `90s` or `2h30m`. Zero is rejected, since none of
+// the flags can wait or run for no time at all.
+fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let duration = humantime::parse_duration(value).map_err(|e| e.to_string())?;
+    if duration.is_zero() {
+        return Err("must be longer than zero".to_string());
+    }
+    Ok(duration)
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    
+    if let Some(path) = args.config.clone() {
+        let applied = read_job_config(&path).and_then(|config| apply_config(&mut args, &matches, config));
+        if let Err(e) = applied {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    
+    // An unusable random source (or a seed for a source that can't take one)
+    // is reported before anything else is printed
+    if let Err(e) = new_rng(args.rng.into(), args.seed, 0) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    
+    // Shown even with --quiet: a 64-bit seed can be searched exhaustively, so
+    // every seeded key is one an attacker could regenerate
+    if args.seed.is_some() && !args.insecure_seed {
+        eprintln!("⚠️  WARNING: --seed makes every key reproducible by anyone who knows or guesses the seed.");
+        eprintln!("⚠️  Seeded keys are for testing only. Never use them for a real identity.");
+        eprintln!("⚠️  Pass --insecure-seed to acknowledge this and hide the warning.\n");
+    }
+    
+    if let Some(count) = args.generate {
+        check_outputs(&args);
+        return generate_keys(&args, count);
+    }
     
-    let patterns = collect_patterns(&args)?;
+    if let Some(addr) = &args.server {
+        check_outputs(&args);
+        return serve(&args, addr);
+    }
+    
+    let specs = collect_patterns(&args)?;
     
-    if patterns.is_empty() {
-        eprintln!("Error: No patterns provided. Use --patterns or --file");
+    if specs.is_empty() && !args.benchmark && args.sample.is_none() {
+        eprintln!("Error: No patterns provided. Use --patterns, --file, --config, --at, --repeat or --charclass");
         std::process::exit(1);
     }
     
-    for pattern in &patterns {
-        if !validate_bech32_chars(pattern) {
+    let target: Target = if args.hex { Target::Hex } else { args.target.into() };
+    let options = MatchOptions {
+        case_sensitive: args.case_sensitive,
+        target,
+        include_prefix: args.include_prefix,
+        substitutions: match &args.leet_map {
+            Some(map) => Some(Substitutions::leet().with_overrides(map.parse()?)),
+            None => args.leet.then(Substitutions::leet),
+        },
+        exclude_checksum: args.before_checksum,
+        skip: 0,
+        fuzzy: char_classes(&args)?,
+        boundary: args.boundary,
+    };
+    // Keep at least one character to match against
+    let max_skip = options.body_len() - 1;
+    if args.skip > max_skip {
+        eprintln!("Warning: --skip {} leaves nothing to match, using {}", args.skip, max_skip);
+    }
+    let options = MatchOptions { skip: args.skip.min(max_skip), ..options };
+    let default_match_type: MatchType = args.match_type.into();
+    
+    if let Some(boundary) = args.boundary {
+        let boundary = boundary.to_ascii_lowercase().to_string();
+        let invalid = match target {
+            Target::Hex => (!validate_hex_chars(&boundary)).then_some("hex"),
+            Target::Npub | Target::Nsec => validate_bech32_chars(&boundary).is_err().then_some("bech32"),
+        };
+        if let Some(alphabet) = invalid {
+            eprintln!("Error: --boundary '{}' isn't a {} character", boundary, alphabet);
+            std::process::exit(EXIT_INVALID_PATTERN);
+        }
+        let contains = specs
+            .iter()
+            .any(|spec| spec.match_type.as_ref().unwrap_or(&default_match_type) == &MatchType::Contains);
+        if !contains {
+            eprintln!("Warning: --boundary only applies to contains patterns, and there are none");
+        }
+    }
+    
+    if args.validate_only {
+        return validate_patterns(&specs, &default_match_type, &options);
+    }
+    
+    for spec in &specs {
+        let match_type = spec.match_type.as_ref().unwrap_or(&default_match_type);
+        if let Err(e) = validate_spec(spec, match_type, &options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_INVALID_PATTERN);
+        }
+    }
+    
+    let built = match specs
+        .into_iter()
+        .map(|spec| spec.build(&default_match_type, &options))
+        .collect::<Result<_, VanityError>>()
+    {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_INVALID_PATTERN);
+        }
+    };
+    // With --count every pattern has its own quota, so longer patterns aren't
+    // redundant even when a shorter one matches all of their keys. With
+    // --match-all it's the shorter one that adds nothing, but it's clearer
+    // to search for what was asked.
+    let drop_extensions = args.count.is_none() && !args.balance && !args.match_all;
+    let (mut built, dropped) = dedupe_patterns(built, drop_extensions);
+    for (pattern, keeper) in &dropped {
+        if pattern.value == keeper.value {
+            eprintln!("Warning: Dropping duplicate pattern '{}'", pattern.value);
+        } else {
             eprintln!(
-                "Error: Pattern '{}' contains invalid characters. \
-                Valid: 023456789acdefghjklmnpqrstuvwxyz",
-                pattern
+                "Warning: Dropping pattern '{}': every match is already a match for '{}'",
+                pattern.value, keeper.value
             );
-            std::process::exit(1);
         }
     }
+    if args.shuffle_patterns {
+        // Seeded runs shuffle the same way every time
+        match args.seed {
+            Some(seed) => built.shuffle(&mut seeded_rng(seed, SHUFFLE_STREAM)),
+            None => built.shuffle(&mut rand::rng()),
+        }
+    }
+    let patterns: Vec<String> = built.iter().map(|pattern| pattern.value.clone()).collect();
+    let matcher = PatternMatcher::new(built);
+    if matcher.unindexed() > SLOW_UNINDEXED_PATTERNS && !args.quiet {
+        eprintln!(
+            "Warning: {} patterns are checked one by one against every key. Plain prefix and contains \
+            patterns share a single trie or Aho-Corasick pass instead and scale to far larger lists",
+            matcher.unindexed()
+        );
+    }
+    let exclude = exclude_matcher(&args, &options)?;
+    let denylist = args.denylist.as_deref().map(read_denylist).transpose()?;
+    
+    if args.batch_size == Some(0) {
+        eprintln!("Error: --batch-size must be at least 1");
+        std::process::exit(1);
+    }
+    
+    if let Some(relay) = args.relays.iter().find(|relay| relay.len() > u8::MAX as usize) {
+        eprintln!("Error: Relay URL '{}' is longer than 255 bytes", relay);
+        std::process::exit(1);
+    }
+    
+    if !args.check.is_empty() || args.check_file.is_some() {
+        return check_keys(&args, &matcher, exclude.as_ref());
+    }
+    
+    if let Some(count) = args.sample {
+        return sample_keys(&args, &matcher, count);
+    }
+    
+    // `--threads 0` means one per core, the same as leaving it out, so rates
+    // and estimates below never work from zero threads
+    let thread_count = args.threads.filter(|&threads| threads > 0).unwrap_or_else(num_cpus::get);
+    
+    if args.benchmark {
+        println!(
+            "⏱️  Benchmarking key generation for {}s on {} threads...",
+            BENCHMARK_DURATION.as_secs(),
+            thread_count
+        );
+        let config = SearchConfig {
+            threads: thread_count,
+            seed: args.seed,
+            rng: args.rng.into(),
+            mnemonic: args.mnemonic,
+            mnemonic_length: args.words.map(Into::into).unwrap_or_default(),
+            sequential: args.sequential,
+            max_cpu: args.max_cpu,
+            ..SearchConfig::default()
+        };
+        let rate = benchmark(config, BENCHMARK_DURATION)?;
+        println!("  Total: {:.0} keys/sec", rate);
+        println!("  Per thread: {:.0} keys/sec", rate / thread_count as f64);
+        
+        if !patterns.is_empty() {
+            println!();
+            estimate_patterns(matcher.patterns(), args.match_all, Some(rate));
+        }
+        return Ok(());
+    }
     
     if args.estimate {
-        estimate_patterns(&patterns);
+        estimate_patterns(matcher.patterns(), args.match_all, None);
         return Ok(());
     }
     
-    let thread_count = args.threads.unwrap_or_else(num_cpus::get);
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(thread_count)
-        .build_global()?;
+    check_outputs(&args);
     
-    if !args.quiet {
+    let (matcher, patterns) = if args.force {
+        (matcher, patterns)
+    } else {
+        let rate = KEYS_PER_SEC_PER_THREAD * thread_count as f64;
+        let matcher = match args.match_all {
+            true => check_match_all_feasible(matcher, rate),
+            false => drop_infeasible_patterns(matcher, rate)?,
+        };
+        let patterns = matcher.patterns().iter().map(|p| p.value.clone()).collect();
+        (matcher, patterns)
+    };
+    
+    if !args.quiet && !args.json {
         println!("🔍 Nostr Vanity npub Generator");
         println!("Searching for {} pattern(s) with {} threads", patterns.len(), thread_count);
-        println!("Patterns: {}", patterns.join(", "));
+        if patterns.len() > BANNER_PATTERNS {
+            println!(
+                "Patterns: {}, and {} more",
+                patterns[..BANNER_PATTERNS].join(", "),
+                patterns.len() - BANNER_PATTERNS
+            );
+        } else {
+            println!("Patterns: {}", patterns.join(", "));
+        }
+        if args.match_all {
+            println!("Keys must match all {} patterns", patterns.len());
+        }
         println!("Match type: {:?}", args.match_type);
+        println!("Target: {:?}", target);
+        if let Some(count) = args.count {
+            println!("Matches per pattern: {}", count);
+        }
+        if interactive(&args) {
+            println!("Type p and Enter to pause, r and Enter to resume");
+        }
         println!();
     }
     
-    let match_type = args.match_type.clone().into();
-    let matcher = PatternMatcher::from_strings(
-        patterns.clone(),
-        match_type,
-        args.case_sensitive,
-    );
-    
-    run_search(args, matcher)?;
+    let resume = match &args.checkpoint {
+        Some(path) => read_checkpoint(path)?.unwrap_or_default(),
+        None => Checkpoint::default(),
+    };
+    if resume.attempts > 0 && !args.quiet && !args.json {
+        println!(
+            "Resuming from checkpoint: {} attempts over {:.0}s\n",
+            resume.attempts, resume.elapsed_seconds
+        );
+    }
     
-    Ok(())
-}
-
-fn collect_patterns(args: &Args) -> Result<Vec<String>> {
-    let mut patterns = Vec::new();
+    let config = SearchConfig {
+        threads: thread_count,
+        continuous: args.continuous,
+        count: if args.balance { Some(args.count.unwrap_or(1)) } else { args.count },
+        max_attempts: args.max_attempts,
+        timeout: args.timeout,
+        batch_size: args.batch_size,
+        seed: args.seed,
+        rng: args.rng.into(),
+        mnemonic: args.mnemonic,
+        mnemonic_length: args.words.map(Into::into).unwrap_or_default(),
+        match_all: args.match_all,
+        sequential: args.sequential,
+        max_cpu: args.max_cpu,
+        relays: args.relays.clone(),
+        resume,
+    };
     
-    if let Some(pattern_str) = &args.patterns {
-        patterns.extend(parse_patterns_string(pattern_str));
+    let mut search = VanitySearch::new(matcher, config);
+    if let Some(exclude) = exclude {
+        search = search.with_exclude(exclude);
     }
-    
-    if let Some(file_path) = &args.file {
-        patterns.extend(read_patterns_from_file(file_path)?);
+    if let Some(denylist) = denylist {
+        search = search.with_denylist(denylist);
     }
     
-    Ok(patterns)
-}
-
-fn estimate_patterns(patterns: &[String]) {
-    println!("⏱️  Time estimates (assuming ~100k keys/sec per core):");
-    println!();
+    let reporter = match &args.connect {
+        Some(addr) => {
+            let hello = Message::Hello { threads: thread_count, patterns: patterns.clone() };
+            let reporter = Reporter::connect(addr.as_str(), &hello)
+                .map_err(|e| anyhow!("could not connect to coordinator at {}: {}", addr, e))?;
+            if !args.quiet {
+                eprintln!("📡 Reporting to coordinator at {} (unencrypted)\n", addr);
+            }
+            Some(Arc::new(reporter))
+        }
+        None => None,
+    };
+    if let Some(reporter) = reporter.clone() {
+        search = search.on_progress(REPORT_INTERVAL, move |attempts, keys_per_sec| {
+            if let Err(e) = reporter.send(&Message::Progress { attempts, keys_per_sec }) {
+                eprintln!("Warning: lost connection to coordinator, searching alone: {}", e);
+            }
+        });
+    }
     
-    for pattern in patterns {
-        let time = estimate_time(pattern.len(), 100_000.0 * num_cpus::get() as f64);
-        println!("  Pattern '{}' ({} chars): ~{}", pattern, pattern.len(), time);
+    let outcome = run_search(args, search, reporter)?;
+    if outcome != SearchOutcome::Found {
+        std::process::exit(outcome.exit_code());
     }
+    
+    Ok(())
 }
 
-fn run_search(args: Args, matcher: PatternMatcher) -> Result<()> {
-    let found = Arc::new(AtomicBool::new(false));
-    let attempts = Arc::new(AtomicU64::new(0));
-    let start_time = Instant::now();
-    let (tx, rx) = unbounded();
-    
-    let progress = if !args.quiet {
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} {msg} [{elapsed_precise}] {per_sec}")?
-                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
-        );
-        pb.enable_steady_tick(Duration::from_millis(100));
-        Some(pb)
-    } else {
-        None
-    };
+// Mints `count` keys with no pattern, in the chosen output formats
+fn generate_keys(args: &Args, count: usize) -> Result<()> {
+    let mut rng = new_rng(args.rng.into(), args.seed, 0)?;
+    let format = output_format(args);
+    let outputs = output_paths(args);
     
-    let search_handle = std::thread::spawn({
-        let found = found.clone();
-        let attempts = attempts.clone();
-        let continuous = args.continuous;
-        let tx = tx.clone();
+    for index in 0..count {
+        let keypair = if args.mnemonic {
+            NostrKeyPair::generate_mnemonic_with_length(rng.as_mut(), args.words.map(Into::into).unwrap_or_default())?
+        } else {
+            NostrKeyPair::generate_with_rng(rng.as_mut())?
+        };
+        keypair.verify()?;
         
-        move || {
-            loop {
-                if !continuous && found.load(Ordering::Relaxed) {
-                    break;
-                }
-                
-                let batch_size = 10000;
-                let results: Vec<_> = (0..batch_size)
-                    .into_par_iter()
-                    .filter_map(|_| {
-                        if !continuous && found.load(Ordering::Relaxed) {
-                            return None;
-                        }
-                        
-                        attempts.fetch_add(1, Ordering::Relaxed);
-                        
-                        match NostrKeyPair::generate() {
-                            Ok(keypair) => {
-                                if let Some(pattern) = matcher.find_match(&keypair) {
-                                    Some((keypair, pattern))
-                                } else {
-                                    None
-                                }
-                            }
-                            Err(_) => None,
-                        }
-                    })
-                    .collect();
-                
-                for (keypair, pattern) in results {
-                    found.store(true, Ordering::Relaxed);
-                    let _ = tx.send((keypair, pattern));
-                    if !continuous {
-                        break;
+        if !args.quiet {
+            match format {
+                OutputFormat::Text => println!("{}", format_keypair(&keypair)),
+                OutputFormat::Csv => {
+                    if index == 0 {
+                        println!("{}", keypair_csv_header(&keypair));
                     }
+                    println!("{}", format_keypair_csv(&keypair));
                 }
+                OutputFormat::Json => println!("{}", format_keypair_json(&keypair)?),
             }
         }
-    });
-    
-    let output_handle = std::thread::spawn({
-        let output = args.output.clone();
-        let csv = args.csv;
-        let quiet = args.quiet;
-        let continuous = args.continuous;
-        let attempts = attempts.clone();
         
-        move || {
-            for (keypair, pattern) in rx {
-                let result = VanityResult {
-                    keypair,
-                    matched_pattern: pattern,
-                    attempts: attempts.load(Ordering::Relaxed),
-                    time_elapsed: start_time.elapsed(),
-                };
-                
-                if !quiet {
-                    println!("\n{}", result.format_output());
-                }
-                
-                if let Some(ref path) = output {
-                    let _ = if csv {
-                        write_csv_result(&result, path)
-                    } else {
-                        write_result_to_file(&result, path)
-                    };
-                }
-                
-                if !continuous {
-                    break;
-                }
+        for (path, format) in &outputs {
+            if let Err(e) = write_keypair(&keypair, path, *format) {
+                eprintln!("Error: failed to write {}: {}", path.display(), e);
+                std::process::exit(EXIT_OUTPUT_FAILED);
             }
         }
-    });
-    
-    if let Some(pb) = &progress {
-        while !found.load(Ordering::Relaxed) || args.continuous {
-            let current_attempts = attempts.load(Ordering::Relaxed);
-            let elapsed = start_time.elapsed().as_secs_f64();
-            let rate = current_attempts as f64 / elapsed.max(0.1);
-            
-            pb.set_message(format!("Attempts: {}", current_attempts));
-            pb.set_prefix(format!("{:.0} keys/sec", rate));
-            
-            std::thread::sleep(Duration::from_millis(100));
-            
-            if args.continuous && pb.elapsed() > Duration::from_secs(3600) {
-                break;
+        if let Some(path) = &args.json_file {
+            if let Err(e) = write_keypair_json_file(&keypair, path) {
+                eprintln!("Error: failed to write {}: {}", path.display(), e);
+                std::process::exit(EXIT_OUTPUT_FAILED);
             }
         }
-        
-        pb.finish_with_message("Complete!");
-    } else {
-        search_handle.join().unwrap();
     }
+    Ok(())
+}
+
+// --sample: prints `count` throwaway public keys to show what keys look like
+// and how often the patterns turn up. The npub is printed, or the hex pubkey
+// with --hex, so the patterns can be highlighted in it.
+fn sample_keys(args: &Args, matcher: &PatternMatcher, count: usize) -> Result<()> {
+    let mut rng = new_rng(args.rng.into(), args.seed, 0)?;
+    let target = match args.hex || matches!(args.target, TargetArg::Hex) {
+        true => Target::Hex,
+        false => Target::Npub,
+    };
+    let color = use_color(args);
     
-    drop(tx);
-    output_handle.join().unwrap();
+    let mut matched = 0;
+    for _ in 0..count {
+        let keypair = NostrKeyPair::generate_with_rng(rng.as_mut())?;
+        let is_match = match args.match_all {
+            true => matcher.all_match(&keypair),
+            false => matcher.find_match(&keypair).is_some(),
+        };
+        if is_match {
+            matched += 1;
+        }
+        let highlighted = highlight_matches(matcher.patterns(), &keypair, target, color);
+        println!("{}", highlighted.unwrap_or_else(|| target.select(&keypair).into_owned()));
+    }
     
+    if !matcher.patterns().is_empty() && !args.quiet {
+        eprintln!("\n{} of {} sample key(s) matched", matched, count);
+    }
+    Ok(())
+}
+
+// --server: collects matches and attempt counts from --connect workers until
+// interrupted. Each worker gets its own thread; the coordinator state is
+// shared behind a lock, which also keeps printed matches from interleaving.
+fn serve(args: &Args, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let coordinator = Arc::new(Mutex::new(Coordinator::new()));
+    let interrupted = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let interrupted = interrupted.clone();
+        move || interrupted.store(true, Ordering::Relaxed)
+    })?;
+    
+    if !args.quiet {
+        eprintln!("📡 Coordinating workers on {} (unencrypted, keys included)", listener.local_addr()?);
+        eprintln!("Start workers with --connect <this host>:{}, press Ctrl+C to stop\n", listener.local_addr()?.port());
+    }
+    if let Some(addr) = &args.metrics {
+        let coordinator = coordinator.clone();
+        start_metrics(addr, args.quiet, move || {
+            let coordinator = coordinator.lock().unwrap();
+            Metrics {
+                attempts: coordinator.attempts(),
+                matches: coordinator.matches(),
+                keys_per_sec: coordinator.keys_per_sec(),
+            }
+        })?;
+    }
+    
+    std::thread::spawn({
+        let coordinator = coordinator.clone();
+        let format = output_format(args);
+        let outputs = output_paths(args);
+        let quiet = args.quiet;
+        move || {
+            for stream in listener.incoming().flatten() {
+                let coordinator = coordinator.clone();
+                let outputs = outputs.clone();
+                std::thread::spawn(move || handle_worker(stream, &coordinator, format, &outputs, quiet));
+            }
+        }
+    });
+    
+    let started = Instant::now();
+    let mut last_stats = Instant::now();
+    while !interrupted.load(Ordering::Relaxed) {
+        std::thread::sleep(PROGRESS_POLL);
+        if !args.quiet && last_stats.elapsed() >= SERVER_STATS_INTERVAL {
+            last_stats = Instant::now();
+            let coordinator = coordinator.lock().unwrap();
+            eprintln!(
+                "Workers: {} | Attempts: {} | {:.0} keys/sec | Matches: {}",
+                coordinator.connected(),
+                coordinator.attempts(),
+                coordinator.keys_per_sec(),
+                coordinator.matches()
+            );
+        }
+    }
+    
+    let coordinator = coordinator.lock().unwrap();
+    if !args.quiet {
+        eprintln!("\nInterrupted");
+        eprintln!("Summary:");
+        eprintln!("  Matches: {}", coordinator.matches());
+        eprintln!("  Workers: {}", coordinator.workers().len());
+        eprintln!("  Attempts: {}", coordinator.attempts());
+        eprintln!("  Time: {:.2}s", started.elapsed().as_secs_f64());
+    }
+    if coordinator.matches() == 0 {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    Ok(())
+}
+
+// Binds the --metrics address and answers scrapes from a background thread
+// until the process exits. An address that can't be bound stops the run
+// before it starts.
+fn start_metrics<F>(addr: &str, quiet: bool, metrics: F) -> Result<()>
+where
+    F: Fn() -> Metrics + Send + 'static,
+{
+    let listener = TcpListener::bind(addr).map_err(|e| anyhow!("cannot serve metrics on {}: {}", addr, e))?;
+    if !quiet {
+        eprintln!("📈 Serving metrics at http://{}/metrics", listener.local_addr()?);
+    }
+    std::thread::spawn(move || serve_metrics(listener, metrics));
+    Ok(())
+}
+
+// Reads one worker's messages until it disconnects, printing and saving each
+// match no other worker has already reported
+fn handle_worker(
+    stream: TcpStream,
+    coordinator: &Mutex<Coordinator>,
+    format: OutputFormat,
+    outputs: &[(PathBuf, OutputFormat)],
+    quiet: bool,
+) {
+    let Ok(peer) = stream.peer_addr() else {
+        return;
+    };
+    coordinator.lock().unwrap().connect(peer);
+    
+    let mut reader = BufReader::new(stream);
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Warning: dropping worker {}: {}", peer, e);
+                break;
+            }
+        };
+        // A worker's key is checked like a local one before it's shown or
+        // saved; a pair that doesn't add up ends the connection
+        if let Message::Match { keypair, .. } = &message {
+            if let Err(e) = keypair.verify() {
+                eprintln!("Error: dropping worker {}, it sent a key pair that doesn't match: {}", peer, e);
+                break;
+            }
+        }
+        
+        let mut coordinator = coordinator.lock().unwrap();
+        let new_match = coordinator.record(peer, &message);
+        match &message {
+            Message::Hello { threads, patterns } if !quiet => eprintln!(
+                "Worker {} connected: {} threads, patterns {}",
+                peer,
+                threads,
+                patterns.join(", ")
+            ),
+            Message::Match { keypair, pattern, .. } if new_match => {
+                if !quiet {
+                    match format {
+                        OutputFormat::Text => {
+                            println!("\n✨ Worker {} found '{}'\n{}", peer, pattern, format_keypair(keypair))
+                        }
+                        OutputFormat::Csv => {
+                            if coordinator.matches() == 1 {
+                                println!("{}", keypair_csv_header(keypair));
+                            }
+                            println!("{}", format_keypair_csv(keypair));
+                        }
+                        OutputFormat::Json => match serde_json::to_string(&message) {
+                            Ok(line) => println!("{}", line),
+                            Err(e) => eprintln!("Error: {}", e),
+                        },
+                    }
+                }
+                for (path, format) in outputs {
+                    if let Err(e) = write_keypair(keypair, path, *format) {
+                        eprintln!("Warning: failed to write {}: {}", path.display(), e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    coordinator.lock().unwrap().disconnect(peer);
+    if !quiet {
+        eprintln!("Worker {} disconnected", peer);
+    }
+}
+
+// Whether a search can be paused from the keyboard: the spinner is showing
+// and stdin is a terminal the user can type into
+fn interactive(args: &Args) -> bool {
+    spinner(args) && std::io::stdin().is_terminal()
+}
+
+// Whether to draw the spinner. Drawn into a log or a pipe it's a mess of
+// control characters, so by default it's only drawn on a terminal.
+fn spinner(args: &Args) -> bool {
+    !args.quiet
+        && !args.progress_json
+        && match args.progress {
+            ProgressArg::Auto => std::io::stderr().is_terminal(),
+            ProgressArg::Always => true,
+            ProgressArg::Never => false,
+        }
+}
+
+// Whether to print a plain progress line now and then in place of the
+// spinner, for output captured to a file or CI log
+fn plain_progress(args: &Args) -> bool {
+    !args.quiet && !args.progress_json && args.progress == ProgressArg::Auto && !std::io::stderr().is_terminal()
+}
+
+// Whether to color matches on stdout: only for a terminal, and not with
+// --no-color or the NO_COLOR convention
+fn use_color(args: &Args) -> bool {
+    !args.no_color && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+// What output files get in place of the secret key. The terminal always
+// shows it, so it's seen at least once.
+fn secret_output(args: &Args) -> SecretOutput {
+    if args.fingerprint {
+        SecretOutput::Fingerprint
+    } else if args.no_nsec {
+        SecretOutput::Omit
+    } else {
+        SecretOutput::Full
+    }
+}
+
+// Format for stdout, and for output paths without a recognised extension
+fn output_format(args: &Args) -> OutputFormat {
+    if args.csv {
+        OutputFormat::Csv
+    } else if args.json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Text
+    }
+}
+
+fn output_paths(args: &Args) -> Vec<(PathBuf, OutputFormat)> {
+    let default_format = output_format(args);
+    args.output
+        .iter()
+        .map(|path| (path.clone(), OutputFormat::from_path(path).unwrap_or(default_format)))
+        .collect()
+}
+
+// Exits if anywhere a match or checkpoint will be written can't be written,
+// before hours go into a search whose result would be lost
+fn check_outputs(args: &Args) {
+    let mut checks: Vec<Result<(), VanityError>> = output_paths(args)
+        .iter()
+        .map(|(path, _)| check_writable(path))
+        .collect();
+    if args.meta {
+        checks.extend(args.output.iter().map(|path| check_writable(&meta_path(path))));
+    }
+    if let Some(path) = &args.json_file {
+        checks.push(check_writable(path));
+    }
+    if let Some(path) = &args.output_fifo {
+        checks.push(check_fifo(path));
+    }
+    if let Some(dir) = &args.output_dir {
+        checks.push(check_writable_dir(dir));
+    }
+    if let Some(dir) = &args.qr_dir {
+        checks.push(check_writable_dir(dir));
+    }
+    // Checkpoints are written to a temporary file and renamed into place
+    if let Some(path) = &args.checkpoint {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        checks.push(check_writable_dir(dir.unwrap_or(Path::new("."))));
+    }
+    
+    if let Some(Err(e)) = checks.into_iter().find(Result::is_err) {
+        eprintln!("Error: {}", e);
+        std::process::exit(EXIT_OUTPUT_FAILED);
+    }
+}
+
+// The --exclude list as contains patterns over the whole key body,
+// including the checksum, with the same case and substitution rules as the
+// search patterns
+fn exclude_matcher(args: &Args, options: &MatchOptions) -> Result<Option<PatternMatcher>> {
+    let Some(list) = &args.exclude else {
+        return Ok(None);
+    };
+    
+    let values = parse_patterns_string(list, args.pattern_delimiter);
+    for value in &values {
+        let valid = match options.target {
+            Target::Hex => validate_hex_chars(&strip_wildcards(&value.to_lowercase())),
+            Target::Npub | Target::Nsec => validate_bech32_wildcard_chars(&value.to_lowercase()).is_ok(),
+        };
+        if !valid && options.substitutions.is_none() {
+            eprintln!("Warning: Excluded '{}' can never occur in a key", value);
+        }
+    }
+    
+    let options = MatchOptions {
+        include_prefix: false,
+        exclude_checksum: false,
+        skip: 0,
+        ..options.clone()
+    };
+    Ok(Some(PatternMatcher::from_strings(values, MatchType::Contains, options)?))
+}
+
+// Runs existing nsecs through the matcher instead of generating keys
+fn check_keys(args: &Args, matcher: &PatternMatcher, exclude: Option<&PatternMatcher>) -> Result<()> {
+    let mut nsecs = args.check.clone();
+    if let Some(path) = &args.check_file {
+        let contents = std::fs::read_to_string(path)?;
+        nsecs.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+    
+    let mut matched = 0;
+    for nsec in &nsecs {
+        let keypair = match NostrKeyPair::from_nsec(nsec) {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                eprintln!("Error: Could not decode '{}': {}", nsec, e);
+                continue;
+            }
+        };
+        let excluded = exclude.and_then(|exclude| exclude.find_match(&keypair));
+        let found = match args.match_all {
+            true => matcher.all_match(&keypair).then(|| matcher.patterns()[0].clone()),
+            false => matcher.find_match(&keypair),
+        };
+        match (found, excluded) {
+            (Some(_), None) if args.match_all => {
+                matched += 1;
+                println!("✅ {} matches all {} patterns", keypair.npub, matcher.patterns().len());
+            }
+            (Some(pattern), None) => {
+                matched += 1;
+                println!("✅ {} matches '{}'", keypair.npub, pattern.value);
+            }
+            _ if args.quiet => {}
+            (Some(pattern), Some(excluded)) => println!(
+                "❌ {} matches '{}' but contains excluded '{}'",
+                keypair.npub, pattern.value, excluded.value
+            ),
+            (None, _) if args.match_all => println!("❌ {} doesn't match every pattern", keypair.npub),
+            (None, _) => println!("❌ {} matches no pattern", keypair.npub),
+        }
+    }
+    
+    if !args.quiet {
+        println!("\n{} of {} key(s) matched", matched, nsecs.len());
+    }
+    if matched == 0 {
+        std::process::exit(EXIT_NO_MATCH);
+    }
+    Ok(())
+}
+
+fn collect_patterns(args: &Args) -> Result<Vec<PatternSpec>> {
+    let mut patterns = args.config_patterns.clone();
+    
+    if let Some(pattern_str) = &args.patterns {
+        patterns.extend(parse_patterns_string(pattern_str, args.pattern_delimiter).into_iter().map(PatternSpec::new));
+    }
+    
+    match &args.file {
+        Some(file_path) if file_path.as_os_str() == "-" => {
+            if std::io::stdin().is_terminal() {
+                eprintln!("Enter patterns, one per line, then press Ctrl-D:");
+            }
+            let specs = read_patterns_from_stdin(args.max_patterns)?;
+            if !args.quiet {
+                eprintln!("Loaded {} pattern(s) from stdin", specs.len());
+            }
+            patterns.extend(specs);
+        }
+        Some(file_path) => {
+            let specs = read_patterns_from_file(file_path, args.max_patterns)?;
+            if !args.quiet {
+                eprintln!("Loaded {} pattern(s) from {}", specs.len(), file_path.display());
+            }
+            patterns.extend(specs);
+        }
+        None => {}
+    }
+    
+    for repeat in &args.repeat {
+        patterns.push(PatternSpec {
+            value: repeat.clone(),
+            match_type: Some(MatchType::Repeat),
+            case_sensitive: None,
+        });
+    }
+    
+    for charclass in &args.charclass {
+        patterns.push(PatternSpec {
+            value: charclass.clone(),
+            match_type: Some(MatchType::Charclass),
+            case_sensitive: None,
+        });
+    }
+    
+    if !args.at.is_empty() {
+        patterns.push(PatternSpec {
+            value: args.at.join(","),
+            match_type: Some(MatchType::Position),
+            case_sensitive: None,
+        });
+    }
+    
+    Ok(patterns)
+}
+
+// Checks a pattern's characters and length against its match type and the
+// key text it targets, before any pattern is built
+fn validate_spec(spec: &PatternSpec, match_type: &MatchType, options: &MatchOptions) -> Result<()> {
+    if matches!(match_type, MatchType::Regex) {
+        return Ok(());
+    }
+    let target = options.target;
+    validate_pattern_length(&spec.value, match_type, options)?;
+    
+    // Both classes have characters in every target's alphabet
+    if matches!(match_type, MatchType::Charclass) {
+        parse_charclass(&spec.value)?;
+        return Ok(());
+    }
+    
+    // Position patterns only need their constrained characters checked; the
+    // length check above keeps every index inside the string being matched.
+    // With substitutions, every expansion of the pattern is checked instead.
+    let constrained: String;
+    let pattern = match (match_type, options.include_prefix) {
+        (MatchType::Position, _) => {
+            let positions = parse_positions(&spec.value)?;
+            constrained = positions.into_iter().map(|(_, c)| c).collect();
+            &constrained
+        }
+        (MatchType::Repeat, _) => {
+            let (c, min) = parse_repeat(&spec.value)?;
+            constrained = c.to_string().repeat(min.min(options.body_len()));
+            &constrained
+        }
+        // Both ends are checked together, the prefix without any `npub1`
+        (MatchType::Bookend, include_prefix) => {
+            let (prefix, suffix) = parse_bookend(&spec.value)?;
+            let prefix = match include_prefix {
+                true => prefix.strip_prefix(target.hrp_prefix()).unwrap_or(&prefix),
+                false => &prefix,
+            };
+            constrained = format!("{}{}", prefix, suffix);
+            &constrained
+        }
+        // With --include-prefix the pattern may spell out the `npub1` itself
+        (_, true) => spec.value.strip_prefix(target.hrp_prefix()).unwrap_or(&spec.value),
+        (_, false) => &spec.value,
+    };
+    let variants = match (match_type, &options.substitutions) {
+        (MatchType::Position | MatchType::Repeat | MatchType::Bookend, _) | (_, None) => vec![pattern.to_string()],
+        (_, Some(substitutions)) => substitutions.expand(&pattern.to_lowercase())?,
+    };
+    // Prefix, suffix and contains patterns may also hold wildcards
+    let wildcards = !matches!(match_type, MatchType::Position | MatchType::Repeat | MatchType::Bookend);
+    for pattern in &variants {
+        match target {
+            Target::Hex if wildcards => {
+                if !validate_hex_chars(&strip_wildcards(pattern)) {
+                    bail!(
+                        "pattern '{}' contains invalid characters. Valid: 0123456789abcdef and the wildcards ? and *",
+                        pattern
+                    );
+                }
+            }
+            Target::Hex if !validate_hex_chars(pattern) => bail!(
+                "pattern '{}' contains invalid characters. Valid: 0123456789abcdef",
+                pattern
+            ),
+            Target::Hex => {}
+            Target::Npub | Target::Nsec if wildcards => validate_bech32_wildcard_chars(pattern)?,
+            Target::Npub | Target::Nsec => validate_bech32_chars(pattern)?,
+        }
+    }
     Ok(())
+}
+
+// --validate-only: checks and builds every pattern without stopping at the
+// first bad one, then reports each with its estimate. Exits non-zero if any
+// pattern is invalid.
+fn validate_patterns(specs: &[PatternSpec], default_match_type: &MatchType, options: &MatchOptions) -> Result<()> {
+    let keys_per_sec = KEYS_PER_SEC_PER_THREAD * num_cpus::get() as f64;
+    println!("🔎 Validating {} pattern(s) (estimates assume ~100k keys/sec per core):", specs.len());
+    println!();
+    
+    let mut invalid = 0;
+    for spec in specs {
+        let match_type = spec.match_type.as_ref().unwrap_or(default_match_type);
+        let built = validate_spec(spec, match_type, options)
+            .and_then(|_| Ok(spec.clone().build(default_match_type, options)?));
+        match built {
+            Ok(pattern) => {
+                let estimate = estimate_time(&pattern, keys_per_sec);
+                println!("  ✓ '{}' ({}): ~{}", spec.value, match_type, estimate.human_time);
+                print_odds(&estimate);
+            }
+            Err(e) => {
+                invalid += 1;
+                println!("  ✗ '{}' ({}): {}", spec.value, match_type, e);
+            }
+        }
+    }
+    
+    println!();
+    println!("{} valid, {} invalid", specs.len() - invalid, invalid);
+    if invalid > 0 {
+        std::process::exit(EXIT_INVALID_PATTERN);
+    }
+    Ok(())
+}
+
+fn estimate_patterns(patterns: &[Pattern], match_all: bool, measured_rate: Option<f64>) {
+    let keys_per_sec = match measured_rate {
+        Some(rate) => {
+            println!("⏱️  Time estimates (measured {:.0} keys/sec):", rate);
+            rate
+        }
+        None => {
+            println!("⏱️  Time estimates (assuming ~100k keys/sec per core):");
+            KEYS_PER_SEC_PER_THREAD * num_cpus::get() as f64
+        }
+    };
+    println!();
+    
+    for pattern in patterns {
+        let estimate = estimate_time(pattern, keys_per_sec);
+        println!(
+            "  Pattern '{}' ({} chars, {:?}): ~{}",
+            pattern.value,
+            pattern.value.len(),
+            pattern.match_type,
+            estimate.human_time
+        );
+        print_odds(&estimate);
+    }
+    
+    if patterns.len() > 1 && match_all {
+        let estimate = estimate_all(patterns, keys_per_sec);
+        println!("\n  All {} patterns in one key: ~{}", patterns.len(), estimate.human_time);
+        print_odds(&estimate);
+    } else if patterns.len() > 1 {
+        let estimate = estimate_combined(patterns, keys_per_sec);
+        println!("\n  Any of the {} patterns: ~{}", patterns.len(), estimate.human_time);
+        print_odds(&estimate);
+    }
+}
+
+fn print_odds(estimate: &Estimate) {
+    if let (Some(probability), Some(attempts)) = (estimate.probability, estimate.expected_attempts) {
+        if probability > 0.0 {
+            println!(
+                "      probability {:.3e} per key, ~{:.0} expected attempts",
+                probability, attempts
+            );
+        }
+    }
+    if !estimate.quantiles.is_empty() {
+        let quantiles: Vec<String> = estimate
+            .quantiles
+            .iter()
+            .map(|&(chance, seconds)| format!("{}% by {}", chance * 100.0, format_duration(seconds)))
+            .collect();
+        println!("      chance of a match: {}", quantiles.join(", "));
+    }
+}
+
+// One line per pattern for --balance: matches so far, and progress towards
+// the pattern's quota in expected attempts
+fn pattern_breakdown(search: &VanitySearch) -> String {
+    let attempts = search.attempts() as f64;
+    let rate = attempts / search.elapsed().as_secs_f64().max(0.1);
+    let quota = search.config().count.unwrap_or(1);
+    
+    let mut lines = vec!["Per-pattern progress:".to_string()];
+    for (pattern, hits) in search.matcher().patterns().iter().zip(search.hits()) {
+        let mut line = format!("  '{}': {}/{} found", pattern.value, hits, quota);
+        if hits >= quota {
+            line += " | done";
+        } else if let Some(p) = pattern_probability(pattern) {
+            let expected = expected_attempts(p) * quota as f64;
+            if expected.is_infinite() {
+                line += " | can't occur";
+            } else {
+                line += &format!(" | ~{:.0}% of expected attempts", attempts / expected * 100.0);
+                let remaining = expected - attempts;
+                if remaining > 0.0 && rate > 0.0 {
+                    line += &format!(", ETA ~{}", format_duration(remaining / rate));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+// Attempts and rate of each worker thread. Threads of one search should run
+// at about the same speed; one far behind the rest is being starved, by
+// other processes or by more threads than cores.
+fn thread_breakdown(search: &VanitySearch) -> String {
+    let per_thread = search.thread_attempts();
+    let total: u64 = per_thread.iter().sum();
+    let secs = search.run_elapsed().as_secs_f64().max(0.001);
+    
+    let mut lines = vec!["Per-thread throughput:".to_string()];
+    for (index, &attempts) in per_thread.iter().enumerate() {
+        let share = attempts as f64 / total.max(1) as f64 * 100.0;
+        lines.push(format!(
+            "  Thread {}: {} attempts, {:.0} keys/sec ({:.1}%)",
+            index, attempts, attempts as f64 / secs, share
+        ));
+    }
+    
+    let fastest = per_thread.iter().copied().max().unwrap_or(0);
+    let slowest = per_thread.iter().copied().min().unwrap_or(0);
+    if fastest > 0 {
+        lines.push(format!(
+            "  Slowest thread ran at {:.0}% of the fastest",
+            slowest as f64 / fastest as f64 * 100.0
+        ));
+    }
+    let cores = num_cpus::get();
+    if per_thread.len() > cores {
+        let plural = if cores == 1 { "" } else { "s" };
+        lines.push(format!("  Note: {} threads share {} logical core{}", per_thread.len(), cores, plural));
+    }
+    lines.join("\n")
+}
+
+fn print_qr(label: &str, data: &str) {
+    match qr_terminal(data) {
+        Ok(code) => println!("{}:\n{}", label, code),
+        Err(e) => eprintln!("Error: failed to render QR code: {}", e),
+    }
+}
+
+// Attempts the search is expected to need, for the progress ETA: until any
+// pattern matches, or with --count until the rarest pattern meets its quota.
+// None for continuous searches and patterns that can't be estimated.
+fn expected_search_attempts(search: &VanitySearch) -> Option<f64> {
+    let config = search.config();
+    if config.continuous {
+        return None;
+    }
+    
+    let probabilities = search
+        .matcher()
+        .patterns()
+        .iter()
+        .map(pattern_probability)
+        .collect::<Option<Vec<f64>>>()?;
+    if config.match_all {
+        let all: f64 = probabilities.iter().product();
+        let expected = expected_attempts(all) * config.count.unwrap_or(1) as f64;
+        return expected.is_finite().then_some(expected);
+    }
+    let expected = match config.count {
+        Some(count) => probabilities
+            .iter()
+            .map(|&p| expected_attempts(p) * count as f64)
+            .fold(0.0, f64::max),
+        None => expected_attempts(probabilities.iter().sum::<f64>().min(1.0)),
+    };
+    expected.is_finite().then_some(expected)
+}
+
+// Warns about patterns expected to take over 100 years at `keys_per_sec`.
+// If all are that slow, or the user declines to continue without them, exits
+// and points at --force. Otherwise returns a matcher with only the feasible
+// patterns.
+fn drop_infeasible_patterns(matcher: PatternMatcher, keys_per_sec: f64) -> Result<PatternMatcher> {
+    let (feasible, infeasible): (Vec<Pattern>, Vec<Pattern>) = matcher
+        .patterns()
+        .iter()
+        .cloned()
+        .partition(|p| estimate_seconds(p, keys_per_sec).is_none_or(|s| s <= IMPOSSIBLE_AFTER_SECS));
+    
+    if infeasible.is_empty() {
+        return Ok(matcher);
+    }
+    
+    eprintln!("⚠️  WARNING: these patterns would take over 100 years at ~{:.0} keys/sec:", keys_per_sec);
+    for pattern in &infeasible {
+        eprintln!("  '{}': ~{}", pattern.value, estimate_time(pattern, keys_per_sec).human_time);
+    }
+    
+    if feasible.is_empty() {
+        eprintln!("Error: No pattern can be found in reasonable time. Use --force to search anyway");
+        std::process::exit(1);
+    }
+    
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Error: Remove these patterns or use --force to search anyway");
+        std::process::exit(1);
+    }
+    eprint!("Continue with the {} remaining pattern(s)? [y/N] ", feasible.len());
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        std::process::exit(1);
+    }
+    
+    Ok(PatternMatcher::new(feasible))
+}
+
+// drop_infeasible_patterns for --match-all, where dropping a pattern would
+// change what's being searched for. Exits if all the patterns together are
+// expected to take over 100 years.
+fn check_match_all_feasible(matcher: PatternMatcher, keys_per_sec: f64) -> PatternMatcher {
+    let estimate = estimate_all(matcher.patterns(), keys_per_sec);
+    let seconds = estimate.expected_attempts.map(|attempts| attempts / keys_per_sec);
+    if seconds.is_some_and(|seconds| seconds > IMPOSSIBLE_AFTER_SECS) {
+        eprintln!(
+            "Error: A key matching all {} patterns would take ~{} at ~{:.0} keys/sec. \
+            Use fewer or shorter patterns, or --force to search anyway",
+            matcher.patterns().len(),
+            estimate.human_time,
+            keys_per_sec
+        );
+        std::process::exit(1);
+    }
+    matcher
+}
+
+// --progress-log: timestamped lines appended to a file, each flushed so
+// `tail -f` sees it right away. A failed write is reported once, after which
+// logging stops and the search carries on.
+struct ProgressLog {
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl ProgressLog {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("failed to open progress log {}: {}", path.display(), e))?;
+        Ok(ProgressLog {
+            writer: BufWriter::new(file),
+            path: path.to_path_buf(),
+        })
+    }
+    
+    fn write(log: &mut Option<ProgressLog>, message: &str) {
+        let Some(progress_log) = log else {
+            return;
+        };
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+        let written = writeln!(progress_log.writer, "{} {}", timestamp, message)
+            .and_then(|_| progress_log.writer.flush());
+        if let Err(e) = written {
+            eprintln!("Warning: failed to write progress log {}, no longer logging: {}", progress_log.path.display(), e);
+            *log = None;
+        }
+    }
+}
+
+fn run_search(args: Args, search: VanitySearch, reporter: Option<Arc<Reporter>>) -> Result<SearchOutcome> {
+    // Opened before the Ctrl+C handler is installed, so waiting for a reader
+    // can still be interrupted
+    let mut fifo = match &args.output_fifo {
+        Some(path) => {
+            if !args.quiet {
+                eprintln!("Waiting for a reader on {}...", path.display());
+            }
+            let format = OutputFormat::from_path(path).unwrap_or(output_format(&args));
+            match ResultWriter::open_fifo(path.clone(), format, args.csv_extended) {
+                Ok(writer) => Some(writer.with_secrets(secret_output(&args))),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(EXIT_OUTPUT_FAILED);
+                }
+            }
+        }
+        None => None,
+    };
+    let mut progress_log = args.progress_log.as_deref().map(ProgressLog::open).transpose()?;
+    ProgressLog::write(
+        &mut progress_log,
+        &format!(
+            "Started searching for {} pattern(s) with {} threads",
+            search.matcher().patterns().len(),
+            search.config().threads
+        ),
+    );
+    
+    let search = Arc::new(search);
+    let interrupted = Arc::new(AtomicBool::new(false));
+    
+    if let Some(addr) = &args.metrics {
+        let search = search.clone();
+        start_metrics(addr, args.quiet, move || Metrics {
+            attempts: search.attempts(),
+            matches: search.matches(),
+            keys_per_sec: search.attempts() as f64 / search.elapsed().as_secs_f64().max(0.1),
+        })?;
+    }
+    
+    ctrlc::set_handler({
+        let search = search.clone();
+        let interrupted = interrupted.clone();
+        move || {
+            interrupted.store(true, Ordering::Relaxed);
+            search.stop();
+        }
+    })?;
+    
+    let rx = search.run()?;
+    
+    let checkpoint_handle = args.checkpoint.clone().map(|path| {
+        let search = search.clone();
+        std::thread::spawn(move || {
+            let mut last_write = Instant::now();
+            while !search.is_done() {
+                std::thread::sleep(Duration::from_millis(100));
+                if last_write.elapsed() >= CHECKPOINT_INTERVAL {
+                    if let Err(e) = write_checkpoint(&search.checkpoint(), &path) {
+                        eprintln!("Warning: failed to write checkpoint: {}", e);
+                    }
+                    last_write = Instant::now();
+                }
+            }
+        })
+    });
+    
+    let progress = if spinner(&args) {
+        let pb = ProgressBar::new_spinner();
+        // indicatif hides itself when stderr isn't a terminal, unless it
+        // draws through a terminal of its own
+        if args.progress == ProgressArg::Always {
+            pb.set_draw_target(ProgressDrawTarget::term_like_with_hz(Box::new(Term::stderr()), 20));
+        }
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} {msg} [{elapsed_precise}] {per_sec}")?
+                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
+        );
+        pb.enable_steady_tick(Duration::from_millis(100));
+        Some(pb)
+    } else {
+        None
+    };
+    
+    // Pause and resume commands typed while the spinner is up. The thread
+    // blocks on stdin and simply ends with the process.
+    if interactive(&args) {
+        let search = search.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else { break };
+                match line.trim() {
+                    "p" => search.pause(),
+                    "r" => search.resume(),
+                    _ => {}
+                }
+            }
+        });
+    }
+    
+    let output_handle = std::thread::spawn({
+        let secrets = secret_output(&args);
+        let mut writers: Vec<ResultWriter> = output_paths(&args)
+            .into_iter()
+            .map(|(path, format)| ResultWriter::new(path, format, args.csv_extended).with_secrets(secrets))
+            .collect();
+        let meta = args.meta;
+        let json = args.json;
+        let quiet = args.quiet;
+        let qr = args.qr;
+        let qr_nsec = args.qr_nsec;
+        let color = use_color(&args);
+        let qr_dir = args.qr_dir.clone();
+        let output_dir = args.output_dir.clone();
+        let format = output_format(&args);
+        let csv_extended = args.csv_extended;
+        let json_file = args.json_file.clone();
+        let mut summary = RunSummary::new(search.matcher().patterns());
+        let mut output_failed = false;
+        let reporter = reporter.clone();
+        // Every key reported so far. Repeats are vanishingly unlikely, but a
+        // key is still only written once.
+        let mut seen: HashSet<String> = HashSet::new();
+        
+        move || {
+            for result in rx {
+                if !seen.insert(result.keypair.npub.clone()) {
+                    continue;
+                }
+                summary.record(&result);
+                if let Some(reporter) = &reporter {
+                    let message = Message::Match {
+                        keypair: result.keypair.clone(),
+                        pattern: result.matched_pattern.value.clone(),
+                        attempts: result.attempts,
+                    };
+                    if let Err(e) = reporter.send(&message) {
+                        eprintln!("Warning: lost connection to coordinator, searching alone: {}", e);
+                    }
+                }
+                if !quiet {
+                    if json {
+                        match result.format_json(SecretOutput::Full) {
+                            Ok(line) => println!("{}", line),
+                            Err(e) => eprintln!("Error: {}", e),
+                        }
+                    } else {
+                        println!("\n{}", result.format_output(color, SecretOutput::Full));
+                        if qr {
+                            print_qr("npub", &result.keypair.npub);
+                            if qr_nsec {
+                                print_qr("nsec", &result.keypair.nsec);
+                            }
+                        }
+                    }
+                }
+                
+                if let Some(ref path) = json_file {
+                    if let Err(e) = write_json_file(&result, path, secrets) {
+                        eprintln!("Warning: failed to write {}: {}", path.display(), e);
+                        output_failed = true;
+                    }
+                }
+                
+                if let Some(ref dir) = qr_dir {
+                    if let Err(e) = write_qr_pngs(&result, dir, qr_nsec, secrets) {
+                        eprintln!("Warning: failed to write QR code: {}", e);
+                        output_failed = true;
+                    }
+                }
+                
+                // A reader going away only ends the stream; the match still
+                // reaches stdout and the output files
+                if let Some(writer) = fifo.as_mut().filter(|writer| writer.is_open()) {
+                    if let Err(e) = writer.write(&result) {
+                        eprintln!("Warning: stopped streaming to {}: {}", writer.path().display(), e);
+                    }
+                }
+                
+                // Opened for each match rather than kept open, as a big
+                // harvest could otherwise hold a file per pattern
+                if let Some(ref dir) = output_dir {
+                    let path = pattern_output_path(dir, &result.matched_pattern.value, format);
+                    let mut writer = ResultWriter::new(path, format, csv_extended).with_secrets(secrets);
+                    if let Err(e) = writer.write(&result) {
+                        eprintln!("Warning: failed to write {}: {}", writer.path().display(), e);
+                        output_failed = true;
+                    }
+                }
+                
+                for writer in &mut writers {
+                    if let Err(e) = writer.write(&result) {
+                        eprintln!("Warning: failed to write {}: {}", writer.path().display(), e);
+                        output_failed = true;
+                    }
+                    if meta {
+                        let meta_file = meta_path(writer.path());
+                        if let Err(e) = write_meta_file(&result, &meta_file) {
+                            eprintln!("Warning: failed to write {}: {}", meta_file.display(), e);
+                            output_failed = true;
+                        }
+                    }
+                }
+            }
+            (summary, output_failed)
+        }
+    });
+    
+    let plain = plain_progress(&args);
+    if progress.is_some() || plain || args.progress_json || progress_log.is_some() {
+        let expected = expected_search_attempts(&search);
+        let interval = args.progress_interval;
+        let mut last_update: Option<Instant> = None;
+        let mut last_log = Instant::now();
+        let mut last_line = Instant::now();
+        let mut last_breakdown = Instant::now();
+        while !search.is_done() {
+            if args.balance && last_breakdown.elapsed() >= BALANCE_INTERVAL {
+                last_breakdown = Instant::now();
+                match &progress {
+                    Some(pb) => pb.println(pattern_breakdown(&search)),
+                    None if plain => eprintln!("{}", pattern_breakdown(&search)),
+                    None => {}
+                }
+            }
+            
+            if let Some(pb) = progress.as_ref().filter(|_| search.is_paused()) {
+                pb.set_message("⏸ Paused, type r and Enter to resume");
+                std::thread::sleep(PROGRESS_POLL);
+                continue;
+            }
+            
+            if last_update.is_none_or(|last| last.elapsed() >= interval) {
+                last_update = Some(Instant::now());
+                
+                let current_attempts = search.attempts();
+                let elapsed = search.elapsed().as_secs_f64();
+                let rate = current_attempts as f64 / elapsed.max(0.1);
+                let remaining = expected.map(|expected| expected - current_attempts as f64);
+                let eta = remaining.filter(|&r| r > 0.0 && rate > 0.0).map(|r| r / rate);
+                let stats = ProgressStats {
+                    attempts: current_attempts,
+                    keys_per_sec: rate,
+                    elapsed_seconds: elapsed,
+                    expected_attempts: expected,
+                    eta_seconds: eta,
+                };
+                
+                if args.progress_json {
+                    eprintln!("{}", serde_json::to_string(&stats)?);
+                }
+                if last_log.elapsed() >= args.progress_log_interval {
+                    last_log = Instant::now();
+                    ProgressLog::write(&mut progress_log, &stats.describe());
+                }
+                if plain && last_line.elapsed() >= PLAIN_PROGRESS_INTERVAL {
+                    last_line = Instant::now();
+                    eprintln!("{}", stats.describe());
+                }
+                
+                if let Some(pb) = &progress {
+                    let mut message = format!("Attempts: {}", current_attempts);
+                    if let Some(expected) = expected {
+                        message += &format!(
+                            " | ~{:.0}% of expected attempts",
+                            current_attempts as f64 / expected * 100.0
+                        );
+                        if let Some(eta) = eta {
+                            message += &format!(", ETA ~{}", format_duration(eta));
+                        }
+                    }
+                    pb.set_message(message);
+                    pb.set_prefix(format!("{:.0} keys/sec", rate));
+                }
+            }
+            
+            std::thread::sleep(interval.min(PROGRESS_POLL));
+        }
+    }
+    
+    let status = if interrupted.load(Ordering::Relaxed) {
+        "Interrupted"
+    } else if search.is_exhausted() {
+        "Attempt limit reached"
+    } else if search.is_timed_out() {
+        "Time limit reached"
+    } else {
+        "Complete!"
+    };
+    if let Some(pb) = &progress {
+        pb.finish_with_message(status);
+    }
+    
+    if args.balance && !args.quiet {
+        eprintln!("{}", pattern_breakdown(&search));
+    }
+    
+    let (summary, output_failed) = output_handle.join().unwrap();
+    if progress_log.is_some() {
+        let elapsed = search.elapsed().as_secs_f64();
+        let stats = ProgressStats {
+            attempts: search.attempts(),
+            keys_per_sec: search.attempts() as f64 / elapsed.max(0.1),
+            elapsed_seconds: elapsed,
+            expected_attempts: None,
+            eta_seconds: None,
+        };
+        ProgressLog::write(
+            &mut progress_log,
+            &format!("{} | {} | {} match(es)", status, stats.describe(), summary.matches),
+        );
+    }
+    if let Some(reporter) = &reporter {
+        // Best effort: the coordinator already has every progress report
+        let _ = reporter.send(&Message::Done { attempts: search.attempts() });
+    }
+    
+    if let Some(handle) = checkpoint_handle {
+        handle.join().unwrap();
+    }
+    if let Some(path) = &args.checkpoint {
+        write_checkpoint(&search.checkpoint(), path)?;
+    }
+    
+    if !args.quiet {
+        let reason = if interrupted.load(Ordering::Relaxed) {
+            "Interrupted\n".to_string()
+        } else if let Some(timeout) = args.timeout.filter(|_| search.is_timed_out()) {
+            format!("Stopped after {}\n", humantime::format_duration(timeout))
+        } else {
+            String::new()
+        };
+        eprintln!("\n{}{}", reason, summary.format(search.attempts(), search.elapsed()));
+    }
+    if args.per_thread_stats {
+        eprintln!("\n{}", thread_breakdown(&search));
+    }
+    // Shown even with --quiet, as it points at a failing random source
+    if search.key_retries() > 0 {
+        eprintln!(
+            "⚠️  {} key draw(s) gave an invalid key and were retried; the random source may be failing",
+            search.key_retries()
+        );
+    }
+    
+    // A found key that couldn't be saved is the worst outcome, as it may be
+    // lost once the terminal is closed
+    if output_failed {
+        return Ok(SearchOutcome::OutputFailed);
+    }
+    if let Some(e) = search.take_error() {
+        bail!("key generation failed: {}", e);
+    }
+    if search.is_found() {
+        return Ok(SearchOutcome::Found);
+    }
+    if interrupted.load(Ordering::Relaxed) {
+        return Ok(SearchOutcome::Interrupted);
+    }
+    if search.is_exhausted() {
+        eprintln!("Error: No match within {} attempts", search.attempts());
+    } else if let Some(timeout) = args.timeout.filter(|_| search.is_timed_out()) {
+        eprintln!("Error: No match within {}", humantime::format_duration(timeout));
+    }
+    Ok(SearchOutcome::NoMatch)
 }
\ No newline at end of file
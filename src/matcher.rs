@@ -1,95 +1,1897 @@
-use crate::generator::NostrKeyPair;
+use aho_corasick::AhoCorasick;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use crate::error::{Result, VanityError};
+use crate::generator::{KeyText, BECH32_BODY_LEN, BECH32_DATA_LEN};
+use std::borrow::Cow;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MatchType {
     Prefix,
     Suffix,
     Contains,
+    Regex,
+    Position,
+    Repeat,
+    // A prefix or a suffix, whichever the key happens to have
+    Either,
+    // A run of letters only or digits only at the start of the body
+    Charclass,
+    // A prefix and a suffix together, written `prefix...suffix`
+    Bookend,
+    // Stands for a library user's own predicate, see
+    // `VanitySearch::with_matcher`. Never matches by itself.
+    Custom,
+}
+
+impl FromStr for MatchType {
+    type Err = VanityError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "prefix" => Ok(MatchType::Prefix),
+            "suffix" => Ok(MatchType::Suffix),
+            "contains" => Ok(MatchType::Contains),
+            "regex" => Ok(MatchType::Regex),
+            "position" => Ok(MatchType::Position),
+            "repeat" => Ok(MatchType::Repeat),
+            "either" => Ok(MatchType::Either),
+            "charclass" => Ok(MatchType::Charclass),
+            "bookend" => Ok(MatchType::Bookend),
+            other => Err(VanityError::InvalidPattern(format!("unknown match type '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for MatchType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MatchType::Prefix => "prefix",
+            MatchType::Suffix => "suffix",
+            MatchType::Contains => "contains",
+            MatchType::Regex => "regex",
+            MatchType::Position => "position",
+            MatchType::Repeat => "repeat",
+            MatchType::Either => "either",
+            MatchType::Charclass => "charclass",
+            MatchType::Bookend => "bookend",
+            MatchType::Custom => "custom",
+        };
+        f.write_str(name)
+    }
+}
+
+impl MatchType {
+    // The match types a pattern of this type is satisfied by any of, in the
+    // order they are tried
+    pub fn alternatives(&self) -> &'static [MatchType] {
+        match self {
+            MatchType::Prefix => &[MatchType::Prefix],
+            MatchType::Suffix => &[MatchType::Suffix],
+            MatchType::Contains => &[MatchType::Contains],
+            MatchType::Regex => &[MatchType::Regex],
+            MatchType::Position => &[MatchType::Position],
+            MatchType::Repeat => &[MatchType::Repeat],
+            MatchType::Either => &[MatchType::Prefix, MatchType::Suffix],
+            MatchType::Charclass => &[MatchType::Charclass],
+            MatchType::Bookend => &[MatchType::Bookend],
+            MatchType::Custom => &[MatchType::Custom],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Npub,
+    Nsec,
+    Hex,
+}
+
+impl Target {
+    pub fn hrp_prefix(&self) -> &'static str {
+        match self {
+            Target::Npub => "npub1",
+            Target::Nsec => "nsec1",
+            Target::Hex => "",
+        }
+    }
+    
+    pub fn prefix_len(&self) -> usize {
+        self.hrp_prefix().len()
+    }
+    
+    // Length of the part after the prefix
+    pub fn body_len(&self) -> usize {
+        match self {
+            Target::Npub | Target::Nsec => BECH32_BODY_LEN,
+            Target::Hex => 64,
+        }
+    }
+    
+    pub fn alphabet_size(&self) -> usize {
+        match self {
+            Target::Npub | Target::Nsec => 32,
+            Target::Hex => 16,
+        }
+    }
+    
+    pub fn select<'a, K: KeyText + ?Sized>(&self, key: &'a K) -> Cow<'a, str> {
+        match self {
+            Target::Npub => key.npub(),
+            Target::Nsec => key.nsec(),
+            Target::Hex => key.hex_pubkey(),
+        }
+    }
+}
+
+// Characters dropped from the end of bech32 bodies by `exclude_checksum`
+const CHECKSUM_REGION_LEN: usize = BECH32_BODY_LEN - (BECH32_DATA_LEN - 1);
+
+// Wildcards for prefix, suffix and contains patterns: `?` stands for any one
+// character and `*` for any run of characters, e.g. `sat?shi` or `sat*dev`.
+// Neither is a bech32 or hex character, so no literal pattern changes meaning.
+pub const WILDCARD_ONE: char = '?';
+pub const WILDCARD_ANY: char = '*';
+
+pub fn contains_wildcards(value: &str) -> bool {
+    value.contains([WILDCARD_ONE, WILDCARD_ANY])
+}
+
+pub fn strip_wildcards(value: &str) -> String {
+    value.replace([WILDCARD_ONE, WILDCARD_ANY], "")
+}
+
+// Compiles wildcard variants into one regex matching like `match_type`. A
+// `*` at an unanchored end is dropped since it can only stretch the matched
+// slice to the end of the body.
+fn wildcard_regex(variants: &[String], match_type: &MatchType) -> Result<Regex> {
+    let alternatives: Vec<String> = variants
+        .iter()
+        .map(|variant| {
+            let trimmed = match match_type {
+                MatchType::Prefix => variant.trim_end_matches(WILDCARD_ANY),
+                MatchType::Suffix => variant.trim_start_matches(WILDCARD_ANY),
+                _ => variant.trim_matches(WILDCARD_ANY),
+            };
+            trimmed
+                .chars()
+                .map(|c| match c {
+                    WILDCARD_ONE => ".".to_string(),
+                    WILDCARD_ANY => ".*".to_string(),
+                    c => regex::escape(c.encode_utf8(&mut [0; 4])),
+                })
+                .collect()
+        })
+        .collect();
+    let alternation = format!("(?:{})", alternatives.join("|"));
+    let anchored = match match_type {
+        MatchType::Prefix => format!("^{}", alternation),
+        MatchType::Suffix => format!("{}$", alternation),
+        _ => alternation,
+    };
+    Ok(Regex::new(&anchored)?)
+}
+
+// Upper bound on the strings a single pattern may expand to under
+// substitutions, since every substitutable character multiplies the count.
+pub const MAX_VARIANTS: usize = 256;
+
+// Character substitutions applied to prefix, suffix and contains patterns,
+// mapping a character to the characters that may stand in for it. The
+// original character is only kept if it is listed among its replacements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitutions(Vec<(char, String)>);
+
+impl Substitutions {
+    // The leetspeak table for the four characters bech32 leaves out
+    pub fn leet() -> Self {
+        Substitutions(vec![
+            ('b', "8".to_string()),
+            ('i', "lj".to_string()),
+            ('o', "0".to_string()),
+            ('1', "l".to_string()),
+        ])
+    }
+    
+    // Entries from `other` replace entries for the same character
+    pub fn with_overrides(mut self, other: Substitutions) -> Self {
+        for (from, to) in other.0 {
+            match self.0.iter_mut().find(|(c, _)| *c == from) {
+                Some(entry) => entry.1 = to,
+                None => self.0.push((from, to)),
+            }
+        }
+        self
+    }
+    
+    pub fn expand(&self, value: &str) -> Result<Vec<String>> {
+        let mut variants = vec![String::new()];
+        for c in value.chars() {
+            let replacements = match self.0.iter().find(|(from, _)| *from == c.to_ascii_lowercase()) {
+                Some((_, to)) => to.chars().collect(),
+                None => vec![c],
+            };
+            variants = variants
+                .iter()
+                .flat_map(|prefix| replacements.iter().map(move |&r| format!("{}{}", prefix, r)))
+                .collect();
+            if variants.len() > MAX_VARIANTS {
+                return Err(VanityError::InvalidPattern(format!(
+                    "pattern '{}' expands to more than {} variants",
+                    value,
+                    MAX_VARIANTS
+                )));
+            }
+        }
+        Ok(variants)
+    }
+}
+
+// Parses `from=to[,from=to...]`, e.g. `o=0,e=e3`
+impl FromStr for Substitutions {
+    type Err = VanityError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (from, to) = entry
+                    .split_once('=')
+                    .ok_or_else(|| {
+                        VanityError::InvalidPattern(format!("invalid substitution '{}', expected from=to", entry))
+                    })?;
+                let mut from_chars = from.trim().chars();
+                let to = to.trim();
+                match (from_chars.next(), from_chars.next()) {
+                    (Some(from), None) if !to.is_empty() => Ok((from.to_ascii_lowercase(), to.to_string())),
+                    _ => Err(VanityError::InvalidPattern(format!(
+                        "invalid substitution '{}', expected from=to",
+                        entry
+                    ))),
+                }
+            })
+            .collect::<Result<_>>()
+            .map(Substitutions)
+    }
+}
+
+// Groups of look-alike or sound-alike characters treated as one when
+// matching prefix, suffix and contains patterns, e.g. `u` and `v`. Both the
+// pattern and the key are folded so every character becomes the first of
+// its group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharClasses(Vec<String>);
+
+impl CharClasses {
+    // Pairs that are easy to confuse in many display fonts
+    pub fn lookalikes() -> Self {
+        CharClasses(["uv", "s5", "z2", "g9q"].iter().map(|class| class.to_string()).collect())
+    }
+    
+    // Characters whose names are easy to mishear when an npub is read
+    // aloud: ef/ess/ex, em/en, tee/dee, pee/vee, see/zee, ay/jay/kay/eight
+    // and queue/you/two
+    pub fn soundalikes() -> Self {
+        CharClasses(["fsx", "mn", "dt", "pv", "cz", "ajk8", "qu2"].iter().map(|class| class.to_string()).collect())
+    }
+    
+    pub fn classes(&self) -> &[String] {
+        &self.0
+    }
+    
+    // Both sets of groups at once. Groups that share a character are joined,
+    // so every character still folds to a single one.
+    pub fn merge(&self, other: &CharClasses) -> CharClasses {
+        let mut merged: Vec<String> = Vec::new();
+        for class in self.0.iter().chain(&other.0) {
+            let mut joined = class.clone();
+            merged.retain(|existing| {
+                if !existing.chars().any(|c| joined.contains(c)) {
+                    return true;
+                }
+                let extra: String = existing.chars().filter(|&c| !joined.contains(c)).collect();
+                joined.push_str(&extra);
+                false
+            });
+            merged.push(joined);
+        }
+        CharClasses(merged)
+    }
+    
+    // The group `c` belongs to, if any
+    pub fn class_of(&self, c: char) -> Option<&str> {
+        self.0.iter().find(|class| class.contains(c)).map(String::as_str)
+    }
+    
+    pub fn fold(&self, c: char) -> char {
+        self.class_of(c).and_then(|class| class.chars().next()).unwrap_or(c)
+    }
+    
+    pub fn fold_str(&self, value: &str) -> String {
+        value.chars().map(|c| self.fold(c)).collect()
+    }
+}
+
+// Parses groups separated by commas or whitespace, e.g. `uv,s5 z2`. Groups
+// hold at least two lowercase ASCII letters or digits, and a character may
+// only be in one group.
+impl FromStr for CharClasses {
+    type Err = VanityError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        let mut classes: Vec<String> = Vec::new();
+        for class in s.split(|c: char| c == ',' || c.is_whitespace()).filter(|class| !class.is_empty()) {
+            if let Some(c) = class.chars().find(|c| !c.is_ascii_lowercase() && !c.is_ascii_digit()) {
+                return Err(VanityError::InvalidPattern(format!(
+                    "invalid character '{}' in look-alike group '{}'",
+                    c,
+                    class
+                )));
+            }
+            if class.len() < 2 {
+                return Err(VanityError::InvalidPattern(format!(
+                    "look-alike group '{}' needs at least two characters",
+                    class
+                )));
+            }
+            if let Some(c) = class.chars().find(|&c| classes.iter().any(|other| other.contains(c))) {
+                return Err(VanityError::InvalidPattern(format!(
+                    "'{}' is in more than one look-alike group",
+                    c
+                )));
+            }
+            classes.push(class.to_string());
+        }
+        if classes.is_empty() {
+            return Err(VanityError::InvalidPattern("no look-alike groups given".to_string()));
+        }
+        Ok(CharClasses(classes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    pub target: Target,
+    pub include_prefix: bool,
+    pub substitutions: Option<Substitutions>,
+    pub exclude_checksum: bool,
+    pub skip: usize,
+    pub fuzzy: Option<CharClasses>,
+    pub boundary: Option<char>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            case_sensitive: false,
+            target: Target::Npub,
+            include_prefix: false,
+            substitutions: None,
+            exclude_checksum: false,
+            skip: 0,
+            fuzzy: None,
+            boundary: None,
+        }
+    }
+}
+
+impl MatchOptions {
+    // The part of the target string patterns are compared against: the whole
+    // string with `include_prefix`, otherwise everything after `npub1`/`nsec1`.
+    // With `exclude_checksum`, bech32 targets also lose the checksum and the
+    // padded last data character, so suffixes land on freely varying data.
+    // The first `skip` characters of the body are dropped too, leaving an
+    // empty body when there aren't that many. Strings too short to carry the
+    // prefix have no body.
+    pub fn body<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let value = if self.exclude_checksum && self.target != Target::Hex {
+            value.get(..value.len().checked_sub(CHECKSUM_REGION_LEN)?)?
+        } else {
+            value
+        };
+        
+        let body = if self.include_prefix {
+            value
+        } else {
+            value.get(self.target.prefix_len()..)?
+        };
+        body.get(self.skip.min(body.len())..)
+    }
+    
+    // Length of the body after the prefix, less any excluded checksum region
+    // and skipped characters
+    pub fn body_len(&self) -> usize {
+        let len = if self.exclude_checksum && self.target != Target::Hex {
+            self.target.body_len() - CHECKSUM_REGION_LEN
+        } else {
+            self.target.body_len()
+        };
+        len.saturating_sub(self.skip)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Pattern {
     pub value: String,
     pub match_type: MatchType,
-    pub case_sensitive: bool,
+    pub options: MatchOptions,
+    regex: Option<Regex>,
+    positions: Vec<(usize, char)>,
+    repeat: Option<(char, usize)>,
+    charclass: Option<(CharKind, usize)>,
+    bookend: Option<(String, String)>,
+    variants: Vec<String>,
+    // For patterns with wildcards, a regex for each alternative match type
+    wildcards: Vec<(MatchType, Regex)>,
+}
+
+// Position patterns are comma-separated `index:char` constraints, all of
+// which must hold, e.g. `2:7,5:q`. Indices are zero-based into the body.
+pub fn parse_positions(value: &str) -> Result<Vec<(usize, char)>> {
+    value
+        .split(',')
+        .map(|constraint| {
+            let (index, c) = constraint
+                .split_once(':')
+                .ok_or_else(|| {
+                    VanityError::InvalidPattern(format!("invalid position '{}', expected index:char", constraint))
+                })?;
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| VanityError::InvalidPattern(format!("invalid position index '{}'", index)))?;
+            let mut chars = c.trim().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok((index, c)),
+                _ => Err(VanityError::InvalidPattern(format!(
+                    "invalid position character '{}', expected a single character",
+                    c
+                ))),
+            }
+        })
+        .collect()
+}
+
+// Repeat patterns are `char:min`: the body must start with at least `min`
+// copies of `char`, e.g. `0:6`
+pub fn parse_repeat(value: &str) -> Result<(char, usize)> {
+    let (c, min) = value
+        .split_once(':')
+        .ok_or_else(|| {
+            VanityError::InvalidPattern(format!("invalid repeat '{}', expected char:min", value))
+        })?;
+    let min = min
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&min| min > 0)
+        .ok_or_else(|| {
+            VanityError::InvalidPattern(format!("invalid repeat length '{}', expected a positive number", min))
+        })?;
+    let mut chars = c.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => Ok((c, min)),
+        _ => Err(VanityError::InvalidPattern(format!(
+            "invalid repeat character '{}', expected a single character",
+            c
+        ))),
+    }
+}
+
+// Which characters a charclass pattern accepts. Bech32 keys have 23 letters
+// and 9 digits to choose from, hex keys 6 and 10.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharKind {
+    Alpha,
+    Numeric,
+}
+
+impl CharKind {
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            CharKind::Alpha => c.is_ascii_alphabetic(),
+            CharKind::Numeric => c.is_ascii_digit(),
+        }
+    }
+}
+
+// Charclass patterns are `alpha:len` or `numeric:len`: the first `len` body
+// characters must all be letters, or all digits, e.g. `alpha:8`
+pub fn parse_charclass(value: &str) -> Result<(CharKind, usize)> {
+    let (kind, len) = value
+        .split_once(':')
+        .ok_or_else(|| {
+            VanityError::InvalidPattern(format!(
+                "invalid charclass '{}', expected alpha:len or numeric:len",
+                value
+            ))
+        })?;
+    let kind = match kind.trim().to_lowercase().as_str() {
+        "alpha" => CharKind::Alpha,
+        "numeric" => CharKind::Numeric,
+        other => {
+            return Err(VanityError::InvalidPattern(format!(
+                "invalid character class '{}', expected alpha or numeric",
+                other
+            )))
+        }
+    };
+    let len = len
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&len| len > 0)
+        .ok_or_else(|| {
+            VanityError::InvalidPattern(format!(
+                "invalid charclass length '{}', expected a positive number",
+                len
+            ))
+        })?;
+    Ok((kind, len))
+}
+
+// Separates the two ends of a bookend pattern
+pub const BOOKEND_SEPARATOR: &str = "...";
+
+// Bookend patterns are `prefix...suffix`: the body must start with `prefix`
+// and end with `suffix`, e.g. `sat...shi`. Both ends must be given.
+pub fn parse_bookend(value: &str) -> Result<(String, String)> {
+    match value.split_once(BOOKEND_SEPARATOR) {
+        Some((prefix, suffix)) if !prefix.is_empty() && !suffix.is_empty() => {
+            Ok((prefix.to_string(), suffix.to_string()))
+        }
+        _ => Err(VanityError::InvalidPattern(format!(
+            "invalid bookend '{}', expected prefix{}suffix",
+            value,
+            BOOKEND_SEPARATOR
+        ))),
+    }
 }
 
 impl Pattern {
-    pub fn new(value: String, match_type: MatchType, case_sensitive: bool) -> Self {
-        let value = if case_sensitive {
+    pub fn new(value: String, match_type: MatchType, options: MatchOptions) -> Result<Self> {
+        let regex = match match_type {
+            MatchType::Regex => Some(
+                RegexBuilder::new(&value)
+                    .case_insensitive(!options.case_sensitive)
+                    .build()?,
+            ),
+            _ => None,
+        };
+        
+        let value = if options.case_sensitive || regex.is_some() {
             value
         } else {
             value.to_lowercase()
         };
         
-        Pattern {
+        let positions = match match_type {
+            MatchType::Position => parse_positions(&value)?,
+            _ => Vec::new(),
+        };
+        
+        let repeat = match match_type {
+            MatchType::Repeat => Some(parse_repeat(&value)?),
+            _ => None,
+        };
+        
+        let charclass = match match_type {
+            MatchType::Charclass => Some(parse_charclass(&value)?),
+            _ => None,
+        };
+        
+        let bookend = match match_type {
+            MatchType::Bookend => Some(parse_bookend(&value)?),
+            _ => None,
+        };
+        
+        let variants = match (&match_type, &options.substitutions) {
+            (
+                MatchType::Prefix | MatchType::Suffix | MatchType::Contains | MatchType::Either,
+                Some(substitutions),
+            ) => {
+                substitutions.expand(&value)?
+            }
+            _ => vec![value.clone()],
+        };
+        let variants = match (&match_type, &options.fuzzy) {
+            (
+                MatchType::Prefix | MatchType::Suffix | MatchType::Contains | MatchType::Either,
+                Some(classes),
+            ) => {
+                let mut folded: Vec<String> = Vec::new();
+                for variant in variants.iter().map(|v| classes.fold_str(v)) {
+                    if !folded.contains(&variant) {
+                        folded.push(variant);
+                    }
+                }
+                folded
+            }
+            _ => variants,
+        };
+        
+        if let (MatchType::Contains, Some(boundary)) = (&match_type, options.boundary) {
+            if variants.iter().any(|v| contains_wildcards(v)) {
+                return Err(VanityError::InvalidPattern(format!(
+                    "pattern '{}' can't have wildcards and a '{}' boundary",
+                    value,
+                    boundary
+                )));
+            }
+        }
+        
+        let wildcards = match match_type {
+            MatchType::Prefix | MatchType::Suffix | MatchType::Contains | MatchType::Either
+                if variants.iter().any(|v| contains_wildcards(v)) =>
+            {
+                match_type
+                    .alternatives()
+                    .iter()
+                    .map(|alternative| Ok((alternative.clone(), wildcard_regex(&variants, alternative)?)))
+                    .collect::<Result<_>>()?
+            }
+            _ => Vec::new(),
+        };
+        
+        Ok(Pattern {
             value,
             match_type,
-            case_sensitive,
+            options,
+            regex,
+            positions,
+            repeat,
+            charclass,
+            bookend,
+            variants,
+            wildcards,
+        })
+    }
+    
+    // The pattern results of a custom predicate search report, labelled
+    // `label`. It has no spelling of its own, so it never matches a key.
+    pub fn custom(label: &str) -> Self {
+        Pattern {
+            value: label.to_string(),
+            match_type: MatchType::Custom,
+            options: MatchOptions::default(),
+            regex: None,
+            positions: Vec::new(),
+            repeat: None,
+            charclass: None,
+            bookend: None,
+            variants: Vec::new(),
+            wildcards: Vec::new(),
         }
     }
     
-    pub fn matches(&self, npub: &str) -> bool {
-        let npub_without_prefix = &npub[5..];
+    pub fn has_wildcards(&self) -> bool {
+        !self.wildcards.is_empty()
+    }
+    
+    // The look-alike groups this pattern's variants were folded through
+    fn fuzzy(&self) -> Option<&CharClasses> {
+        match self.match_type {
+            MatchType::Prefix | MatchType::Suffix | MatchType::Contains | MatchType::Either => {
+                self.options.fuzzy.as_ref()
+            }
+            _ => None,
+        }
+    }
+    
+    // The character a contains match must have on each side, unless it's at
+    // the start or end of the body, cased and folded like the text it's
+    // compared against
+    fn boundary(&self) -> Option<char> {
+        let boundary = self.options.boundary.filter(|_| self.match_type == MatchType::Contains)?;
+        let boundary = if self.options.case_sensitive { boundary } else { boundary.to_ascii_lowercase() };
+        Some(match &self.options.fuzzy {
+            Some(classes) => classes.fold(boundary),
+            None => boundary,
+        })
+    }
+    
+    // Start and end of the earliest occurrence of a variant in `compare_str`
+    // with `boundary` or the edge of the body on both sides. Occurrences may
+    // overlap, so every start is tried.
+    fn find_bounded(&self, compare_str: &str, boundary: char) -> Option<(usize, usize)> {
+        let bytes = compare_str.as_bytes();
+        let bounded = |index: Option<usize>| {
+            index.is_none_or(|index| bytes.get(index).is_none_or(|&b| b == boundary as u8))
+        };
+        (0..compare_str.len())
+            .filter_map(|start| {
+                self.variants
+                    .iter()
+                    .filter(|v| compare_str[start..].starts_with(v.as_str()))
+                    .map(|v| (start, start + v.len()))
+                    .find(|&(start, end)| bounded(start.checked_sub(1)) && bounded(Some(end)))
+            })
+            .next()
+    }
+    
+    fn wildcard_regex(&self, match_type: &MatchType) -> Option<&Regex> {
+        self.wildcards
+            .iter()
+            .find(|(alternative, _)| alternative == match_type)
+            .map(|(_, regex)| regex)
+    }
+    
+    // For repeat patterns, how many times the repeated character occurs at the
+    // start of the body of `value`. For charclass patterns, how many of its
+    // first characters are in the class.
+    pub fn run_length(&self, value: &str) -> Option<usize> {
+        if let Some((kind, _)) = self.charclass {
+            let body = self.options.body(value)?;
+            return Some(body.chars().take_while(|&c| kind.contains(c)).count());
+        }
+        let (c, _) = self.repeat?;
+        let body = self.options.body(value)?;
+        Some(if self.options.case_sensitive {
+            body.chars().take_while(|&b| b == c).count()
+        } else {
+            body.chars().take_while(|b| b.eq_ignore_ascii_case(&c)).count()
+        })
+    }
+    
+    // The part of the body of `value` this pattern matched, with its body
+    // index, in the casing it actually has. Position and bookend patterns
+    // match scattered characters or both ends rather than one region, so
+    // they have no slice.
+    pub fn matched_slice<'a>(&self, value: &'a str) -> Option<(usize, &'a str)> {
+        let body = self.options.body(value)?;
+        // Keys are ASCII, so lowercasing and folding keep byte offsets intact
+        let compare_str = if self.options.case_sensitive {
+            body.to_string()
+        } else {
+            body.to_ascii_lowercase()
+        };
+        let compare_str = match self.fuzzy() {
+            Some(classes) => classes.fold_str(&compare_str),
+            None => compare_str,
+        };
         
-        let compare_str = if self.case_sensitive {
-            npub_without_prefix.to_string()
+        let matched_type = self.matched_type(value)?;
+        if let Some(regex) = self.wildcard_regex(&matched_type) {
+            let found = regex.find(&compare_str)?;
+            return Some((found.start(), &body[found.start()..found.end()]));
+        }
+        let (start, end) = match matched_type {
+            MatchType::Prefix => self
+                .variants
+                .iter()
+                .find(|v| compare_str.starts_with(v.as_str()))
+                .map(|v| (0, v.len()))?,
+            MatchType::Suffix => self
+                .variants
+                .iter()
+                .find(|v| compare_str.ends_with(v.as_str()))
+                .map(|v| (body.len() - v.len(), body.len()))?,
+            MatchType::Contains => match self.boundary() {
+                Some(boundary) => self.find_bounded(&compare_str, boundary)?,
+                None => self
+                    .variants
+                    .iter()
+                    .filter_map(|v| compare_str.find(v.as_str()).map(|start| (start, start + v.len())))
+                    .min()?,
+            },
+            MatchType::Regex => self
+                .regex
+                .as_ref()?
+                .find(&compare_str)
+                .map(|m| (m.start(), m.end()))?,
+            MatchType::Position | MatchType::Either | MatchType::Bookend | MatchType::Custom => return None,
+            MatchType::Repeat | MatchType::Charclass => (0, self.run_length(value).filter(|&run| run > 0)?),
+        };
+        Some((start, &body[start..end]))
+    }
+    
+    // Byte range of `matched_slice` within the body of `value` before any
+    // `skip`, so it lines up with the text after the prefix
+    pub fn matched_span(&self, value: &str) -> Option<Range<usize>> {
+        let (start, slice) = self.matched_slice(value)?;
+        let start = self.options.skip + start;
+        Some(start..start + slice.len())
+    }
+    
+    // Every region of `value` this pattern matched, like `matched_span`: both
+    // ends for bookend patterns, otherwise the one span if there is one
+    pub fn matched_spans(&self, value: &str) -> Vec<Range<usize>> {
+        let Some((prefix, suffix)) = &self.bookend else {
+            return self.matched_span(value).into_iter().collect();
+        };
+        if !self.matches(value) {
+            return Vec::new();
+        }
+        let end = self.options.skip + self.options.body(value).map_or(0, str::len);
+        vec![
+            self.options.skip..self.options.skip + prefix.len(),
+            end - suffix.len()..end,
+        ]
+    }
+    
+    // True if every key matching `other` also matches this pattern: the same
+    // pattern, for prefix/suffix/contains one whose every variant extends a
+    // variant of this one, for repeats and charclasses a longer run of the
+    // same character or class, and for bookends one whose prefix and suffix
+    // both extend this one's. Regex and position patterns only cover
+    // themselves. Wildcards are compared as written, so `sa?` covers `sa?x`
+    // but not `sat`.
+    pub fn covers(&self, other: &Pattern) -> bool {
+        if self.match_type != other.match_type || self.options != other.options {
+            return false;
+        }
+        if let (Some((c, min)), Some((other_c, other_min))) = (self.repeat, other.repeat) {
+            return c == other_c && min <= other_min;
+        }
+        if let (Some((kind, len)), Some((other_kind, other_len))) = (self.charclass, other.charclass) {
+            return kind == other_kind && len <= other_len;
+        }
+        if let (Some((prefix, suffix)), Some((other_prefix, other_suffix))) = (&self.bookend, &other.bookend) {
+            return other_prefix.starts_with(prefix.as_str()) && other_suffix.ends_with(suffix.as_str());
+        }
+        
+        let extends = |longer: &String| {
+            self.variants.iter().any(|v| match self.match_type {
+                MatchType::Prefix => longer.starts_with(v.as_str()),
+                MatchType::Suffix => longer.ends_with(v.as_str()),
+                // A bounded match of the longer pattern needn't have the
+                // boundary around the shorter one
+                MatchType::Contains => self.boundary().is_none() && longer.contains(v.as_str()),
+                // Whichever end `other` matched at, this pattern must too
+                MatchType::Either => longer.starts_with(v.as_str()) && longer.ends_with(v.as_str()),
+                MatchType::Regex
+                | MatchType::Position
+                | MatchType::Repeat
+                | MatchType::Charclass
+                | MatchType::Bookend
+                | MatchType::Custom => false,
+            })
+        };
+        self.value == other.value || other.variants.iter().all(extends)
+    }
+    
+    // The literal strings searched for: just `value`, or every expansion of
+    // it when substitutions are enabled
+    pub fn variants(&self) -> &[String] {
+        &self.variants
+    }
+    
+    pub fn matches(&self, value: &str) -> bool {
+        self.matched_type(value).is_some()
+    }
+    
+    // Which of the pattern's alternative match types `value` satisfies, the
+    // first listed if several do. Only differs from `match_type` for either.
+    pub fn matched_type(&self, value: &str) -> Option<MatchType> {
+        let body = self.options.body(value)?;
+        
+        let compare_str = if self.options.case_sensitive {
+            body.to_string()
         } else {
-            npub_without_prefix.to_lowercase()
+            body.to_lowercase()
+        };
+        let compare_str = match self.fuzzy() {
+            Some(classes) => classes.fold_str(&compare_str),
+            None => compare_str,
         };
         
-        match self.match_type {
-            MatchType::Prefix => compare_str.starts_with(&self.value),
-            MatchType::Suffix => compare_str.ends_with(&self.value),
-            MatchType::Contains => compare_str.contains(&self.value),
+        self.match_type
+            .alternatives()
+            .iter()
+            .find(|match_type| self.matches_as(&compare_str, match_type))
+            .cloned()
+    }
+    
+    fn matches_as(&self, compare_str: &str, match_type: &MatchType) -> bool {
+        if let Some(regex) = self.wildcard_regex(match_type) {
+            return regex.is_match(compare_str);
+        }
+        match match_type {
+            MatchType::Prefix => self.variants.iter().any(|v| compare_str.starts_with(v.as_str())),
+            MatchType::Suffix => self.variants.iter().any(|v| compare_str.ends_with(v.as_str())),
+            MatchType::Contains => match self.boundary() {
+                Some(boundary) => self.find_bounded(compare_str, boundary).is_some(),
+                None => self.variants.iter().any(|v| compare_str.contains(v.as_str())),
+            },
+            MatchType::Regex => self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(compare_str)),
+            MatchType::Position => self
+                .positions
+                .iter()
+                .all(|&(index, c)| compare_str.as_bytes().get(index) == Some(&(c as u8))),
+            MatchType::Repeat => self.repeat.is_some_and(|(c, min)| {
+                compare_str.len() >= min && compare_str.bytes().take(min).all(|b| b == c as u8)
+            }),
+            MatchType::Charclass => self.charclass.is_some_and(|(kind, len)| {
+                compare_str.len() >= len && compare_str.chars().take(len).all(|c| kind.contains(c))
+            }),
+            // The ends may not share characters
+            MatchType::Bookend => self.bookend.as_ref().is_some_and(|(prefix, suffix)| {
+                compare_str.len() >= prefix.len() + suffix.len()
+                    && compare_str.starts_with(prefix.as_str())
+                    && compare_str.ends_with(suffix.as_str())
+            }),
+            MatchType::Custom => false,
+            MatchType::Either => unreachable!("either is matched through its alternatives"),
+        }
+    }
+}
+
+// Removes patterns covered by another pattern, keeping the first listed
+// order of the rest. With `drop_extensions` false only exact duplicates go,
+// e.g. when each pattern has its own match quota. Each dropped pattern is
+// returned alongside the kept pattern that covers it.
+//
+// Only patterns with the same match type and options can cover each other.
+// Within such a group, plain prefix, suffix and contains patterns are
+// checked by looking up each shorter piece of their value, and patterns that
+// only cover exact duplicates by looking up the value itself, so large
+// pattern files dedupe in linear time. The rest are compared pairwise.
+pub fn dedupe_patterns(
+    patterns: Vec<Pattern>,
+    drop_extensions: bool,
+) -> (Vec<Pattern>, Vec<(Pattern, Pattern)>) {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (index, pattern) in patterns.iter().enumerate() {
+        let same = |group: &&mut Vec<usize>| {
+            let first = &patterns[group[0]];
+            first.match_type == pattern.match_type && first.options == pattern.options
+        };
+        match groups.iter_mut().find(same) {
+            Some(group) => group.push(index),
+            None => groups.push(vec![index]),
+        }
+    }
+    
+    let mut keepers: Vec<Option<usize>> = vec![None; patterns.len()];
+    for group in &groups {
+        let first = &patterns[group[0]];
+        let literal = group.iter().all(|&i| patterns[i].variants.len() == 1);
+        match first.match_type {
+            _ if !drop_extensions => dedupe_exact(&patterns, group, |p| p.value.as_str(), &mut keepers),
+            MatchType::Regex | MatchType::Position => {
+                dedupe_exact(&patterns, group, |p| p.value.as_str(), &mut keepers)
+            }
+            MatchType::Contains if first.boundary().is_some() => {
+                dedupe_exact(&patterns, group, |p| p.value.as_str(), &mut keepers)
+            }
+            MatchType::Prefix | MatchType::Suffix | MatchType::Contains if literal => {
+                dedupe_extensions(&patterns, group, &mut keepers)
+            }
+            _ => dedupe_pairwise(&patterns, group, &mut keepers),
+        }
+    }
+    
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (index, keeper) in keepers.into_iter().enumerate() {
+        match keeper {
+            Some(keeper) => dropped.push((patterns[index].clone(), patterns[keeper].clone())),
+            None => kept.push(index),
+        }
+    }
+    let mut patterns: Vec<Option<Pattern>> = patterns.into_iter().map(Some).collect();
+    let kept = kept.into_iter().filter_map(|index| patterns[index].take()).collect();
+    (kept, dropped)
+}
+
+// Drops every pattern whose key, as given by `key`, an earlier one shares
+fn dedupe_exact<'a, F>(patterns: &'a [Pattern], group: &[usize], key: F, keepers: &mut [Option<usize>])
+where
+    F: Fn(&'a Pattern) -> &'a str,
+{
+    let mut first: HashMap<&str, usize> = HashMap::new();
+    for &index in group {
+        let earliest = *first.entry(key(&patterns[index])).or_insert(index);
+        if earliest != index {
+            keepers[index] = Some(earliest);
+        }
+    }
+}
+
+// For single-spelling prefix, suffix and contains patterns, one pattern
+// covers another exactly when its spelling is a prefix, suffix or substring
+// of the other's. The shortest such piece present can't itself be covered,
+// as its own pieces are pieces of the longer spelling too, so it's the keeper.
+fn dedupe_extensions(patterns: &[Pattern], group: &[usize], keepers: &mut [Option<usize>]) {
+    let spelling = |index: usize| patterns[index].variants[0].as_str();
+    let mut first: HashMap<&str, usize> = HashMap::new();
+    for &index in group {
+        first.entry(spelling(index)).or_insert(index);
+    }
+    
+    for &index in group {
+        let value = spelling(index);
+        let mut bounds: Vec<usize> = value.char_indices().map(|(i, _)| i).collect();
+        bounds.push(value.len());
+        let pieces: Vec<&str> = match patterns[index].match_type {
+            MatchType::Prefix => bounds[1..bounds.len() - 1].iter().map(|&end| &value[..end]).collect(),
+            MatchType::Suffix => bounds[1..bounds.len() - 1].iter().map(|&start| &value[start..]).collect(),
+            _ => bounds
+                .iter()
+                .flat_map(|&start| bounds.iter().map(move |&end| (start, end)))
+                .filter(|&(start, end)| start < end && end - start < value.len())
+                .map(|(start, end)| &value[start..end])
+                .collect(),
+        };
+        
+        let shortest = pieces
+            .iter()
+            .filter_map(|piece| first.get(piece).map(|&keeper| (piece.len(), keeper)))
+            .min();
+        keepers[index] = match shortest {
+            Some((_, keeper)) => Some(keeper),
+            None => Some(first[value]).filter(|&earliest| earliest != index),
+        };
+    }
+}
+
+// Compares every pattern with the ones kept before it
+fn dedupe_pairwise(patterns: &[Pattern], group: &[usize], keepers: &mut [Option<usize>]) {
+    let mut kept: Vec<usize> = Vec::new();
+    for &index in group {
+        let pattern = &patterns[index];
+        if let Some(&keeper) = kept.iter().find(|&&k| patterns[k].covers(pattern)) {
+            keepers[index] = Some(keeper);
+            continue;
+        }
+        
+        kept.retain(|&k| {
+            let covered = pattern.covers(&patterns[k]);
+            if covered {
+                keepers[k] = Some(index);
+            }
+            !covered
+        });
+        kept.push(index);
+    }
+}
+
+// Matches many prefixes at once by walking a trie of them along the text,
+// so a candidate is rejected at the first character no prefix continues
+// with instead of being compared against every prefix.
+pub struct PrefixTrie {
+    nodes: Vec<TrieNode>,
+    case_insensitive: bool,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    // Sorted by byte for binary search
+    children: Vec<(u8, usize)>,
+    // Patterns whose prefix ends at this node
+    patterns: Vec<usize>,
+}
+
+impl PrefixTrie {
+    pub fn new(case_insensitive: bool) -> Self {
+        PrefixTrie {
+            nodes: vec![TrieNode::default()],
+            case_insensitive,
+        }
+    }
+    
+    pub fn insert(&mut self, prefix: &str, pattern: usize) {
+        let mut node = 0;
+        for byte in prefix.bytes() {
+            let byte = self.fold(byte);
+            node = match self.nodes[node].children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(position) => self.nodes[node].children[position].1,
+                Err(position) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode::default());
+                    self.nodes[node].children.insert(position, (byte, child));
+                    child
+                }
+            };
+        }
+        self.nodes[node].patterns.push(pattern);
+    }
+    
+    // The lowest pattern whose prefix starts `text`, ignoring patterns for
+    // which `skip` returns true
+    pub fn find<F: Fn(usize) -> bool>(&self, text: &str, skip: F) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        let mut node = 0;
+        let mut bytes = text.bytes();
+        
+        loop {
+            let found = self.nodes[node].patterns.iter().copied().filter(|&p| !skip(p)).min();
+            if let Some(pattern) = found {
+                best = Some(best.map_or(pattern, |b| b.min(pattern)));
+            }
+            
+            let Some(byte) = bytes.next() else {
+                return best;
+            };
+            let byte = self.fold(byte);
+            match self.nodes[node].children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(position) => node = self.nodes[node].children[position].1,
+                Err(_) => return best,
+            }
+        }
+    }
+    
+    fn fold(&self, byte: u8) -> u8 {
+        if self.case_insensitive {
+            byte.to_ascii_lowercase()
+        } else {
+            byte
         }
     }
 }
 
+// Prefix patterns sharing match options go into one trie, contains patterns
+// into one Aho-Corasick automaton, where `indices` maps automaton pattern IDs,
+// one per variant, back into `patterns`.
+enum GroupSearch {
+    Prefix(PrefixTrie),
+    Contains {
+        automaton: AhoCorasick,
+        indices: Vec<usize>,
+    },
+}
+
+struct PatternGroup {
+    options: MatchOptions,
+    search: GroupSearch,
+}
+
 pub struct PatternMatcher {
     patterns: Vec<Pattern>,
+    groups: Vec<PatternGroup>,
+    fallback: Vec<usize>,
 }
 
 impl PatternMatcher {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let mut groups: Vec<((MatchOptions, bool), Vec<usize>)> = Vec::new();
+        let mut fallback = Vec::new();
+        
+        for (index, pattern) in patterns.iter().enumerate() {
+            // Wildcard patterns need their own regex, and bounded contains
+            // patterns a look at their neighbours
+            let prefix = match pattern.match_type {
+                MatchType::Prefix if !pattern.has_wildcards() => true,
+                MatchType::Contains if !pattern.has_wildcards() && pattern.boundary().is_none() => false,
+                _ => {
+                    fallback.push(index);
+                    continue;
+                }
+            };
+            
+            let key = (pattern.options.clone(), prefix);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, indices)) => indices.push(index),
+                None => groups.push((key, vec![index])),
+            }
+        }
+        
+        let mut built_groups = Vec::new();
+        for ((options, prefix), indices) in groups {
+            let variants: Vec<(usize, &String)> = indices
+                .iter()
+                .flat_map(|&i| patterns[i].variants.iter().map(move |v| (i, v)))
+                .collect();
+            
+            if prefix {
+                let mut trie = PrefixTrie::new(!options.case_sensitive);
+                for (index, variant) in variants {
+                    trie.insert(variant, index);
+                }
+                built_groups.push(PatternGroup {
+                    options,
+                    search: GroupSearch::Prefix(trie),
+                });
+                continue;
+            }
+            
+            let built = AhoCorasick::builder()
+                .ascii_case_insensitive(!options.case_sensitive)
+                .build(variants.iter().map(|(_, v)| v));
+            match built {
+                Ok(automaton) => built_groups.push(PatternGroup {
+                    options,
+                    search: GroupSearch::Contains {
+                        automaton,
+                        indices: variants.iter().map(|&(i, _)| i).collect(),
+                    },
+                }),
+                Err(_) => fallback.extend(indices),
+            }
+        }
+        fallback.sort_unstable();
+        
+        PatternMatcher {
+            patterns,
+            groups: built_groups,
+            fallback,
+        }
+    }
+    
     pub fn from_strings(
         values: Vec<String>, 
         match_type: MatchType, 
-        case_sensitive: bool
-    ) -> Self {
+        options: MatchOptions,
+    ) -> Result<Self> {
         let patterns = values
             .into_iter()
-            .map(|v| Pattern::new(v, match_type.clone(), case_sensitive))
-            .collect();
+            .map(|v| Pattern::new(v, match_type.clone(), options.clone()))
+            .collect::<Result<_>>()?;
         
-        PatternMatcher { patterns }
+        Ok(PatternMatcher::new(patterns))
+    }
+    
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+    
+    // How many patterns are checked one at a time against every key rather
+    // than through a shared trie or automaton
+    pub fn unindexed(&self) -> usize {
+        self.fallback.len()
     }
     
-    pub fn find_match(&self, keypair: &NostrKeyPair) -> Option<Pattern> {
-        for pattern in &self.patterns {
-            if pattern.matches(&keypair.npub) {
-                return Some(pattern.clone());
+    // Returns the earliest listed pattern that matches, as the plain loop over
+    // `patterns` would, but with a single trie walk or automaton pass per group.
+    pub fn find_match<K: KeyText + ?Sized>(&self, key: &K) -> Option<Pattern> {
+        self.find_match_index(key, |_| false)
+            .map(|index| self.patterns[index].clone())
+    }
+    
+    // True if every pattern matches `key`, for --match-all. Checked in list
+    // order, so the rarest pattern listed first rejects most keys soonest.
+    pub fn all_match<K: KeyText + ?Sized>(&self, key: &K) -> bool {
+        !self.patterns.is_empty()
+            && self
+                .patterns
+                .iter()
+                .all(|pattern| pattern.matches(&pattern.options.target.select(key)))
+    }
+    
+    // Indices of the patterns listed after `first` that also match `key`,
+    // ignoring patterns for which `skip` returns true. Each pattern is checked
+    // on its own, so this is meant for the rare keys that already matched.
+    pub fn later_matches<K, F>(&self, key: &K, first: usize, skip: F) -> Vec<usize>
+    where
+        K: KeyText + ?Sized,
+        F: Fn(usize) -> bool,
+    {
+        (first + 1..self.patterns.len())
+            .filter(|&index| !skip(index))
+            .filter(|&index| {
+                let pattern = &self.patterns[index];
+                pattern.matches(&pattern.options.target.select(key))
+            })
+            .collect()
+    }
+    
+    // `find_match` plus where the pattern matched in the body of the key it
+    // targets, for highlighting. The span is None for position patterns,
+    // which match scattered characters rather than one region.
+    pub fn find_match_span<K: KeyText + ?Sized>(&self, key: &K) -> Option<(Pattern, Option<Range<usize>>)> {
+        let pattern = self.find_match(key)?;
+        let span = pattern.matched_span(&pattern.options.target.select(key));
+        Some((pattern, span))
+    }
+    
+    // Index into `patterns` of the earliest listed match, ignoring patterns
+    // for which `skip` returns true
+    pub fn find_match_index<K, F>(&self, key: &K, skip: F) -> Option<usize>
+    where
+        K: KeyText + ?Sized,
+        F: Fn(usize) -> bool,
+    {
+        let mut best: Option<usize> = None;
+        
+        for group in &self.groups {
+            let text = group.options.target.select(key);
+            let Some(body) = group.options.body(&text) else {
+                continue;
+            };
+            // Variants were folded when the patterns were built
+            let folded;
+            let body = match &group.options.fuzzy {
+                Some(classes) => {
+                    folded = classes.fold_str(body);
+                    folded.as_str()
+                }
+                None => body,
+            };
+            let found = match &group.search {
+                GroupSearch::Prefix(trie) => trie.find(body, &skip),
+                GroupSearch::Contains { automaton, indices } => automaton
+                    .find_overlapping_iter(body)
+                    .map(|m| indices[m.pattern().as_usize()])
+                    .filter(|&index| !skip(index))
+                    .min(),
+            };
+            
+            if let Some(index) = found {
+                best = Some(best.map_or(index, |b| b.min(index)));
+            }
+        }
+        
+        for &index in &self.fallback {
+            if best.is_some_and(|b| b < index) {
+                break;
+            }
+            if skip(index) {
+                continue;
+            }
+            let pattern = &self.patterns[index];
+            if pattern.matches(&pattern.options.target.select(key)) {
+                best = Some(index);
+                break;
             }
         }
-        None
+        
+        best
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generator::NostrKeyPair;
     
     #[test]
     fn test_pattern_matching() {
-        let pattern = Pattern::new("test".to_string(), MatchType::Prefix, false);
+        let pattern = Pattern::new("test".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap();
         assert!(pattern.matches("npub1test123456"));
         assert!(!pattern.matches("npub1abc123456"));
         
-        let pattern = Pattern::new("end".to_string(), MatchType::Suffix, false);
+        let pattern = Pattern::new("end".to_string(), MatchType::Suffix, MatchOptions::default()).unwrap();
         assert!(pattern.matches("npub1123456end"));
         assert!(!pattern.matches("npub1123456abc"));
         
-        let pattern = Pattern::new("mid".to_string(), MatchType::Contains, false);
+        let pattern = Pattern::new("mid".to_string(), MatchType::Contains, MatchOptions::default()).unwrap();
         assert!(pattern.matches("npub1123mid456"));
         assert!(!pattern.matches("npub1123456789"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_target_prefix_stripping() {
+        let nsec = MatchOptions { target: Target::Nsec, ..MatchOptions::default() };
+        let pattern = Pattern::new("abc".to_string(), MatchType::Prefix, nsec).unwrap();
+        assert!(pattern.matches("nsec1abc123456"));
+        assert!(!pattern.matches("nsec1123abc456"));
+        
+        let hex = MatchOptions { target: Target::Hex, ..MatchOptions::default() };
+        let pattern = Pattern::new("00".to_string(), MatchType::Prefix, hex).unwrap();
+        assert!(pattern.matches("00ff12ab"));
+        assert!(!pattern.matches("ff0012ab"));
+    }
+    
+    #[test]
+    fn test_regex_matching() {
+        let pattern = Pattern::new("^[0-9]{2}sat".to_string(), MatchType::Regex, MatchOptions::default()).unwrap();
+        assert!(pattern.matches("npub142satxyz"));
+        assert!(!pattern.matches("npub1satxyz42"));
+        
+        assert!(Pattern::new("(unclosed".to_string(), MatchType::Regex, MatchOptions::default()).is_err());
+    }
+    
+    #[test]
+    fn test_matcher_reports_first_listed_pattern() {
+        let keypair = NostrKeyPair {
+            npub: "npub1sat0zap7xend".to_string(),
+            nsec: "nsec1abc".to_string(),
+            hex_pubkey: "00ff".to_string(),
+            mnemonic: None,
+        };
+        let patterns = vec![
+            Pattern::new("nope".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap(),
+            Pattern::new("end".to_string(), MatchType::Suffix, MatchOptions::default()).unwrap(),
+            Pattern::new("zap".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            Pattern::new("sat".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap(),
+        ];
+        let matcher = PatternMatcher::new(patterns);
+        assert_eq!(matcher.find_match(&keypair).unwrap().value, "end");
+        assert_eq!(matcher.find_match_index(&keypair, |index| index == 1), Some(2));
+        assert_eq!(matcher.find_match_index(&keypair, |index| index != 3), Some(3));
+        
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqq".to_string(), "zap".to_string(), "sat".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        assert_eq!(matcher.find_match(&keypair).unwrap().value, "zap");
+        
+        let matcher = PatternMatcher::from_strings(
+            vec!["zap".to_string(), "ff".to_string()],
+            MatchType::Prefix,
+            MatchOptions { target: Target::Hex, ..MatchOptions::default() },
+        ).unwrap();
+        assert!(matcher.find_match(&keypair).is_none());
+    }
+    
+    #[test]
+    fn test_include_prefix() {
+        let options = MatchOptions { include_prefix: true, ..MatchOptions::default() };
+        let pattern = Pattern::new("npub1sat".to_string(), MatchType::Prefix, options).unwrap();
+        assert!(pattern.matches("npub1satxyz"));
+        assert!(!pattern.matches("npub1xyzsat"));
+        
+        let pattern = Pattern::new("sat".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap();
+        assert!(!pattern.matches("npu"));
+    }
+    
+    #[test]
+    fn test_short_inputs() {
+        let excluded = MatchOptions { exclude_checksum: true, ..MatchOptions::default() };
+        let patterns = [
+            ("sat", MatchType::Prefix, MatchOptions::default()),
+            ("sat", MatchType::Suffix, MatchOptions::default()),
+            ("sat", MatchType::Contains, MatchOptions::default()),
+            ("sat", MatchType::Either, MatchOptions::default()),
+            ("sat", MatchType::Suffix, excluded),
+            ("^sat", MatchType::Regex, MatchOptions::default()),
+            ("2:t", MatchType::Position, MatchOptions::default()),
+            ("s:3", MatchType::Repeat, MatchOptions::default()),
+        ];
+        
+        for (value, match_type, options) in patterns {
+            let pattern = Pattern::new(value.to_string(), match_type, options).unwrap();
+            for input in ["", "n", "npub", "npub1", "npub1s", "npub1sa"] {
+                assert!(!pattern.matches(input), "{} matched {:?}", value, input);
+                assert_eq!(pattern.matched_slice(input), None);
+            }
+        }
+        
+        // Multi-byte characters where the prefix should end
+        let pattern = Pattern::new("sat".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap();
+        assert!(!pattern.matches("npub✨sat"));
+    }
+    
+    #[test]
+    fn test_position_matching() {
+        let pattern = Pattern::new("2:7,5:q".to_string(), MatchType::Position, MatchOptions::default()).unwrap();
+        assert!(pattern.matches("npub1ab7deqxyz"));
+        assert!(!pattern.matches("npub1ab7dexxyz"));
+        assert!(!pattern.matches("npub1ab"));
+        
+        assert!(Pattern::new("2".to_string(), MatchType::Position, MatchOptions::default()).is_err());
+        assert!(Pattern::new("x:7".to_string(), MatchType::Position, MatchOptions::default()).is_err());
+        assert!(Pattern::new("2:77".to_string(), MatchType::Position, MatchOptions::default()).is_err());
+    }
+    
+    #[test]
+    fn test_substitutions() {
+        let leet = MatchOptions { substitutions: Some(Substitutions::leet()), ..MatchOptions::default() };
+        let pattern = Pattern::new("bio".to_string(), MatchType::Prefix, leet.clone()).unwrap();
+        assert_eq!(pattern.variants(), ["8l0", "8j0"]);
+        assert!(pattern.matches("npub18j0xyz"));
+        assert!(!pattern.matches("npub1bioxyz"));
+        
+        let matcher = PatternMatcher::new(vec![
+            Pattern::new("zap".to_string(), MatchType::Contains, leet.clone()).unwrap(),
+            Pattern::new("bob".to_string(), MatchType::Contains, leet).unwrap(),
+        ]);
+        let keypair = NostrKeyPair {
+            npub: "npub1xx808yy".to_string(),
+            nsec: String::new(),
+            hex_pubkey: String::new(),
+            mnemonic: None,
+        };
+        assert_eq!(matcher.find_match(&keypair).unwrap().value, "bob");
+        
+        let custom = Substitutions::leet().with_overrides("e=e3,o=0o".parse().unwrap());
+        assert_eq!(custom.expand("oe").unwrap(), ["0e", "03", "oe", "o3"]);
+        assert!("e3".parse::<Substitutions>().is_err());
+        assert!(Substitutions::leet().expand(&"i".repeat(9)).is_err());
+    }
+    
+    #[test]
+    fn test_dedupe_patterns() {
+        let pattern = |value: &str, match_type: MatchType| {
+            Pattern::new(value.to_string(), match_type, MatchOptions::default()).unwrap()
+        };
+        let patterns = vec![
+            pattern("satx", MatchType::Prefix),
+            pattern("SAT", MatchType::Prefix),
+            pattern("sat", MatchType::Prefix),
+            pattern("sat", MatchType::Suffix),
+            pattern("xsat", MatchType::Suffix),
+            pattern("zap", MatchType::Contains),
+            pattern("zapzap", MatchType::Contains),
+        ];
+        
+        let (kept, dropped) = dedupe_patterns(patterns.clone(), true);
+        let kept: Vec<_> = kept.iter().map(|p| p.value.as_str()).collect();
+        assert_eq!(kept, ["sat", "sat", "zap"]);
+        let dropped: Vec<_> = dropped.iter().map(|(p, by)| (p.value.as_str(), by.value.as_str())).collect();
+        assert_eq!(dropped, [("satx", "sat"), ("sat", "sat"), ("xsat", "sat"), ("zapzap", "zap")]);
+        
+        let (kept, _) = dedupe_patterns(patterns, false);
+        assert_eq!(kept.len(), 6);
+        
+        // Dropped patterns name the pattern that was kept
+        let chain = vec![
+            pattern("satx", MatchType::Prefix),
+            pattern("sat", MatchType::Prefix),
+            pattern("sa", MatchType::Prefix),
+        ];
+        let (kept, dropped) = dedupe_patterns(chain, true);
+        assert_eq!(kept.len(), 1);
+        assert!(dropped.iter().all(|(_, by)| by.value == "sa"));
+    }
+    
+    #[test]
+    fn test_dedupe_lookups_match_pairwise() {
+        use rand::Rng;
+        
+        // Short values from a small alphabet, so many cover each other
+        let mut rng = crate::generator::seeded_rng(3, 0);
+        for match_type in [MatchType::Prefix, MatchType::Suffix, MatchType::Contains] {
+            let patterns: Vec<Pattern> = (0..300)
+                .map(|_| {
+                    let len = rng.random_range(1..5);
+                    let value: String = (0..len).map(|_| if rng.random() { 'a' } else { 'c' }).collect();
+                    Pattern::new(value, match_type.clone(), MatchOptions::default()).unwrap()
+                })
+                .collect();
+            let group: Vec<usize> = (0..patterns.len()).collect();
+            
+            let mut lookups = vec![None; patterns.len()];
+            dedupe_extensions(&patterns, &group, &mut lookups);
+            let mut pairwise = vec![None; patterns.len()];
+            dedupe_pairwise(&patterns, &group, &mut pairwise);
+            let kept = |keepers: &[Option<usize>]| -> Vec<usize> {
+                keepers.iter().enumerate().filter(|(_, k)| k.is_none()).map(|(i, _)| i).collect()
+            };
+            assert_eq!(kept(&lookups), kept(&pairwise), "{:?}", match_type);
+        }
+    }
+    
+    #[test]
+    fn test_exclude_checksum() {
+        let npub = "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu";
+        let options = MatchOptions { exclude_checksum: true, ..MatchOptions::default() };
+        assert_eq!(options.body(npub), Some("zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumyt"));
+        assert_eq!(options.body_len(), 51);
+        
+        let pattern = Pattern::new("umyt".to_string(), MatchType::Suffix, options).unwrap();
+        assert!(pattern.matches(npub));
+    }
+    
+    #[test]
+    fn test_skip() {
+        let npub = "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu";
+        let options = |skip| MatchOptions { skip, ..MatchOptions::default() };
+        assert_eq!(options(1).body(npub), Some("utzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu"));
+        assert_eq!(options(1).body_len(), 57);
+        assert_eq!(options(58).body(npub), Some(""));
+        assert_eq!(options(100).body(npub), Some(""));
+        assert_eq!(options(100).body_len(), 0);
+        
+        let pattern = |value: &str, match_type, skip| {
+            Pattern::new(value.to_string(), match_type, options(skip)).unwrap()
+        };
+        assert!(pattern("utz", MatchType::Prefix, 1).matches(npub));
+        assert!(!pattern("zut", MatchType::Prefix, 1).matches(npub));
+        assert!(!pattern("zut", MatchType::Contains, 1).matches(npub));
+        assert!(pattern("x7nu", MatchType::Suffix, 1).matches(npub));
+        assert!(pattern("0:t", MatchType::Position, 2).matches(npub));
+        assert!(pattern("^tze", MatchType::Regex, 2).matches(npub));
+        assert_eq!(pattern("eys", MatchType::Contains, 2).matched_slice(npub), Some((2, "eys")));
+        
+        // Skipping the whole body leaves nothing to match
+        assert!(!pattern("u", MatchType::Contains, 58).matches(npub));
+        assert!(!pattern("u", MatchType::Contains, 100).matches(npub));
+        
+        let matcher = PatternMatcher::from_strings(
+            vec!["utz".to_string(), "zut".to_string()],
+            MatchType::Prefix,
+            options(1),
+        )
+        .unwrap();
+        let keypair = NostrKeyPair {
+            npub: npub.to_string(),
+            nsec: String::new(),
+            hex_pubkey: String::new(),
+            mnemonic: None,
+        };
+        assert_eq!(matcher.find_match(&keypair).map(|p| p.value), Some("utz".to_string()));
+    }
+    
+    #[test]
+    fn test_prefix_trie() {
+        let mut trie = PrefixTrie::new(true);
+        trie.insert("sat", 2);
+        trie.insert("sa", 3);
+        trie.insert("satoshi", 0);
+        trie.insert("zap", 1);
+        
+        assert_eq!(trie.find("satoshi42", |_| false), Some(0));
+        assert_eq!(trie.find("SATx", |_| false), Some(2));
+        assert_eq!(trie.find("satx", |p| p == 2), Some(3));
+        assert_eq!(trie.find("s", |_| false), None);
+        assert_eq!(trie.find("xsat", |_| false), None);
+        
+        let mut sensitive = PrefixTrie::new(false);
+        sensitive.insert("Sat", 0);
+        assert_eq!(sensitive.find("sat", |_| false), None);
+        assert_eq!(sensitive.find("Sat", |_| false), Some(0));
+    }
+    
+    #[test]
+    fn test_repeat_matching() {
+        let pattern = Pattern::new("a:3".to_string(), MatchType::Repeat, MatchOptions::default()).unwrap();
+        assert!(pattern.matches("npub1aaaxyz"));
+        assert!(pattern.matches("npub1aaaaa"));
+        assert!(!pattern.matches("npub1aaxyz"));
+        assert!(!pattern.matches("npub1aa"));
+        assert_eq!(pattern.run_length("npub1aaaaaxy"), Some(5));
+        
+        let longer = Pattern::new("a:5".to_string(), MatchType::Repeat, MatchOptions::default()).unwrap();
+        assert!(pattern.covers(&longer));
+        assert!(!longer.covers(&pattern));
+        
+        assert!(parse_repeat("a:0").is_err());
+        assert!(parse_repeat("aa:3").is_err());
+        assert!(parse_repeat("a").is_err());
+    }
+    
+    #[test]
+    fn test_boundary_matching() {
+        let bounded = MatchOptions { boundary: Some('0'), ..MatchOptions::default() };
+        let pattern = Pattern::new("sat".to_string(), MatchType::Contains, bounded.clone()).unwrap();
+        assert!(pattern.matches("npub1xx0sat0yy"));
+        assert!(!pattern.matches("npub1xxsat0yy"));
+        assert!(!pattern.matches("npub1xx0satyy"));
+        // The ends of the body count as boundaries
+        assert!(pattern.matches("npub1sat0yy"));
+        assert!(pattern.matches("npub1xx0sat"));
+        // A later occurrence can be the bounded one
+        assert_eq!(pattern.matched_slice("npub1satx0sat0"), Some((5, "sat")));
+        // Overlapping occurrences are all tried
+        let overlap = Pattern::new("a0a".to_string(), MatchType::Contains, bounded.clone()).unwrap();
+        assert!(overlap.matches("npub1xa0a0a0"));
+        
+        // Shorter bounded patterns don't cover longer ones, and the matcher
+        // checks the neighbours rather than using its automaton
+        let longer = Pattern::new("sats".to_string(), MatchType::Contains, bounded.clone()).unwrap();
+        assert!(!pattern.covers(&longer));
+        let matcher = PatternMatcher::from_strings(vec!["sat".to_string()], MatchType::Contains, bounded.clone()).unwrap();
+        assert_eq!(matcher.unindexed(), 1);
+        
+        // Other match types ignore the boundary, contains wildcards reject it
+        let prefix = Pattern::new("sat".to_string(), MatchType::Prefix, bounded.clone()).unwrap();
+        assert!(prefix.matches("npub1satx"));
+        assert!(Pattern::new("s?t".to_string(), MatchType::Contains, bounded).is_err());
+    }
+    
+    #[test]
+    fn test_charclass_matching() {
+        let alpha = Pattern::new("alpha:4".to_string(), MatchType::Charclass, MatchOptions::default()).unwrap();
+        assert!(alpha.matches("npub1qwer7x"));
+        assert!(!alpha.matches("npub1qwe7rx"));
+        assert!(!alpha.matches("npub1qw"));
+        assert_eq!(alpha.run_length("npub1qwerty02"), Some(6));
+        assert_eq!(alpha.matched_slice("npub1qwerty02"), Some((0, "qwerty")));
+        
+        let numeric = Pattern::new("NUMERIC:3".to_string(), MatchType::Charclass, MatchOptions::default()).unwrap();
+        assert!(numeric.matches("npub1203q"));
+        assert!(!numeric.matches("npub1qwer"));
+        
+        let longer = Pattern::new("alpha:6".to_string(), MatchType::Charclass, MatchOptions::default()).unwrap();
+        assert!(alpha.covers(&longer));
+        assert!(!longer.covers(&alpha));
+        assert!(!numeric.covers(&longer));
+        
+        assert_eq!(parse_charclass("numeric:5").unwrap(), (CharKind::Numeric, 5));
+        assert!(parse_charclass("alpha:0").is_err());
+        assert!(parse_charclass("alnum:3").is_err());
+        assert!(parse_charclass("alpha").is_err());
+    }
+    
+    #[test]
+    fn test_bookend_matching() {
+        let pattern = Pattern::new("SAT...shi".to_string(), MatchType::Bookend, MatchOptions::default()).unwrap();
+        assert!(pattern.matches("npub1satxxshi"));
+        assert!(pattern.matches("npub1satshi"));
+        assert!(!pattern.matches("npub1satxxshy"));
+        assert!(!pattern.matches("npub1xsatshi"));
+        // The two ends can't share characters
+        let overlapping = Pattern::new("sat...ats".to_string(), MatchType::Bookend, MatchOptions::default()).unwrap();
+        assert!(!overlapping.matches("npub1sats"));
+        assert!(overlapping.matches("npub1satats"));
+        
+        assert_eq!(pattern.matched_slice("npub1satxxshi"), None);
+        assert_eq!(pattern.matched_spans("npub1satxxshi"), vec![0..3, 5..8]);
+        assert!(pattern.matched_spans("npub1satxxshy").is_empty());
+        let skipped = MatchOptions { skip: 1, ..MatchOptions::default() };
+        let skipped = Pattern::new("sat...shi".to_string(), MatchType::Bookend, skipped).unwrap();
+        assert_eq!(skipped.matched_spans("npub1xsatshi"), vec![1..4, 4..7]);
+        
+        let longer = Pattern::new("sato...oshi".to_string(), MatchType::Bookend, MatchOptions::default()).unwrap();
+        assert!(pattern.covers(&longer));
+        assert!(!longer.covers(&pattern));
+        
+        assert_eq!(parse_bookend("sat...shi").unwrap(), ("sat".to_string(), "shi".to_string()));
+        assert!(parse_bookend("satshi").is_err());
+        assert!(parse_bookend("...shi").is_err());
+        assert!(parse_bookend("sat...").is_err());
+    }
+    
+    #[test]
+    fn test_matched_slice() {
+        let contains = Pattern::new("SAT".to_string(), MatchType::Contains, MatchOptions::default()).unwrap();
+        assert_eq!(contains.matched_slice("npub1xxSaTyy"), Some((2, "SaT")));
+        assert_eq!(contains.matched_slice("npub1xxyy"), None);
+        
+        let suffix = Pattern::new("end".to_string(), MatchType::Suffix, MatchOptions::default()).unwrap();
+        assert_eq!(suffix.matched_slice("npub1abcEND"), Some((3, "END")));
+        
+        let regex = Pattern::new("^a+".to_string(), MatchType::Regex, MatchOptions::default()).unwrap();
+        assert_eq!(regex.matched_slice("npub1AaAx"), Some((0, "AaA")));
+        
+        let repeat = Pattern::new("a:2".to_string(), MatchType::Repeat, MatchOptions::default()).unwrap();
+        assert_eq!(repeat.matched_slice("npub1aaax"), Some((0, "aaa")));
+        
+        // Spans count skipped characters, like the body index users see
+        let skip = MatchOptions { skip: 1, ..MatchOptions::default() };
+        let skipped = Pattern::new("sat".to_string(), MatchType::Contains, skip).unwrap();
+        assert_eq!(skipped.matched_slice("npub1xxsat"), Some((1, "sat")));
+        assert_eq!(skipped.matched_span("npub1xxsat"), Some(2..5));
+        
+        let keypair = NostrKeyPair::generate().unwrap();
+        let body = &keypair.npub[5..];
+        let matcher = PatternMatcher::from_strings(
+            vec![body[20..24].to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let (pattern, span) = matcher.find_match_span(&keypair).unwrap();
+        let span = span.unwrap();
+        assert_eq!(&body[span.clone()], pattern.value);
+        assert!(span.start <= 20);
+        
+        let position = PatternMatcher::from_strings(
+            vec![format!("0:{}", &body[..1])],
+            MatchType::Position,
+            MatchOptions::default(),
+        ).unwrap();
+        assert_eq!(position.find_match_span(&keypair).unwrap().1, None);
+    }
+    
+    #[test]
+    fn test_all_match() {
+        let keypair = NostrKeyPair::generate().unwrap();
+        let body = &keypair.npub[5..];
+        let matcher = |values: Vec<String>| {
+            PatternMatcher::from_strings(values, MatchType::Contains, MatchOptions::default()).unwrap()
+        };
+        
+        let both = matcher(vec![body[3..6].to_string(), body[40..43].to_string()]);
+        assert!(both.all_match(&keypair));
+        assert!(both.find_match(&keypair).is_some());
+        
+        // Any one missing pattern rejects the key, though it still matches
+        // the rest
+        let one_missing = matcher(vec![body[3..6].to_string(), "!!".to_string()]);
+        assert!(!one_missing.all_match(&keypair));
+        assert!(one_missing.find_match(&keypair).is_some());
+        
+        assert!(!matcher(Vec::new()).all_match(&keypair));
+    }
+    
+    #[test]
+    fn test_either_matching() {
+        let pattern = Pattern::new("sat".to_string(), MatchType::Either, MatchOptions::default()).unwrap();
+        assert_eq!(pattern.matched_type("npub1satxyz"), Some(MatchType::Prefix));
+        assert_eq!(pattern.matched_type("npub1xyzsat"), Some(MatchType::Suffix));
+        assert_eq!(pattern.matched_type("npub1xsatx"), None);
+        assert_eq!(pattern.matched_slice("npub1xyzsat"), Some((3, "sat")));
+        
+        let longer = Pattern::new("satsat".to_string(), MatchType::Either, MatchOptions::default()).unwrap();
+        let one_ended = Pattern::new("sats".to_string(), MatchType::Either, MatchOptions::default()).unwrap();
+        assert!(pattern.covers(&longer));
+        assert!(!pattern.covers(&one_ended));
+    }
+    
+    #[test]
+    fn test_wildcard_matching() {
+        let pattern = |value: &str, match_type| {
+            Pattern::new(value.to_string(), match_type, MatchOptions::default()).unwrap()
+        };
+        assert!(pattern("sat?shi", MatchType::Prefix).matches("npub1satoshixyz"));
+        assert!(pattern("sat?shi", MatchType::Prefix).matches("npub1SAT0SHIxyz"));
+        assert!(!pattern("sat?shi", MatchType::Prefix).matches("npub1satshixyz"));
+        assert!(!pattern("sat?shi", MatchType::Prefix).matches("npub1xsat0shi"));
+        assert!(pattern("sat*dev", MatchType::Prefix).matches("npub1satxxxdevxx"));
+        assert!(pattern("*dev", MatchType::Prefix).matches("npub1xxdev"));
+        assert!(pattern("x?z", MatchType::Suffix).matches("npub1aaxyz"));
+        assert!(!pattern("x?z", MatchType::Suffix).matches("npub1xyza"));
+        assert!(pattern("a??d", MatchType::Contains).matches("npub1xxabcdxx"));
+        assert_eq!(pattern("a?c*", MatchType::Contains).matched_slice("npub1xxABCdxx"), Some((2, "ABC")));
+        assert_eq!(pattern("sat*", MatchType::Prefix).matched_slice("npub1satxx"), Some((0, "sat")));
+        
+        let either = pattern("s?t", MatchType::Either);
+        assert_eq!(either.matched_type("npub1sxtyyy"), Some(MatchType::Prefix));
+        assert_eq!(either.matched_type("npub1yyysxt"), Some(MatchType::Suffix));
+        
+        // Wildcards are literal characters to regex, position and repeat patterns
+        assert!(!pattern("a?", MatchType::Regex).has_wildcards());
+        
+        // Wildcard patterns go through the fallback but keep their listed order
+        let keypair = NostrKeyPair::generate().unwrap();
+        let body = &keypair.npub[5..];
+        let matcher = PatternMatcher::from_strings(
+            vec![
+                format!("{}?{}", &body[..1], &body[2..3]),
+                body[..2].to_string(),
+            ],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        assert_eq!(matcher.unindexed(), 1);
+        assert_eq!(matcher.find_match_index(&keypair, |_| false), Some(0));
+        assert_eq!(matcher.find_match_index(&keypair, |index| index == 0), Some(1));
+    }
+    
+    #[test]
+    fn test_fuzzy_chars() {
+        let classes: CharClasses = "uv, s5\ng9q".parse().unwrap();
+        assert_eq!(classes.classes(), ["uv", "s5", "g9q"]);
+        assert_eq!(classes.fold_str("v5q7"), "usg7");
+        assert!("uv,vw".parse::<CharClasses>().is_err());
+        assert!("u".parse::<CharClasses>().is_err());
+        assert!("uV".parse::<CharClasses>().is_err());
+        assert!(" , ".parse::<CharClasses>().is_err());
+        
+        let options = MatchOptions { fuzzy: Some(CharClasses::lookalikes()), ..MatchOptions::default() };
+        let pattern = |value: &str, match_type| Pattern::new(value.to_string(), match_type, options.clone()).unwrap();
+        assert_eq!(pattern("vvs", MatchType::Prefix).value, "vvs");
+        assert!(pattern("vvs", MatchType::Prefix).matches("npub1uv5xyz"));
+        assert!(!pattern("vvs", MatchType::Prefix).matches("npub1uvaxyz"));
+        assert!(pattern("z2", MatchType::Suffix).matches("npub1xyz22"));
+        assert_eq!(pattern("g9", MatchType::Contains).matched_slice("npub1xxqgxx"), Some((2, "qg")));
+        
+        // Regex patterns see the key as it is
+        assert!(!pattern("^vv", MatchType::Regex).matches("npub1uuxyz"));
+        
+        // The trie and the automaton fold keys the same way
+        let keypair = NostrKeyPair::generate().unwrap();
+        let lookalike = |c: char| match c {
+            'u' => 'v',
+            'v' => 'u',
+            c => c,
+        };
+        let body: String = keypair.npub[5..].chars().map(lookalike).collect();
+        for match_type in [MatchType::Prefix, MatchType::Contains] {
+            let matcher = PatternMatcher::from_strings(vec![body[..8].to_string()], match_type, options.clone()).unwrap();
+            assert_eq!(matcher.find_match_index(&keypair, |_| false), Some(0));
+        }
+    }
+    
+    #[test]
+    fn test_speakable() {
+        let options = MatchOptions { fuzzy: Some(CharClasses::soundalikes()), ..MatchOptions::default() };
+        let pattern = |value: &str| Pattern::new(value.to_string(), MatchType::Prefix, options.clone()).unwrap();
+        // "mad" read aloud could be heard as "n", "a", "t"
+        assert!(pattern("mad").matches("npub1natxyz"));
+        assert!(pattern("fee").matches("npub1xeexyz"));
+        assert!(!pattern("mad").matches("npub1nagxyz"));
+        
+        // Overlapping groups are joined, so each character is in one group
+        let merged = CharClasses::lookalikes().merge(&CharClasses::soundalikes());
+        for c in "qpzry9x8gf2tvdw0s3jn54khce6mua7l".chars() {
+            assert!(merged.classes().iter().filter(|class| class.contains(c)).count() <= 1, "{}", c);
+        }
+        // u/v from one set and q/u/2 from the other end up together
+        assert_eq!(merged.fold('v'), merged.fold('2'));
+        assert_eq!(merged.fold('5'), merged.fold('x'));
+        assert_ne!(merged.fold('m'), merged.fold('s'));
+    }
+}
@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::{Result, VanityError};
+use crate::generator::NostrKeyPair;
+
+// Frames larger than this are rejected rather than allocated, so a stray
+// connection can't make the coordinator reserve gigabytes
+pub const MAX_FRAME_LEN: u32 = 1 << 20;
+
+// Everything a worker sends its coordinator. On the wire each message is a
+// 4-byte big-endian length followed by that many bytes of JSON, e.g.
+// `{"type":"progress","attempts":120000,"keys_per_sec":98000.0}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    // First message on a connection
+    Hello { threads: usize, patterns: Vec<String> },
+    // Running totals for this worker, not increments
+    Progress { attempts: u64, keys_per_sec: f64 },
+    Match { keypair: NostrKeyPair, pattern: String, attempts: u64 },
+    // Sent when the worker's search ends, with its final total
+    Done { attempts: u64 },
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> Result<()> {
+    let json = serde_json::to_vec(message)?;
+    let len = u32::try_from(json.len()).ok().filter(|&len| len <= MAX_FRAME_LEN);
+    let Some(len) = len else {
+        return Err(VanityError::Protocol(format!("message of {} bytes is too large to send", json.len())));
+    };
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&json)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Reads the next message, or None once the peer has closed the connection
+// between messages
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Option<Message>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(VanityError::Protocol(format!(
+            "message of {} bytes exceeds the {} byte limit",
+            len,
+            MAX_FRAME_LEN
+        )));
+    }
+    
+    let mut json = vec![0u8; len as usize];
+    reader.read_exact(&mut json)?;
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
+// A worker's connection to its coordinator. Sends are serialized so progress
+// reports and matches from different threads never interleave. After the
+// first failed send the reporter goes quiet, leaving the local search to
+// carry on by itself.
+pub struct Reporter {
+    stream: Mutex<TcpStream>,
+    failed: AtomicBool,
+}
+
+impl Reporter {
+    pub fn connect<A: ToSocketAddrs>(addr: A, hello: &Message) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        write_message(&mut stream, hello)?;
+        Ok(Reporter {
+            stream: Mutex::new(stream),
+            failed: AtomicBool::new(false),
+        })
+    }
+    
+    // Returns the error from the send that broke the connection, once
+    pub fn send(&self, message: &Message) -> Result<()> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut stream = self.stream.lock().unwrap();
+        write_message(&mut *stream, message).inspect_err(|_| {
+            self.failed.store(true, Ordering::Relaxed);
+        })
+    }
+    
+    pub fn is_connected(&self) -> bool {
+        !self.failed.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStats {
+    pub threads: usize,
+    pub patterns: Vec<String>,
+    pub attempts: u64,
+    pub keys_per_sec: f64,
+    pub matches: u64,
+    pub connected: bool,
+}
+
+// What the coordinator knows about its workers. Attempts from workers that
+// have disconnected still count towards the totals.
+#[derive(Debug, Default)]
+pub struct Coordinator {
+    workers: HashMap<SocketAddr, WorkerStats>,
+    seen: HashSet<String>,
+    matches: u64,
+}
+
+impl Coordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn connect(&mut self, worker: SocketAddr) {
+        self.workers.entry(worker).or_default().connected = true;
+    }
+    
+    pub fn disconnect(&mut self, worker: SocketAddr) {
+        if let Some(stats) = self.workers.get_mut(&worker) {
+            stats.connected = false;
+            stats.keys_per_sec = 0.0;
+        }
+    }
+    
+    // Applies a message from `worker`. Returns true for a match no worker
+    // has reported before; repeats of a known key are dropped.
+    pub fn record(&mut self, worker: SocketAddr, message: &Message) -> bool {
+        let stats = self.workers.entry(worker).or_default();
+        match message {
+            Message::Hello { threads, patterns } => {
+                stats.threads = *threads;
+                stats.patterns = patterns.clone();
+            }
+            Message::Progress { attempts, keys_per_sec } => {
+                stats.attempts = stats.attempts.max(*attempts);
+                stats.keys_per_sec = *keys_per_sec;
+            }
+            Message::Match { keypair, .. } => {
+                if !self.seen.insert(keypair.npub.clone()) {
+                    return false;
+                }
+                stats.matches += 1;
+                self.matches += 1;
+                return true;
+            }
+            Message::Done { attempts } => {
+                stats.attempts = stats.attempts.max(*attempts);
+                stats.keys_per_sec = 0.0;
+            }
+        }
+        false
+    }
+    
+    pub fn workers(&self) -> &HashMap<SocketAddr, WorkerStats> {
+        &self.workers
+    }
+    
+    pub fn connected(&self) -> usize {
+        self.workers.values().filter(|stats| stats.connected).count()
+    }
+    
+    pub fn attempts(&self) -> u64 {
+        self.workers.values().map(|stats| stats.attempts).sum()
+    }
+    
+    // Combined rate of the workers still connected
+    pub fn keys_per_sec(&self) -> f64 {
+        self.workers
+            .values()
+            .filter(|stats| stats.connected)
+            .map(|stats| stats.keys_per_sec)
+            .sum()
+    }
+    
+    pub fn matches(&self) -> u64 {
+        self.matches
+    }
+}
+
+// Longest a metrics scrape may take to send its request, and how much of
+// the request is read at most. The request itself is ignored past its path.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LEN: u64 = 8192;
+
+// What --metrics serves, read fresh for every scrape
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    pub attempts: u64,
+    pub matches: u64,
+    pub keys_per_sec: f64,
+}
+
+impl Metrics {
+    // The Prometheus text format
+    pub fn render(&self) -> String {
+        format!(
+            "# HELP nostr_vanity_attempts_total Keys generated and checked against the patterns.\n\
+            # TYPE nostr_vanity_attempts_total counter\n\
+            nostr_vanity_attempts_total {}\n\
+            # HELP nostr_vanity_matches_total Matching keys found.\n\
+            # TYPE nostr_vanity_matches_total counter\n\
+            nostr_vanity_matches_total {}\n\
+            # HELP nostr_vanity_keys_per_second Keys checked per second.\n\
+            # TYPE nostr_vanity_keys_per_second gauge\n\
+            nostr_vanity_keys_per_second {:.1}\n",
+            self.attempts,
+            self.matches,
+            self.keys_per_sec
+        )
+    }
+}
+
+// Answers HTTP GET /metrics on `listener` with a snapshot from `metrics`,
+// and anything else with a 404, for as long as the listener lives. Scrapes
+// are answered one at a time on the calling thread.
+pub fn serve_metrics<F: Fn() -> Metrics>(listener: TcpListener, metrics: F) {
+    for stream in listener.incoming().flatten() {
+        // A scrape that fails only loses that scrape
+        let _ = answer_scrape(&stream, &metrics);
+    }
+}
+
+fn answer_scrape<F: Fn() -> Metrics>(mut stream: &TcpStream, metrics: &F) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_LEN));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Headers are read so the client isn't cut off mid-request, then ignored
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => ("200 OK", metrics().render()),
+        (Some("GET"), Some(_)) => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    
+    #[test]
+    fn test_message_framing() {
+        let messages = [
+            Message::Hello { threads: 8, patterns: vec!["sat".to_string()] },
+            Message::Progress { attempts: 1000, keys_per_sec: 500.0 },
+            Message::Done { attempts: 2000 },
+        ];
+        let mut buffer = Vec::new();
+        for message in &messages {
+            write_message(&mut buffer, message).unwrap();
+        }
+        let first_len = u32::from_be_bytes(buffer[..4].try_into().unwrap()) as usize;
+        assert_eq!(&buffer[4..4 + first_len], br#"{"type":"hello","threads":8,"patterns":["sat"]}"#);
+        
+        let mut reader = Cursor::new(buffer);
+        for _ in &messages {
+            assert!(read_message(&mut reader).unwrap().is_some());
+        }
+        assert!(read_message(&mut reader).unwrap().is_none());
+        
+        // A truncated frame is an error rather than a clean close
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &messages[1]).unwrap();
+        buffer.truncate(buffer.len() - 1);
+        assert!(read_message(&mut Cursor::new(buffer)).is_err());
+        
+        let oversized = (MAX_FRAME_LEN + 1).to_be_bytes().to_vec();
+        assert!(read_message(&mut Cursor::new(oversized)).is_err());
+    }
+    
+    #[test]
+    fn test_match_round_trip() {
+        let keypair = NostrKeyPair::generate().unwrap();
+        let mut buffer = Vec::new();
+        let message = Message::Match { keypair: keypair.clone(), pattern: "sat".to_string(), attempts: 7 };
+        write_message(&mut buffer, &message).unwrap();
+        
+        match read_message(&mut Cursor::new(buffer)).unwrap() {
+            Some(Message::Match { keypair: received, pattern, attempts }) => {
+                assert_eq!(received.npub, keypair.npub);
+                assert_eq!(received.nsec, keypair.nsec);
+                assert_eq!(received.mnemonic, None);
+                assert_eq!(pattern, "sat");
+                assert_eq!(attempts, 7);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_coordinator() {
+        let first: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+        let mut coordinator = Coordinator::new();
+        coordinator.connect(first);
+        coordinator.connect(second);
+        
+        coordinator.record(first, &Message::Progress { attempts: 100, keys_per_sec: 10.0 });
+        coordinator.record(second, &Message::Progress { attempts: 50, keys_per_sec: 5.0 });
+        coordinator.record(first, &Message::Progress { attempts: 300, keys_per_sec: 20.0 });
+        assert_eq!(coordinator.attempts(), 350);
+        assert_eq!(coordinator.keys_per_sec(), 25.0);
+        
+        // The same key from two workers is only reported once
+        let keypair = NostrKeyPair::generate().unwrap();
+        let found = |keypair: &NostrKeyPair| Message::Match {
+            keypair: keypair.clone(),
+            pattern: "q".to_string(),
+            attempts: 1,
+        };
+        assert!(coordinator.record(first, &found(&keypair)));
+        assert!(!coordinator.record(second, &found(&keypair)));
+        assert!(coordinator.record(second, &found(&NostrKeyPair::generate().unwrap())));
+        assert_eq!(coordinator.matches(), 2);
+        
+        // A disconnected worker's attempts still count, its rate doesn't
+        coordinator.disconnect(second);
+        assert_eq!(coordinator.connected(), 1);
+        assert_eq!(coordinator.attempts(), 350);
+        assert_eq!(coordinator.keys_per_sec(), 20.0);
+    }
+    
+    #[test]
+    fn test_metrics_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            serve_metrics(listener, || Metrics { attempts: 1200, matches: 2, keys_per_sec: 600.0 })
+        });
+        let scrape = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        
+        let response = scrape("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("\nnostr_vanity_attempts_total 1200\n"));
+        assert!(response.contains("\nnostr_vanity_matches_total 2\n"));
+        assert!(response.contains("# TYPE nostr_vanity_keys_per_second gauge\nnostr_vanity_keys_per_second 600.0\n"));
+        
+        assert!(scrape("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+        assert!(scrape("POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+    }
+}
@@ -0,0 +1,1223 @@
+use crossbeam_channel::{unbounded, Receiver};
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::{Result, VanityError};
+use crate::generator::{new_rng, seeded_rng, CandidateKey, KeySource, MnemonicLength, RngSource};
+use crate::matcher::{Pattern, PatternMatcher};
+use crate::utils::{rarity_bits, Checkpoint, VanityResult};
+
+// Keys per thread in each batch when no batch size is configured
+const BATCH_KEYS_PER_THREAD: usize = 1000;
+// How often a paused search checks whether it has been resumed or stopped
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+
+// A counter on its own cache line, so threads bumping neighbouring counters
+// don't contend for the same line
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct PaddedCounter(AtomicU64);
+
+// Called with the total attempts so far and the overall keys/sec
+pub type ProgressCallback = Arc<dyn Fn(u64, f64) + Send + Sync>;
+
+// Decides whether a candidate key is a match, in place of the patterns
+pub type KeyPredicate = Arc<dyn Fn(&CandidateKey) -> bool + Send + Sync>;
+
+// The label results of a `with_matcher` search carry as their pattern
+pub const CUSTOM_PATTERN_LABEL: &str = "custom";
+
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub threads: usize,
+    pub continuous: bool,
+    pub count: Option<u64>,
+    pub max_attempts: Option<u64>,
+    pub timeout: Option<Duration>,
+    // Keys generated between collecting matches and updating quotas.
+    // Defaults to 1000 per thread.
+    pub batch_size: Option<usize>,
+    pub seed: Option<u64>,
+    pub rng: RngSource,
+    pub mnemonic: bool,
+    // Phrase length when `mnemonic` is set
+    pub mnemonic_length: MnemonicLength,
+    // Only accept keys matching every pattern. Each match then counts
+    // towards every pattern's hits and quota.
+    pub match_all: bool,
+    // Experimental: each worker steps from one random key to the next secret
+    // up rather than drawing every key, see `CandidateKey::next`. Not
+    // available with `mnemonic`.
+    pub sequential: bool,
+    // Percentage of the time, 1 to 100, the worker threads may spend
+    // generating keys. After each batch they sit idle in proportion to how
+    // long it took, which lowers power draw and heat at the cost of speed.
+    pub max_cpu: Option<u8>,
+    pub relays: Vec<String>,
+    pub resume: Checkpoint,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            threads: num_cpus::get(),
+            continuous: false,
+            count: None,
+            max_attempts: None,
+            timeout: None,
+            batch_size: None,
+            seed: None,
+            rng: RngSource::default(),
+            mnemonic: false,
+            mnemonic_length: MnemonicLength::default(),
+            match_all: false,
+            sequential: false,
+            max_cpu: None,
+            relays: Vec::new(),
+            resume: Checkpoint::default(),
+        }
+    }
+}
+
+pub struct VanitySearch {
+    matcher: Arc<PatternMatcher>,
+    config: SearchConfig,
+    found: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    attempts: Arc<AtomicU64>,
+    // Matches reported so far, by pattern index
+    hits: Arc<Vec<AtomicU64>>,
+    // Results sent, one per key however many patterns it matched
+    matches: Arc<AtomicU64>,
+    // Keys generated by each worker thread in this run, by rayon thread index
+    thread_attempts: Arc<Vec<PaddedCounter>>,
+    started: OnceLock<Instant>,
+    progress: Option<(Duration, ProgressCallback)>,
+    exclude: Option<Arc<PatternMatcher>>,
+    denylist: Option<Arc<HashSet<String>>>,
+    predicate: Option<KeyPredicate>,
+    pause: Arc<Pause>,
+    // Key draws redone because they gave an invalid key, and the error that
+    // ended the search if key generation failed outright
+    key_retries: Arc<AtomicU64>,
+    error: Arc<Mutex<Option<VanityError>>>,
+}
+
+// Pause state shared with the search thread. Time spent paused is tallied so
+// run time, rates and the timeout only count time spent searching.
+#[derive(Debug, Default)]
+struct Pause {
+    paused: AtomicBool,
+    since: Mutex<Option<Instant>>,
+    total_nanos: AtomicU64,
+}
+
+impl Pause {
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+    
+    fn pause(&self) {
+        let mut since = self.since.lock().unwrap();
+        if since.is_none() {
+            *since = Some(Instant::now());
+            self.paused.store(true, Ordering::Relaxed);
+        }
+    }
+    
+    fn resume(&self) {
+        let mut since = self.since.lock().unwrap();
+        if let Some(since) = since.take() {
+            self.total_nanos.fetch_add(since.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            self.paused.store(false, Ordering::Relaxed);
+        }
+    }
+    
+    // Time since `started`, less any time spent paused. The lock is only
+    // taken while paused, when no keys are being generated.
+    fn active_since(&self, started: Instant) -> Duration {
+        let mut paused = Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed));
+        if self.is_paused() {
+            if let Some(since) = *self.since.lock().unwrap() {
+                paused += since.elapsed();
+            }
+        }
+        started.elapsed().saturating_sub(paused)
+    }
+}
+
+impl VanitySearch {
+    pub fn new(matcher: PatternMatcher, mut config: SearchConfig) -> Self {
+        // Zero threads means one per core. It's settled here rather than left
+        // to rayon, so the pool and the per-thread counters agree on the count.
+        if config.threads == 0 {
+            config.threads = num_cpus::get();
+        }
+        let attempts = config.resume.attempts;
+        let hits = matcher.patterns().iter().map(|_| AtomicU64::new(0)).collect();
+        let thread_attempts = (0..config.threads).map(|_| PaddedCounter::default()).collect();
+        VanitySearch {
+            matcher: Arc::new(matcher),
+            config,
+            found: Arc::new(AtomicBool::new(false)),
+            stopped: Arc::new(AtomicBool::new(false)),
+            attempts: Arc::new(AtomicU64::new(attempts)),
+            hits: Arc::new(hits),
+            matches: Arc::new(AtomicU64::new(0)),
+            thread_attempts: Arc::new(thread_attempts),
+            started: OnceLock::new(),
+            progress: None,
+            exclude: None,
+            denylist: None,
+            predicate: None,
+            pause: Arc::new(Pause::default()),
+            key_retries: Arc::new(AtomicU64::new(0)),
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+    
+    // Rejects keys that match the search but also match any pattern of
+    // `exclude`, e.g. to keep unwanted words out of an otherwise good key
+    pub fn with_exclude(mut self, exclude: PatternMatcher) -> Self {
+        self.exclude = Some(Arc::new(exclude));
+        self
+    }
+    
+    // Rejects keys whose npub is in `denylist`, such as keys already handed
+    // out, however well they match
+    pub fn with_denylist(mut self, denylist: HashSet<String>) -> Self {
+        self.denylist = Some(Arc::new(denylist));
+        self
+    }
+    
+    // Accepts exactly the keys `predicate` returns true for, in place of the
+    // patterns, for acceptance rules patterns can't express. It runs on
+    // every worker thread for every key, before the nsec and hex key are
+    // encoded; `KeyText` encodes them on demand. Results report a single
+    // `custom` pattern, which `count` and `hits` refer to, and `exclude`
+    // still applies.
+    pub fn with_matcher<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&CandidateKey) -> bool + Send + Sync + 'static,
+    {
+        self.matcher = Arc::new(PatternMatcher::new(vec![Pattern::custom(CUSTOM_PATTERN_LABEL)]));
+        self.hits = Arc::new(vec![AtomicU64::new(0)]);
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+    
+    // Reports progress to `callback` at most every `interval`, checked
+    // between batches of keys, and once more when the search ends. Lets
+    // embedders draw their own progress instead of the CLI spinner.
+    pub fn on_progress<F>(mut self, interval: Duration, callback: F) -> Self
+    where
+        F: Fn(u64, f64) + Send + Sync + 'static,
+    {
+        self.progress = Some((interval, Arc::new(callback)));
+        self
+    }
+    
+    pub fn matcher(&self) -> &PatternMatcher {
+        &self.matcher
+    }
+    
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+    
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+    
+    // Keys found so far in this run
+    pub fn matches(&self) -> u64 {
+        self.matches.load(Ordering::Relaxed)
+    }
+    
+    // Matches reported so far for each pattern, in `matcher().patterns()` order
+    pub fn hits(&self) -> Vec<u64> {
+        self.hits.iter().map(|hits| hits.load(Ordering::Relaxed)).collect()
+    }
+    
+    // Keys generated by each worker thread during this run, which sum to the
+    // attempts since the search started. Uneven counts point at threads
+    // starved of CPU time.
+    pub fn thread_attempts(&self) -> Vec<u64> {
+        self.thread_attempts.iter().map(|counter| counter.0.load(Ordering::Relaxed)).collect()
+    }
+    
+    // Search time of this run alone, excluding time spent paused
+    pub fn run_elapsed(&self) -> Duration {
+        self.started.get().map(|&started| self.pause.active_since(started)).unwrap_or_default()
+    }
+    
+    // Total search time, including any time carried over from a checkpoint
+    // and excluding time spent paused
+    pub fn elapsed(&self) -> Duration {
+        let resumed = Duration::from_secs_f64(self.config.resume.elapsed_seconds);
+        resumed + self.run_elapsed()
+    }
+    
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            attempts: self.attempts(),
+            elapsed_seconds: self.elapsed().as_secs_f64(),
+        }
+    }
+    
+    // Key draws that gave an invalid key and were redone. A working random
+    // source all but never needs one, so a count above zero points at a
+    // failing source rather than bad luck.
+    pub fn key_retries(&self) -> u64 {
+        self.key_retries.load(Ordering::Relaxed)
+    }
+    
+    // The error that stopped the search when keys could no longer be
+    // generated, e.g. a random source that only gives invalid keys. Taken
+    // once; the search ends as if stopped.
+    pub fn take_error(&self) -> Option<VanityError> {
+        self.error.lock().unwrap().take()
+    }
+    
+    pub fn is_found(&self) -> bool {
+        self.found.load(Ordering::Relaxed)
+    }
+    
+    pub fn is_exhausted(&self) -> bool {
+        self.config.max_attempts.is_some_and(|max| self.attempts() >= max)
+    }
+    
+    // True once this run has gone on for longer than the configured timeout.
+    // Time carried over from a checkpoint or spent paused doesn't count
+    // towards it.
+    pub fn is_timed_out(&self) -> bool {
+        self.config.timeout.is_some_and(|timeout| {
+            self.started
+                .get()
+                .is_some_and(|&started| self.pause.active_since(started) >= timeout)
+        })
+    }
+    
+    pub fn is_done(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+    
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+    
+    // Holds the worker threads idle until `resume`. A batch in progress is
+    // cut short, and `stop` still ends a paused search.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+    
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+    
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+    
+    // Spawns the search on a dedicated rayon pool and returns the receiving
+    // end of the results channel. The channel closes once the search ends;
+    // `take_error` then says whether it ended because key generation failed.
+    pub fn run(&self) -> Result<Receiver<VanityResult>> {
+        let batch_size = self
+            .config
+            .batch_size
+            .unwrap_or(self.config.threads * BATCH_KEYS_PER_THREAD);
+        if batch_size == 0 {
+            return Err(VanityError::InvalidInput("batch size must be at least 1".to_string()));
+        }
+        if self.config.max_cpu.is_some_and(|percent| !(1..=100).contains(&percent)) {
+            return Err(VanityError::InvalidInput("CPU limit must be between 1 and 100 percent".to_string()));
+        }
+        if self.config.sequential && self.config.mnemonic {
+            return Err(VanityError::InvalidInput(
+                "sequential keys have no mnemonic, so they can't be combined with mnemonic generation".to_string(),
+            ));
+        }
+        // Fails here rather than in every worker if the source is unusable
+        new_rng(self.config.rng, self.config.seed, 0)?;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.config.threads)
+            .build()?;
+        let (tx, rx) = unbounded();
+        let start_time = *self.started.get_or_init(Instant::now);
+        let resumed = Duration::from_secs_f64(self.config.resume.elapsed_seconds);
+        
+        let matcher = self.matcher.clone();
+        let found = self.found.clone();
+        let stopped = self.stopped.clone();
+        let attempts = self.attempts.clone();
+        let continuous = self.config.continuous;
+        let count = self.config.count;
+        let threads = self.config.threads;
+        let max_attempts = self.config.max_attempts;
+        let timeout = self.config.timeout;
+        let seed = self.config.seed;
+        // Seeded searches draw key `n` of the run from ChaCha stream `n`,
+        // numbered on from any attempts already made, so the keys and the
+        // match reported don't depend on threads or scheduling. Sequential
+        // walks keep a stream per worker.
+        let keyed_seed = seed.filter(|_| !self.config.sequential);
+        let mut next_key = self.attempts.load(Ordering::Relaxed);
+        let rng_source = self.config.rng;
+        let mnemonic = self.config.mnemonic.then_some(self.config.mnemonic_length);
+        let match_all = self.config.match_all;
+        let sequential = self.config.sequential;
+        let max_cpu = self.config.max_cpu.filter(|&percent| percent < 100);
+        let seeded = self.config.seed.is_some();
+        let relays = self.config.relays.clone();
+        let progress = self.progress.clone();
+        let exclude = self.exclude.clone();
+        let denylist = self.denylist.clone();
+        let predicate = self.predicate.clone();
+        let pause = self.pause.clone();
+        let key_retries = self.key_retries.clone();
+        let error = self.error.clone();
+        let stop_at_first_match = !continuous && count.is_none();
+        
+        // With a per-pattern quota, patterns that have met it are marked
+        // `saturated` so workers stop reporting them and later listed patterns
+        // get a chance.
+        let hits = self.hits.clone();
+        let matches = self.matches.clone();
+        let thread_attempts = self.thread_attempts.clone();
+        let saturated: Arc<Vec<AtomicBool>> =
+            Arc::new(hits.iter().map(|_| AtomicBool::new(false)).collect());
+        
+        std::thread::spawn(move || {
+            let report = |callback: &ProgressCallback| {
+                let total = attempts.load(Ordering::Relaxed);
+                let elapsed = (resumed + pause.active_since(start_time)).as_secs_f64();
+                callback(total, total as f64 / elapsed.max(f64::EPSILON));
+            };
+            let mut last_report = Instant::now();
+            
+            pool.install(|| loop {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                
+                if let Some((interval, callback)) = &progress {
+                    if last_report.elapsed() >= *interval {
+                        last_report = Instant::now();
+                        report(callback);
+                    }
+                }
+                
+                if pause.is_paused() {
+                    std::thread::sleep(PAUSE_POLL);
+                    continue;
+                }
+                
+                // Each match carries the attempt number and time at which it
+                // was found, rather than the counters when it is reported
+                let batch_start = Instant::now();
+                let first_key = next_key;
+                next_key += batch_size as u64;
+                // The lowest numbered seeded key to match in this batch. Keys
+                // after it needn't be generated, those before it still must.
+                let first_match = AtomicU64::new(u64::MAX);
+                let mut results: Vec<_> = (0..batch_size)
+                    .into_par_iter()
+                    .filter_map(|offset| {
+                        let key = first_key + offset as u64;
+                        if stopped.load(Ordering::Relaxed) || pause.is_paused() {
+                            return None;
+                        }
+                        if key > first_match.load(Ordering::Relaxed) {
+                            return None;
+                        }
+                        
+                        if max_attempts.is_some_and(|max| attempts.load(Ordering::Relaxed) >= max)
+                            || timeout.is_some_and(|timeout| pause.active_since(start_time) >= timeout)
+                        {
+                            stopped.store(true, Ordering::Relaxed);
+                            return None;
+                        }
+                        
+                        // Only keys actually generated count as attempts. A
+                        // source that can't give a valid key ends the search
+                        // rather than dropping keys unnoticed.
+                        let mut retries = 0;
+                        let candidate = match (sequential, keyed_seed) {
+                            (true, _) => next_in_walk(rng_source, seed, &mut retries),
+                            (false, Some(seed)) => seeded_candidate(seed, key, mnemonic, &mut retries),
+                            (false, None) => generate_candidate(rng_source, seed, mnemonic, &mut retries),
+                        };
+                        if retries > 0 {
+                            key_retries.fetch_add(retries, Ordering::Relaxed);
+                        }
+                        let candidate = match candidate {
+                            Ok(candidate) => candidate,
+                            Err(e) => {
+                                error.lock().unwrap().get_or_insert(e);
+                                stopped.store(true, Ordering::Relaxed);
+                                return None;
+                            }
+                        };
+                        let counted = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+                        let attempt = if keyed_seed.is_some() { key + 1 } else { counted };
+                        let thread = rayon::current_thread_index().unwrap_or(0);
+                        thread_attempts[thread].0.fetch_add(1, Ordering::Relaxed);
+                        
+                        // Matches of every pattern are reported as the first
+                        let index = if let Some(predicate) = &predicate {
+                            (!saturated[0].load(Ordering::Relaxed) && predicate(&candidate)).then_some(0)?
+                        } else if match_all {
+                            (!saturated[0].load(Ordering::Relaxed) && matcher.all_match(&candidate))
+                                .then_some(0)?
+                        } else {
+                            matcher.find_match_index(&candidate, |index| {
+                                saturated[index].load(Ordering::Relaxed)
+                            })?
+                        };
+                        if exclude
+                            .as_ref()
+                            .is_some_and(|exclude| exclude.find_match_index(&candidate, |_| false).is_some())
+                        {
+                            return None;
+                        }
+                        if denylist.as_ref().is_some_and(|denylist| denylist.contains(&candidate.npub)) {
+                            return None;
+                        }
+                        
+                        // Other patterns the key satisfies are reported with
+                        // the first rather than lost
+                        let later = match predicate.is_none() && !match_all {
+                            true => matcher.later_matches(&candidate, index, |index| {
+                                saturated[index].load(Ordering::Relaxed)
+                            }),
+                            false => Vec::new(),
+                        };
+                        
+                        // Keys reported from one walk would be a step count
+                        // apart, so each match starts a fresh one
+                        if sequential {
+                            end_walk();
+                        }
+                        
+                        // A pair whose nsec doesn't give back its npub would be
+                        // a key nobody controls, so it ends the search instead
+                        // of being reported
+                        let keypair = candidate.finalize();
+                        if let Err(e) = keypair.verify() {
+                            error.lock().unwrap().get_or_insert(e);
+                            stopped.store(true, Ordering::Relaxed);
+                            return None;
+                        }
+                        
+                        // A single match ends the search, so the rest of the
+                        // batch needn't generate any more keys. A seeded search
+                        // still checks the keys before this one, as the lowest
+                        // numbered match is the one reported.
+                        if stop_at_first_match {
+                            match keyed_seed {
+                                Some(_) => {
+                                    first_match.fetch_min(key, Ordering::Relaxed);
+                                }
+                                None => stopped.store(true, Ordering::Relaxed),
+                            }
+                        }
+                        let elapsed = pause.active_since(start_time);
+                        Some((attempt, elapsed, SystemTime::now(), keypair, index, later))
+                    })
+                    .collect();
+                results.sort_unstable_by_key(|&(attempt, ..)| attempt);
+                
+                for (attempt, elapsed, found_at, keypair, index, later) in results {
+                    let full = |index: usize| count.is_some_and(|quota| hits[index].load(Ordering::Relaxed) >= quota);
+                    if full(index) {
+                        continue;
+                    }
+                    // A key counts towards every pattern it's reported for
+                    let also: Vec<usize> = match match_all {
+                        true => (1..hits.len()).collect(),
+                        false => later.into_iter().filter(|&index| !full(index)).collect(),
+                    };
+                    for &index in std::iter::once(&index).chain(&also) {
+                        let pattern_hits = hits[index].fetch_add(1, Ordering::Relaxed) + 1;
+                        if count.is_some_and(|quota| pattern_hits >= quota) {
+                            saturated[index].store(true, Ordering::Relaxed);
+                        }
+                    }
+                    
+                    found.store(true, Ordering::Relaxed);
+                    let nprofile = if relays.is_empty() {
+                        None
+                    } else {
+                        keypair.nprofile(&relays).ok()
+                    };
+                    let reported: Vec<Pattern> = std::iter::once(index)
+                        .chain(also)
+                        .map(|index| matcher.patterns()[index].clone())
+                        .collect();
+                    let result = VanityResult {
+                        keypair,
+                        matched_pattern: reported[0].clone(),
+                        attempts: attempt,
+                        time_elapsed: resumed + elapsed,
+                        nprofile,
+                        found_at,
+                        threads,
+                        also_matched: reported[1..].to_vec(),
+                        seeded,
+                        rarity: rarity_bits(&reported),
+                    };
+                    
+                    let complete = !continuous
+                        && count.is_none_or(|quota| {
+                            hits.iter().all(|h| h.load(Ordering::Relaxed) >= quota)
+                        });
+                    if complete {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                    matches.fetch_add(1, Ordering::Relaxed);
+                    if tx.send(result).is_err() {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+                
+                // Idle for long enough that the batch just run is `max_cpu`
+                // percent of the time, waking early for a stop or the timeout
+                if let Some(percent) = max_cpu {
+                    let idle = batch_start.elapsed() * (100 - percent as u32) / percent as u32;
+                    let wake = Instant::now() + idle;
+                    let timed_out = || timeout.is_some_and(|timeout| pause.active_since(start_time) >= timeout);
+                    while !stopped.load(Ordering::Relaxed) && !timed_out() && Instant::now() < wake {
+                        std::thread::sleep(wake.saturating_duration_since(Instant::now()).min(PAUSE_POLL));
+                    }
+                }
+            });
+            
+            if let Some((_, callback)) = &progress {
+                report(callback);
+            }
+        });
+        
+        Ok(rx)
+    }
+    
+    // Like `run`, but yields results as an iterator. Dropping the iterator,
+    // e.g. after `take(n)`, stops the search instead of letting it run until
+    // the next match finds the channel closed.
+    pub fn iter(&self) -> Result<impl Iterator<Item = VanityResult>> {
+        Ok(Results {
+            rx: self.run()?,
+            stopped: self.stopped.clone(),
+        })
+    }
+}
+
+struct Results {
+    rx: Receiver<VanityResult>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Iterator for Results {
+    type Item = VanityResult;
+    
+    fn next(&mut self) -> Option<VanityResult> {
+        self.rx.recv().ok()
+    }
+}
+
+impl Drop for Results {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+// Generates keys with no patterns to match for `duration` and returns the
+// measured rate in keys/sec.
+pub fn benchmark(config: SearchConfig, duration: Duration) -> Result<f64> {
+    let search = VanitySearch::new(PatternMatcher::new(Vec::new()), config);
+    let start_time = Instant::now();
+    let rx = search.run()?;
+    
+    std::thread::sleep(duration);
+    search.stop();
+    for _ in rx {}
+    if let Some(e) = search.take_error() {
+        return Err(e);
+    }
+    
+    Ok(search.attempts() as f64 / start_time.elapsed().as_secs_f64())
+}
+
+thread_local! {
+    static WORKER_RNG: RefCell<Option<KeySource>> = const { RefCell::new(None) };
+    // The last key of this worker's sequential walk
+    static WORKER_WALK: RefCell<Option<CandidateKey>> = const { RefCell::new(None) };
+}
+
+// Each rayon worker keeps one generator for the life of the pool rather than
+// setting one up per key. For the chacha source it is seeded once from the
+// OS-backed thread RNG, or with a seed, as sequential walks use, every worker
+// draws from its own stream keyed by its thread index, so workers never
+// overlap.
+fn generate_candidate(
+    source: RngSource,
+    seed: Option<u64>,
+    mnemonic: Option<MnemonicLength>,
+    retries: &mut u64,
+) -> Result<CandidateKey> {
+    WORKER_RNG.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let rng = match cell.as_mut() {
+            Some(rng) => rng,
+            None => {
+                let stream = rayon::current_thread_index().unwrap_or(0) as u64;
+                cell.insert(new_rng(source, seed, stream)?)
+            }
+        };
+        
+        match mnemonic {
+            Some(length) => CandidateKey::generate_mnemonic_with_retries(rng.as_mut(), length, retries),
+            None => CandidateKey::generate_with_retries(rng.as_mut(), retries),
+        }
+    })
+}
+
+// Key `index` of a seeded search, the first draw of its own ChaCha stream
+fn seeded_candidate(
+    seed: u64,
+    index: u64,
+    mnemonic: Option<MnemonicLength>,
+    retries: &mut u64,
+) -> Result<CandidateKey> {
+    let mut rng = seeded_rng(seed, index);
+    match mnemonic {
+        Some(length) => CandidateKey::generate_mnemonic_with_retries(&mut rng, length, retries),
+        None => CandidateKey::generate_with_retries(&mut rng, retries),
+    }
+}
+
+// The next key of this worker's walk for sequential searches, starting from
+// a key drawn as usual when there is no walk yet. A walk that steps off the
+// end of the curve order counts as a retry and starts again from a new key.
+fn next_in_walk(source: RngSource, seed: Option<u64>, retries: &mut u64) -> Result<CandidateKey> {
+    let last = WORKER_WALK.with(|cell| cell.borrow_mut().take());
+    let next = last.map(|last| last.next());
+    if let Some(Err(_)) = next {
+        *retries += 1;
+    }
+    let candidate = match next {
+        Some(Ok(candidate)) => candidate,
+        _ => generate_candidate(source, seed, None, retries)?,
+    };
+    WORKER_WALK.with(|cell| *cell.borrow_mut() = Some(candidate.clone()));
+    Ok(candidate)
+}
+
+// Drops this worker's walk so its next key is drawn fresh
+fn end_walk() {
+    WORKER_WALK.with(|cell| cell.borrow_mut().take());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::{MatchOptions, MatchType};
+    use crate::generator::NostrKeyPair;
+    
+    #[test]
+    fn test_search_finds_match() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig { threads: 2, seed: Some(1), ..SearchConfig::default() };
+        let search = VanitySearch::new(matcher, config);
+        let rx = search.run().unwrap();
+        
+        let result = rx.recv().unwrap();
+        assert!(result.keypair.npub[5..].contains('q'));
+        assert!(search.is_done());
+        assert!(rx.recv().is_err());
+        assert_eq!(search.matches(), 1);
+        // A working source never needs a key redrawn
+        assert_eq!(search.key_retries(), 0);
+        assert!(search.take_error().is_none());
+    }
+    
+    #[test]
+    fn test_search_count_per_pattern() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string(), "p".to_string(), "qp".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig { threads: 2, count: Some(2), seed: Some(1), ..SearchConfig::default() };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        // A key with several of the patterns counts once for each of them
+        assert!(results.len() <= 6);
+        for value in ["q", "p", "qp"] {
+            let hits = results
+                .iter()
+                .filter(|r| std::iter::once(&r.matched_pattern).chain(&r.also_matched).any(|p| p.value == value))
+                .count();
+            assert_eq!(hits, 2);
+        }
+        assert_eq!(search.hits(), vec![2, 2, 2]);
+    }
+    
+    #[test]
+    fn test_search_max_attempts() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig { threads: 2, max_attempts: Some(500), seed: Some(1), ..SearchConfig::default() };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        assert!(results.is_empty());
+        assert!(search.is_exhausted());
+        assert!(!search.is_found());
+    }
+    
+    #[test]
+    fn test_search_progress_callback() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            max_attempts: Some(30_000),
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let search = VanitySearch::new(matcher, config).on_progress(Duration::ZERO, {
+            let reports = reports.clone();
+            move |attempts, rate| reports.lock().unwrap().push((attempts, rate))
+        });
+        for _ in search.run().unwrap() {}
+        
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() > 1);
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        let &(attempts, rate) = reports.last().unwrap();
+        assert_eq!(attempts, search.attempts());
+        assert!(rate > 0.0);
+    }
+    
+    #[test]
+    fn test_search_attempts_for_easy_pattern() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        // One in 32 keys match, and workers stop as soon as one does rather
+        // than finishing the 2000 key batch
+        assert_eq!(results.len(), 1);
+        assert!(results[0].attempts >= 1);
+        assert!(results[0].attempts <= search.attempts());
+        assert!(search.attempts() < 500, "{} attempts", search.attempts());
+    }
+    
+    #[test]
+    fn test_search_match_all() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string(), "p".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            count: Some(3),
+            seed: Some(1),
+            match_all: true,
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        // Each key counts once towards every pattern's quota
+        assert_eq!(results.len(), 3);
+        assert_eq!(search.hits(), vec![3, 3]);
+        for result in &results {
+            let body = &result.keypair.npub[5..];
+            assert!(body.contains('q') && body.contains('p'));
+            assert_eq!(result.matched_pattern.value, "q");
+            assert_eq!(result.pattern_label(), "q+p");
+        }
+    }
+    
+    #[test]
+    fn test_search_reports_every_match() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string(), "p".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig { threads: 2, count: Some(3), seed: Some(1), ..SearchConfig::default() };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        // Most keys have both characters, and count towards both quotas in
+        // one result rather than being reported twice
+        assert_eq!(search.hits(), vec![3, 3]);
+        let reported: usize = results.iter().map(|result| 1 + result.also_matched.len()).sum();
+        assert_eq!(reported, 6);
+        assert!(results.iter().any(|result| result.pattern_label() == "q+p"));
+        for result in &results {
+            for pattern in &result.also_matched {
+                assert!(pattern.matches(&result.keypair.npub));
+            }
+        }
+        let npubs: std::collections::HashSet<_> = results.iter().map(|result| &result.keypair.npub).collect();
+        assert_eq!(npubs.len(), results.len());
+    }
+    
+    #[test]
+    fn test_search_with_matcher() {
+        // Both ends of the npub body the same character, which no pattern
+        // type expresses
+        let same_ends = |candidate: &CandidateKey| {
+            let body = candidate.npub.as_bytes();
+            body[5] == body[body.len() - 1]
+        };
+        let config = SearchConfig { threads: 2, count: Some(2), seed: Some(1), ..SearchConfig::default() };
+        let exclude = PatternMatcher::from_strings(vec!["q".to_string()], MatchType::Prefix, MatchOptions::default()).unwrap();
+        let search = VanitySearch::new(PatternMatcher::new(Vec::new()), config)
+            .with_matcher(same_ends)
+            .with_exclude(exclude);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        assert_eq!(results.len(), 2);
+        assert_eq!(search.hits(), vec![2]);
+        for result in &results {
+            let body = result.keypair.npub.as_bytes();
+            assert_eq!(body[5], body[body.len() - 1]);
+            assert_ne!(body[5], b'q');
+            assert_eq!(result.matched_pattern.match_type, MatchType::Custom);
+            assert_eq!(result.matched_pattern.value, CUSTOM_PATTERN_LABEL);
+        }
+        // The placeholder pattern never matches on its own
+        assert!(!Pattern::custom("custom").matches(&results[0].keypair.npub));
+    }
+    
+    #[test]
+    fn test_search_sequential() {
+        let matcher = || PatternMatcher::from_strings(
+            vec!["q".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            count: Some(3),
+            sequential: true,
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher(), config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        assert_eq!(results.len(), 3);
+        for result in &results {
+            assert!(result.keypair.npub.starts_with("npub1q"));
+            let rebuilt = NostrKeyPair::from_nsec(&result.keypair.nsec).unwrap();
+            assert_eq!(rebuilt.npub, result.keypair.npub);
+        }
+        
+        let config = SearchConfig { sequential: true, mnemonic: true, ..SearchConfig::default() };
+        assert!(VanitySearch::new(matcher(), config).run().is_err());
+    }
+    
+    #[test]
+    fn test_search_batch_size() {
+        let matcher = || PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        
+        let config = SearchConfig {
+            threads: 2,
+            batch_size: Some(7),
+            max_attempts: Some(100),
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher(), config);
+        for _ in search.run().unwrap() {}
+        // Each thread may pass the limit check once before seeing it reached
+        assert!((100..=102).contains(&search.attempts()));
+        
+        let config = SearchConfig { batch_size: Some(0), ..SearchConfig::default() };
+        assert!(VanitySearch::new(matcher(), config).run().is_err());
+    }
+    
+    #[test]
+    fn test_search_timeout() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            timeout: Some(Duration::from_millis(200)),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        let results: Vec<_> = search.run().unwrap().iter().collect();
+        
+        assert!(results.is_empty());
+        assert!(search.is_timed_out());
+        assert!(search.is_done());
+    }
+    
+    #[test]
+    fn test_search_max_cpu() {
+        let matcher = || PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        
+        // At 1% the first batch is followed by an idle spell many times its
+        // length, which the timeout cuts short
+        let config = SearchConfig {
+            threads: 1,
+            batch_size: Some(100),
+            max_cpu: Some(1),
+            timeout: Some(Duration::from_millis(200)),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher(), config);
+        let start = Instant::now();
+        for _ in search.run().unwrap() {}
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(search.is_timed_out());
+        assert!(search.attempts() < 1000, "{}", search.attempts());
+        
+        for percent in [0, 101] {
+            let config = SearchConfig { max_cpu: Some(percent), ..SearchConfig::default() };
+            assert!(VanitySearch::new(matcher(), config).run().is_err());
+        }
+    }
+    
+    #[test]
+    fn test_search_thread_attempts() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 3,
+            max_attempts: Some(3000),
+            resume: Checkpoint { attempts: 500, elapsed_seconds: 1.0 },
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        for _ in search.run().unwrap() {}
+        
+        // Resumed attempts belong to no thread
+        let per_thread = search.thread_attempts();
+        assert_eq!(per_thread.len(), 3);
+        assert_eq!(per_thread.iter().sum::<u64>(), search.attempts() - 500);
+        assert!(search.run_elapsed() < search.elapsed());
+    }
+    
+    #[test]
+    fn test_search_zero_threads() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig { threads: 0, max_attempts: Some(3000), ..SearchConfig::default() };
+        let search = VanitySearch::new(matcher, config);
+        assert_eq!(search.config().threads, num_cpus::get());
+        for _ in search.run().unwrap() {}
+        
+        let per_thread = search.thread_attempts();
+        assert_eq!(per_thread.len(), num_cpus::get());
+        assert_eq!(per_thread.iter().sum::<u64>(), search.attempts());
+    }
+    
+    #[test]
+    fn test_search_pause() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qqqqqqqqqq".to_string()],
+            MatchType::Prefix,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            timeout: Some(Duration::from_millis(300)),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        let rx = search.run().unwrap();
+        
+        std::thread::sleep(Duration::from_millis(50));
+        search.pause();
+        assert!(search.is_paused());
+        std::thread::sleep(Duration::from_millis(100));
+        let attempts = search.attempts();
+        let elapsed = search.elapsed();
+        
+        // Paused time doesn't count towards run time or the timeout
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(search.attempts(), attempts);
+        assert!(search.elapsed() < elapsed + Duration::from_millis(50));
+        assert!(!search.is_done());
+        
+        search.resume();
+        assert!(!search.is_paused());
+        for _ in rx {}
+        assert!(search.attempts() > attempts);
+        assert!(search.is_timed_out());
+    }
+    
+    #[test]
+    fn test_search_iter_stops_when_dropped() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            continuous: true,
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        
+        let results: Vec<_> = search.iter().unwrap().take(3).collect();
+        assert_eq!(results.len(), 3);
+        assert!(search.is_done());
+    }
+    
+    #[test]
+    fn test_search_seeded_reproducible() {
+        let found = |threads: usize, batch_size: Option<usize>, count: Option<u64>| -> Vec<(String, u64)> {
+            let matcher = PatternMatcher::from_strings(
+                vec!["qq".to_string()],
+                MatchType::Prefix,
+                MatchOptions::default(),
+            ).unwrap();
+            let config = SearchConfig { threads, batch_size, count, seed: Some(9), ..SearchConfig::default() };
+            let search = VanitySearch::new(matcher, config);
+            search.run().unwrap().iter().map(|result| (result.keypair.npub.clone(), result.attempts)).collect()
+        };
+        
+        // The lowest numbered match, whichever thread gets to a key first
+        let first = found(4, None, None);
+        assert_eq!(first.len(), 1);
+        for _ in 0..3 {
+            assert_eq!(found(4, None, None), first);
+        }
+        assert_eq!(found(1, Some(7), None), first);
+        let (npub, attempts) = &first[0];
+        let key = CandidateKey::generate_with_rng(&mut seeded_rng(9, attempts - 1)).unwrap();
+        assert_eq!(&key.npub, npub);
+        
+        let several = found(3, None, Some(3));
+        assert_eq!(several.len(), 3);
+        assert_eq!(several[0], first[0]);
+        assert_eq!(found(2, Some(100), Some(3)), several);
+    }
+    
+    #[test]
+    fn test_search_results_record_attempt_at_find_time() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["qq".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 4,
+            continuous: true,
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config);
+        
+        let results: Vec<_> = search.iter().unwrap().take(20).collect();
+        assert!(results.windows(2).all(|pair| pair[0].attempts < pair[1].attempts));
+        assert!(results.iter().all(|result| result.attempts <= search.attempts()));
+    }
+    
+    #[test]
+    fn test_search_exclude() {
+        let matcher = PatternMatcher::from_strings(
+            vec!["q".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let exclude = PatternMatcher::from_strings(
+            vec!["p".to_string()],
+            MatchType::Contains,
+            MatchOptions::default(),
+        ).unwrap();
+        let config = SearchConfig {
+            threads: 2,
+            continuous: true,
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let search = VanitySearch::new(matcher, config).with_exclude(exclude);
+        
+        for result in search.iter().unwrap().take(10) {
+            let body = &result.keypair.npub[5..];
+            assert!(body.contains('q'));
+            assert!(!body.contains('p'));
+        }
+    }
+    
+    #[test]
+    fn test_search_denylist() {
+        let search = |denylist: HashSet<String>| {
+            let matcher = PatternMatcher::from_strings(vec!["q".to_string()], MatchType::Prefix, MatchOptions::default()).unwrap();
+            let config = SearchConfig {
+                threads: 1,
+                continuous: true,
+                seed: Some(3),
+                ..SearchConfig::default()
+            };
+            VanitySearch::new(matcher, config).with_denylist(denylist)
+        };
+        let found = |denylist| -> HashSet<String> {
+            search(denylist).iter().unwrap().take(5).map(|result| result.keypair.npub.clone()).collect()
+        };
+        let known = found(HashSet::new());
+        assert_eq!(found(HashSet::new()), known);
+        
+        // The same seeded run would find the same keys again, but skips them
+        for result in search(known.clone()).iter().unwrap().take(5) {
+            assert!(!known.contains(&result.keypair.npub));
+        }
+    }
+}
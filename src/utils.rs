@@ -1,63 +1,785 @@
-use anyhow::Result;
-use crate::generator::NostrKeyPair;
-use crate::matcher::Pattern;
-use std::fs::{File, OpenOptions};
-use std::io::{Write, BufRead, BufReader};
-use std::path::Path;
+use crate::error::{Result, VanityError};
+use crate::generator::{
+    decode_npub, NostrKeyPair, BECH32_CHARS, BECH32_DATA_LEN, BECH32_PADDED_CHARS, SHORT_HEAD_LEN, SHORT_TAIL_LEN,
+};
+use crate::matcher::{
+    parse_bookend, parse_charclass, parse_positions, parse_repeat, CharClasses, MatchOptions, MatchType, Pattern, Target,
+    WILDCARD_ANY, WILDCARD_ONE,
+};
+use image::Luma;
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct PatternSpec {
+    pub value: String,
+    pub match_type: Option<MatchType>,
+    pub case_sensitive: Option<bool>,
+}
+
+impl PatternSpec {
+    pub fn new(value: String) -> Self {
+        PatternSpec {
+            value,
+            match_type: None,
+            case_sensitive: None,
+        }
+    }
+    
+    pub fn build(
+        self,
+        default_match_type: &MatchType,
+        default_options: &MatchOptions,
+    ) -> Result<Pattern> {
+        let options = MatchOptions {
+            case_sensitive: self.case_sensitive.unwrap_or(default_options.case_sensitive),
+            ..default_options.clone()
+        };
+        Pattern::new(
+            self.value,
+            self.match_type.unwrap_or_else(|| default_match_type.clone()),
+            options,
+        )
+    }
+}
 
 pub struct VanityResult {
     pub keypair: NostrKeyPair,
     pub matched_pattern: Pattern,
     pub attempts: u64,
     pub time_elapsed: std::time::Duration,
+    pub nprofile: Option<String>,
+    // Wall-clock time of the match, and the worker threads searching
+    pub found_at: SystemTime,
+    pub threads: usize,
+    // Patterns listed after `matched_pattern` that the key also matched:
+    // every one with --match-all, otherwise any the key happens to satisfy
+    // whose quota isn't met yet
+    pub also_matched: Vec<Pattern>,
+    // From a --seed run: anyone who knows or guesses the seed can regenerate
+    // the key, so it's only fit for testing
+    pub seeded: bool,
+    // How unlikely a random key is to match every reported pattern, in bits.
+    // None for patterns that can't be estimated.
+    pub rarity: Option<f64>,
+}
+
+// How the secret key appears in written results. `Omit` leaves out the nsec
+// and any mnemonic, `Fingerprint` puts a short hash of the nsec in their
+// place, so a key kept elsewhere can be recognised without being stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecretOutput {
+    #[default]
+    Full,
+    Omit,
+    Fingerprint,
+}
+
+impl SecretOutput {
+    // The nsec, or its fingerprint, under the name it's written with
+    fn nsec_field<'a>(&self, keypair: &'a NostrKeyPair) -> Option<(&'static str, Cow<'a, str>)> {
+        match self {
+            SecretOutput::Full => Some(("nsec", Cow::Borrowed(&keypair.nsec))),
+            SecretOutput::Omit => None,
+            SecretOutput::Fingerprint => Some(("nsec_fingerprint", Cow::Owned(nsec_fingerprint(&keypair.nsec)))),
+        }
+    }
+    
+    fn mnemonic<'a>(&self, keypair: &'a NostrKeyPair) -> Option<&'a str> {
+        match self {
+            SecretOutput::Full => keypair.mnemonic.as_deref(),
+            SecretOutput::Omit | SecretOutput::Fingerprint => None,
+        }
+    }
+}
+
+// First 8 bytes of the SHA-256 of the nsec string, in hex. Enough to tell
+// keys apart, and no help in recovering the key.
+pub fn nsec_fingerprint(nsec: &str) -> String {
+    hex::encode(&Sha256::digest(nsec.as_bytes())[..8])
+}
+
+// Bold green for the matched part of a key on a terminal
+const MATCH_COLOR: &str = "\x1b[1;32m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    pattern: &'a str,
+    match_type: &'a MatchType,
+    npub: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nsec: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nsec_fingerprint: Option<String>,
+    hex_pubkey: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnemonic: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nprofile: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_as: Option<MatchType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_length: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    also_matched: Vec<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    seeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rarity: Option<f64>,
+    attempts: u64,
+    elapsed_seconds: f64,
+    keys_per_sec: f64,
 }
 
 impl VanityResult {
-    pub fn format_output(&self) -> String {
+    pub fn keys_per_sec(&self) -> f64 {
+        self.attempts as f64 / self.time_elapsed.as_secs_f64()
+    }
+    
+    // The achieved run for repeat patterns, which may exceed the minimum
+    pub fn run_length(&self) -> Option<usize> {
+        let pattern = &self.matched_pattern;
+        pattern.run_length(&pattern.options.target.select(&self.keypair))
+    }
+    
+    // For either patterns, whether the key matched as a prefix or a suffix
+    pub fn matched_as(&self) -> Option<MatchType> {
+        let pattern = &self.matched_pattern;
+        pattern
+            .matched_type(&pattern.options.target.select(&self.keypair))
+            .filter(|match_type| *match_type != pattern.match_type)
+    }
+    
+    // Every pattern the key was reported for, `matched_pattern` first
+    fn all_patterns(&self) -> impl Iterator<Item = &Pattern> {
+        std::iter::once(&self.matched_pattern).chain(&self.also_matched)
+    }
+    
+    // The pattern, or every pattern the key matched joined by `+`
+    pub fn pattern_label(&self) -> String {
+        self.all_patterns()
+            .map(|pattern| pattern.value.as_str())
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+    
+    // The matched part of the key as it actually appears, with its index
+    // in the key body
+    pub fn matched_slice(&self) -> Option<(usize, String)> {
+        let pattern = &self.matched_pattern;
+        let value = pattern.options.target.select(&self.keypair);
+        pattern
+            .matched_slice(&value)
+            .map(|(start, slice)| (pattern.options.skip + start, slice.to_string()))
+    }
+    
+    // The matched key with the matched part in brackets, e.g.
+    // `npub1xx[sat]yy...`, so contains matches are easy to spot. With
+    // `color` the part is colored for a terminal instead. --match-all keys
+    // have every pattern's part marked, with overlapping parts merged.
+    pub fn highlighted(&self, color: bool) -> Option<String> {
+        highlight_matches(self.all_patterns(), &self.keypair, self.matched_pattern.options.target, color)
+    }
+    
+    // Whether the matched part of the npub survives `short_display`. None
+    // when matching another target or when there's no single matched region.
+    pub fn visible_when_short(&self) -> Option<bool> {
+        let options = &self.matched_pattern.options;
+        if options.target != Target::Npub {
+            return None;
+        }
+        let (start, slice) = self.matched_slice()?;
+        let prefix_len = if options.include_prefix { 0 } else { options.target.prefix_len() };
+        let start = prefix_len + start;
+        let end = start + slice.len();
+        let npub_len = self.keypair.npub.len();
+        // Too short to truncate, so `short_display` shows the whole npub
+        if npub_len <= "npub1".len() + SHORT_HEAD_LEN + SHORT_TAIL_LEN {
+            return Some(true);
+        }
+        Some(end <= "npub1".len() + SHORT_HEAD_LEN || start >= npub_len - SHORT_TAIL_LEN)
+    }
+    
+    // `color` adds terminal escapes, so leave it off for anything but a TTY.
+    // `secrets` decides whether the nsec and mnemonic are shown.
+    pub fn format_output(&self, color: bool, secrets: SecretOutput) -> String {
+        let nprofile = match &self.nprofile {
+            Some(nprofile) => format!("nprofile: {}\n", nprofile),
+            None => String::new(),
+        };
+        let nsec = match secrets.nsec_field(&self.keypair) {
+            Some(("nsec", nsec)) => format!("nsec: {}\n", nsec),
+            Some((_, fingerprint)) => format!("nsec fingerprint: {}\n", fingerprint),
+            None => "nsec: omitted\n".to_string(),
+        };
+        let mnemonic = match secrets.mnemonic(&self.keypair) {
+            Some(phrase) => format!("Mnemonic: {}\n", phrase),
+            None => String::new(),
+        };
+        let run_length = match self.run_length() {
+            Some(run) => format!("Run length: {}\n", run),
+            None => String::new(),
+        };
+        let rarity = match self.rarity {
+            Some(bits) => format!("Rarity: {:.1} bits\n", bits),
+            None => String::new(),
+        };
+        let seeded = match self.seeded {
+            true => "⚠️ Seeded: anyone with the --seed value can regenerate this key. For testing only.\n",
+            false => "",
+        };
+        let mut parts = Vec::new();
+        match (self.matched_slice(), self.matched_as()) {
+            (Some((start, slice)), Some(match_type)) => {
+                parts.push(format!("{} (position {}, as a {})", slice, start, match_type))
+            }
+            (Some((start, slice)), None) => parts.push(format!("{} (position {})", slice, start)),
+            (None, _) => {}
+        }
+        for pattern in &self.also_matched {
+            let value = pattern.options.target.select(&self.keypair);
+            if let Some((start, slice)) = pattern.matched_slice(&value) {
+                parts.push(format!("{} (position {})", slice, pattern.options.skip + start));
+            }
+        }
+        let matched = match parts.is_empty() {
+            true => String::new(),
+            false => format!("Matched: {}\n", parts.join(", ")),
+        };
+        let highlighted = match self.highlighted(color) {
+            Some(highlighted) => format!("Highlighted: {}\n", highlighted),
+            None => String::new(),
+        };
+        let short = match self.visible_when_short() {
+            Some(false) => format!(
+                "Short: {} (⚠️ the match isn't visible when truncated)\n",
+                self.keypair.short_display()
+            ),
+            _ => format!("Short: {}\n", self.keypair.short_display()),
+        };
+        
         format!(
             "✨ Found vanity address!\n\
             Pattern: {}\n\
+            {}\
+            {}\
+            {}\
+            {}\
             npub: {}\n\
-            nsec: {}\n\
+            {}\
+            {}\
+            {}\
+            {}\
             Hex pubkey: {}\n\
             Attempts: {}\n\
             Time: {:.2}s\n\
             Speed: {:.0} keys/sec\n\
+            {}\
             ---",
-            self.matched_pattern.value,
+            self.pattern_label(),
+            matched,
+            highlighted,
+            run_length,
+            rarity,
             self.keypair.npub,
-            self.keypair.nsec,
+            short,
+            nprofile,
+            nsec,
+            mnemonic,
             self.keypair.hex_pubkey,
             self.attempts,
             self.time_elapsed.as_secs_f64(),
-            self.attempts as f64 / self.time_elapsed.as_secs_f64()
+            self.keys_per_sec(),
+            seeded
         )
     }
     
-    pub fn format_csv(&self) -> String {
-        format!(
-            "{},{},{},{},{},{:.2}",
-            self.matched_pattern.value,
-            self.keypair.npub,
-            self.keypair.nsec,
+    // With `extended`, rows also carry the UTC time of the match, the match
+    // type, case sensitivity and thread count, for logs spanning many runs.
+    // `secrets` decides whether there are nsec and mnemonic columns.
+    pub fn csv_header(&self, extended: bool, secrets: SecretOutput) -> String {
+        let mut header = String::from("pattern,npub,");
+        if let Some((name, _)) = secrets.nsec_field(&self.keypair) {
+            header.push_str(name);
+            header.push(',');
+        }
+        header.push_str("hex_pubkey,attempts,time_seconds");
+        if secrets.mnemonic(&self.keypair).is_some() {
+            header.push_str(",mnemonic");
+        }
+        if self.nprofile.is_some() {
+            header.push_str(",nprofile");
+        }
+        if self.run_length().is_some() {
+            header.push_str(",run_length");
+        }
+        if self.rarity.is_some() {
+            header.push_str(",rarity");
+        }
+        if extended {
+            header.push_str(",found_at,match_type,case_sensitive,threads");
+        }
+        header
+    }
+    
+    pub fn format_csv(&self, extended: bool, secrets: SecretOutput) -> String {
+        let mut line = format!("{},{},", self.pattern_label(), self.keypair.npub);
+        if let Some((_, nsec)) = secrets.nsec_field(&self.keypair) {
+            line.push_str(&nsec);
+            line.push(',');
+        }
+        line.push_str(&format!(
+            "{},{},{:.2}",
             self.keypair.hex_pubkey,
             self.attempts,
             self.time_elapsed.as_secs_f64()
-        )
+        ));
+        if let Some(phrase) = secrets.mnemonic(&self.keypair) {
+            line.push(',');
+            line.push_str(phrase);
+        }
+        if let Some(nprofile) = &self.nprofile {
+            line.push(',');
+            line.push_str(nprofile);
+        }
+        if let Some(run) = self.run_length() {
+            line.push_str(&format!(",{}", run));
+        }
+        if let Some(bits) = self.rarity {
+            line.push_str(&format!(",{:.1}", bits));
+        }
+        if extended {
+            line.push_str(&format!(
+                ",{},{},{},{}",
+                humantime::format_rfc3339_seconds(self.found_at),
+                self.matched_pattern.match_type,
+                self.matched_pattern.options.case_sensitive,
+                self.threads
+            ));
+        }
+        line
+    }
+    
+    pub fn format_json(&self, secrets: SecretOutput) -> Result<String> {
+        let (nsec, nsec_fingerprint) = match secrets {
+            SecretOutput::Full => (Some(self.keypair.nsec.as_str()), None),
+            SecretOutput::Omit => (None, None),
+            SecretOutput::Fingerprint => (None, Some(nsec_fingerprint(&self.keypair.nsec))),
+        };
+        let json = JsonResult {
+            pattern: &self.matched_pattern.value,
+            match_type: &self.matched_pattern.match_type,
+            npub: &self.keypair.npub,
+            nsec,
+            nsec_fingerprint,
+            hex_pubkey: &self.keypair.hex_pubkey,
+            mnemonic: secrets.mnemonic(&self.keypair),
+            nprofile: self.nprofile.as_deref(),
+            matched_as: self.matched_as(),
+            run_length: self.run_length(),
+            also_matched: self.also_matched.iter().map(|pattern| pattern.value.as_str()).collect(),
+            seeded: self.seeded,
+            rarity: self.rarity,
+            attempts: self.attempts,
+            elapsed_seconds: self.time_elapsed.as_secs_f64(),
+            keys_per_sec: self.keys_per_sec(),
+        };
+        Ok(serde_json::to_string(&json)?)
+    }
+    
+    pub fn provenance(&self) -> Provenance {
+        Provenance {
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            npub: self.keypair.npub.clone(),
+            pattern: self.pattern_label(),
+            match_type: self.matched_pattern.match_type.clone(),
+            target: self.matched_pattern.options.target,
+            case_sensitive: self.matched_pattern.options.case_sensitive,
+            mnemonic: self.keypair.mnemonic.is_some(),
+            attempts: self.attempts,
+            elapsed_seconds: self.time_elapsed.as_secs_f64(),
+            threads: self.threads,
+            found_at: humantime::format_rfc3339_seconds(self.found_at).to_string(),
+            seeded: self.seeded,
+            rarity: self.rarity,
+        }
+    }
+}
+
+// The key's `target` text with the part each pattern matches marked, in
+// brackets or, with `color`, colored. Bookend patterns mark both ends.
+// Patterns for other targets, and patterns without a matched region, mark
+// nothing. None when nothing is marked.
+pub fn highlight_matches<'a>(
+    patterns: impl IntoIterator<Item = &'a Pattern>,
+    keypair: &NostrKeyPair,
+    target: Target,
+    color: bool,
+) -> Option<String> {
+    let value = target.select(keypair);
+    let mut spans: Vec<Range<usize>> = patterns
+        .into_iter()
+        .filter(|pattern| pattern.options.target == target)
+        .flat_map(|pattern| {
+            let offset = if pattern.options.include_prefix { 0 } else { target.prefix_len() };
+            pattern
+                .matched_spans(&value)
+                .into_iter()
+                .map(move |span| offset + span.start..offset + span.end)
+        })
+        .collect();
+    spans.sort_unstable_by_key(|span| span.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    if merged.is_empty() {
+        return None;
+    }
+    
+    let (open, close) = if color { (MATCH_COLOR, COLOR_RESET) } else { ("[", "]") };
+    let mut highlighted = String::new();
+    let mut end = 0;
+    for span in merged {
+        highlighted.push_str(&value[end..span.start]);
+        highlighted.push_str(open);
+        highlighted.push_str(&value[span.clone()]);
+        highlighted.push_str(close);
+        end = span.end;
+    }
+    highlighted.push_str(&value[end..]);
+    Some(highlighted)
+}
+
+// How a key was found: the tool and version, what it was searching for and
+// what the search took. Holds nothing secret, so it can be kept or shared
+// apart from the key it documents.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub tool: &'static str,
+    pub version: &'static str,
+    pub npub: String,
+    pub pattern: String,
+    pub match_type: MatchType,
+    pub target: Target,
+    pub case_sensitive: bool,
+    // Whether the key was derived from a mnemonic rather than drawn directly
+    pub mnemonic: bool,
+    pub attempts: u64,
+    pub elapsed_seconds: f64,
+    pub threads: usize,
+    pub found_at: String,
+    // True for keys from a --seed run, which must never be used for a real
+    // identity. The seed itself isn't recorded, since it regenerates the key.
+    pub seeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl OutputFormat {
+    // The format implied by a `.txt`, `.csv`, `.json` or `.jsonl` extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" => Some(OutputFormat::Text),
+            "csv" => Some(OutputFormat::Csv),
+            "json" | "jsonl" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+    
+    // The extension `from_path` reads back as this format. JSON output is
+    // one object per line, so it's `.jsonl`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "jsonl",
+        }
+    }
+}
+
+// A pattern as part of a file name: anything but ASCII letters and digits
+// becomes `_`, so regexes and wildcards can't reach outside the directory
+pub fn pattern_file_stem(pattern: &str) -> String {
+    pattern.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+// Where --output-dir keeps the matches of `pattern`, e.g. `<dir>/sat.csv`
+pub fn pattern_output_path(dir: &Path, pattern: &str, format: OutputFormat) -> PathBuf {
+    dir.join(format!("{}.{}", pattern_file_stem(pattern), format.extension()))
+}
+
+pub fn write_result(
+    result: &VanityResult,
+    path: &Path,
+    format: OutputFormat,
+    csv_extended: bool,
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => write_result_to_file(result, path),
+        OutputFormat::Csv => write_csv_result(result, path, csv_extended),
+        OutputFormat::Json => write_json_result(result, path),
     }
 }
 
+// Appends results to one output file through a handle kept open for the
+// whole run, instead of reopening the file for every match. The file is
+// opened at the first result, so a run without matches leaves no file
+// behind, and each result is flushed as soon as it's written, so a crash
+// loses at most the result being written.
+//
+// A writer for a named pipe is opened up front instead, since opening one
+// waits for a reader. Once the reader goes away the pipe is closed and later
+// results are skipped rather than waiting for another reader.
+pub struct ResultWriter {
+    path: PathBuf,
+    format: OutputFormat,
+    csv_extended: bool,
+    file: Option<BufWriter<File>>,
+    // Whether a CSV header is due before the next result
+    header: bool,
+    fifo: bool,
+    secrets: SecretOutput,
+}
+
+impl ResultWriter {
+    pub fn new(path: PathBuf, format: OutputFormat, csv_extended: bool) -> Self {
+        ResultWriter {
+            path,
+            format,
+            csv_extended,
+            file: None,
+            header: false,
+            fifo: false,
+            secrets: SecretOutput::Full,
+        }
+    }
+    
+    // Blocks until something opens `path` for reading. Each reader gets its
+    // own CSV header.
+    pub fn open_fifo(path: PathBuf, format: OutputFormat, csv_extended: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .map_err(|e| VanityError::file("cannot open", &path, e))?;
+        Ok(ResultWriter {
+            path,
+            format,
+            csv_extended,
+            file: Some(BufWriter::new(file)),
+            header: format == OutputFormat::Csv,
+            fifo: true,
+            secrets: SecretOutput::Full,
+        })
+    }
+    
+    // What to write in place of the nsec, for --no-nsec and --fingerprint
+    pub fn with_secrets(mut self, secrets: SecretOutput) -> Self {
+        self.secrets = secrets;
+        self
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    // False once a pipe's reader has gone
+    pub fn is_open(&self) -> bool {
+        self.file.is_some() || !self.fifo
+    }
+    
+    pub fn write(&mut self, result: &VanityResult) -> Result<()> {
+        if self.file.is_none() && !self.fifo {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            // CSV files get a header unless there's already something to
+            // continue from
+            self.header = self.format == OutputFormat::Csv && file.metadata()?.len() == 0;
+            self.file = Some(BufWriter::new(file));
+        }
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        
+        let written = write_result_line(file, result, self.format, self.csv_extended, self.secrets, self.header);
+        match &written {
+            Ok(()) => self.header = false,
+            Err(_) if self.fifo => self.file = None,
+            Err(_) => {}
+        }
+        written
+    }
+}
+
+fn write_result_line<W: Write>(
+    file: &mut W,
+    result: &VanityResult,
+    format: OutputFormat,
+    csv_extended: bool,
+    secrets: SecretOutput,
+    header: bool,
+) -> Result<()> {
+    if header {
+        writeln!(file, "{}", result.csv_header(csv_extended, secrets))?;
+    }
+    match format {
+        OutputFormat::Text => writeln!(file, "{}", result.format_output(false, secrets))?,
+        OutputFormat::Csv => writeln!(file, "{}", result.format_csv(csv_extended, secrets))?,
+        OutputFormat::Json => writeln!(file, "{}", result.format_json(secrets)?)?,
+    }
+    file.flush()?;
+    Ok(())
+}
+
+// Plain keys, as minted by --generate, without any match details
+pub fn format_keypair(keypair: &NostrKeyPair) -> String {
+    let mnemonic = match &keypair.mnemonic {
+        Some(phrase) => format!("Mnemonic: {}\n", phrase),
+        None => String::new(),
+    };
+    format!(
+        "npub: {}\n\
+        nsec: {}\n\
+        {}\
+        Hex pubkey: {}\n\
+        ---",
+        keypair.npub, keypair.nsec, mnemonic, keypair.hex_pubkey
+    )
+}
+
+pub fn keypair_csv_header(keypair: &NostrKeyPair) -> String {
+    let mut header = String::from("npub,nsec,hex_pubkey");
+    if keypair.mnemonic.is_some() {
+        header.push_str(",mnemonic");
+    }
+    header
+}
+
+pub fn format_keypair_csv(keypair: &NostrKeyPair) -> String {
+    let mut line = format!("{},{},{}", keypair.npub, keypair.nsec, keypair.hex_pubkey);
+    if let Some(phrase) = &keypair.mnemonic {
+        line.push(',');
+        line.push_str(phrase);
+    }
+    line
+}
+
+pub fn format_keypair_json(keypair: &NostrKeyPair) -> Result<String> {
+    Ok(serde_json::to_string(keypair)?)
+}
+
+pub fn write_keypair(keypair: &NostrKeyPair, path: &Path, format: OutputFormat) -> Result<()> {
+    let file_exists = path.exists();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    
+    match format {
+        OutputFormat::Text => writeln!(file, "{}", format_keypair(keypair))?,
+        OutputFormat::Csv => {
+            if !file_exists {
+                writeln!(file, "{}", keypair_csv_header(keypair))?;
+            }
+            writeln!(file, "{}", format_keypair_csv(keypair))?;
+        }
+        OutputFormat::Json => writeln!(file, "{}", format_keypair_json(keypair)?)?,
+    }
+    Ok(())
+}
+
+// Opens `path` for appending the way the result writers do, so an unusable
+// output shows up before a search instead of after its match. A file the
+// check creates is removed again, since the CSV writers only add a header to
+// new files. Named pipes are refused rather than opened, since opening one
+// waits for a reader; --output-fifo streams to those.
+pub fn check_writable(path: &Path) -> Result<()> {
+    if check_fifo(path).is_ok() {
+        return Err(VanityError::InvalidInput(format!(
+            "{} is a named pipe, use --output-fifo to stream to it",
+            path.display()
+        )));
+    }
+    let existed = path.exists();
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| VanityError::file("cannot write to", path, e))?;
+    if !existed {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// Checks `path` is a named pipe, without opening it: opening a pipe for
+// writing waits for a reader
+pub fn check_fifo(path: &Path) -> Result<()> {
+    let metadata = fs::metadata(path).map_err(|e| VanityError::file("cannot open", path, e))?;
+    if !is_fifo(&metadata) {
+        return Err(VanityError::InvalidInput(format!(
+            "{} is not a named pipe, create one with mkfifo",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_fifo(metadata: &fs::Metadata) -> bool {
+    std::os::unix::fs::FileTypeExt::is_fifo(&metadata.file_type())
+}
+
+// mkfifo pipes only exist on unix, so any other file is a regular one
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+// Creates `dir` if needed and checks a file can be made in it
+pub fn check_writable_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).map_err(|e| VanityError::file("cannot create directory", dir, e))?;
+    let probe = dir.join(format!(".nostr_vanity_probe_{}", std::process::id()));
+    File::create(&probe).map_err(|e| VanityError::file("cannot write to directory", dir, e))?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
+
 pub fn write_result_to_file(result: &VanityResult, path: &Path) -> Result<()> {
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
         .open(path)?;
     
-    writeln!(file, "{}", result.format_output())?;
+    writeln!(file, "{}", result.format_output(false, SecretOutput::Full))?;
     Ok(())
 }
 
-pub fn write_csv_result(result: &VanityResult, path: &Path) -> Result<()> {
+pub fn write_csv_result(result: &VanityResult, path: &Path, extended: bool) -> Result<()> {
     let file_exists = path.exists();
     let mut file = OpenOptions::new()
         .create(true)
@@ -65,42 +787,737 @@ pub fn write_csv_result(result: &VanityResult, path: &Path) -> Result<()> {
         .open(path)?;
     
     if !file_exists {
-        writeln!(file, "pattern,npub,nsec,hex_pubkey,attempts,time_seconds")?;
+        writeln!(file, "{}", result.csv_header(extended, SecretOutput::Full))?;
     }
     
-    writeln!(file, "{}", result.format_csv())?;
+    writeln!(file, "{}", result.format_csv(extended, SecretOutput::Full))?;
     Ok(())
 }
 
-pub fn read_patterns_from_file(path: &Path) -> Result<Vec<String>> {
+pub fn write_json_result(result: &VanityResult, path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    
+    writeln!(file, "{}", result.format_json(SecretOutput::Full)?)?;
+    Ok(())
+}
+
+// Keeps `path` a single JSON array of results. Each write replaces the
+// closing bracket with the new element, so the file stays a valid document
+// between writes without being rewritten in full.
+pub fn write_json_file(result: &VanityResult, path: &Path, secrets: SecretOutput) -> Result<()> {
+    append_json_element(&result.format_json(secrets)?, path)
+}
+
+// The same for a --generate key, which has no match details
+pub fn write_keypair_json_file(keypair: &NostrKeyPair, path: &Path) -> Result<()> {
+    append_json_element(&format_keypair_json(keypair)?, path)
+}
+
+// The sidecar --meta keeps next to an output file, e.g. `keys.txt.meta.json`
+pub fn meta_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".meta.json");
+    PathBuf::from(path)
+}
+
+// Adds the result's provenance to the JSON array at `path`
+pub fn write_meta_file(result: &VanityResult, path: &Path) -> Result<()> {
+    append_json_element(&serde_json::to_string(&result.provenance())?, path)
+}
+
+fn append_json_element(element: &str, path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let trimmed = contents.trim_end();
+    if trimmed.is_empty() {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "[\n{}\n]", element)?;
+        return Ok(());
+    }
+    
+    let elements = trimmed
+        .strip_suffix(']')
+        .filter(|_| trimmed.trim_start().starts_with('['))
+        .ok_or_else(|| VanityError::InvalidInput(format!("{} is not a JSON array", path.display())))?
+        .trim_end();
+    let separator = if elements.ends_with('[') { "" } else { "," };
+    file.set_len(elements.len() as u64)?;
+    file.seek(SeekFrom::Start(elements.len() as u64))?;
+    writeln!(file, "{}\n{}\n]", separator, element)?;
+    Ok(())
+}
+
+// Two QR modules per character cell using half-block characters, so the
+// code stays roughly square in a terminal
+pub fn qr_terminal(data: &str) -> Result<String> {
+    let code = QrCode::new(data)?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+pub fn write_qr_png(data: &str, path: &Path) -> Result<()> {
+    let code = QrCode::new(data)?;
+    code.render::<Luma<u8>>().min_dimensions(256, 256).build().save(path)?;
+    Ok(())
+}
+
+// Writes `<pattern>-<npub>.png`, plus `<pattern>-<npub>-nsec.png` when
+// `include_nsec` is set and `secrets` lets the nsec reach disk. Characters
+// outside [a-z0-9] in the pattern become `_`.
+pub fn write_qr_pngs(result: &VanityResult, dir: &Path, include_nsec: bool, secrets: SecretOutput) -> Result<()> {
+    let stem = format!("{}-{}", pattern_file_stem(&result.matched_pattern.value), result.keypair.npub);
+    
+    fs::create_dir_all(dir)?;
+    write_qr_png(&result.keypair.npub, &dir.join(format!("{}.png", stem)))?;
+    if include_nsec && secrets == SecretOutput::Full {
+        write_qr_png(&result.keypair.nsec, &dir.join(format!("{}-nsec.png", stem)))?;
+    }
+    Ok(())
+}
+
+// One line of the --progress-json stream
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProgressStats {
+    pub attempts: u64,
+    pub keys_per_sec: f64,
+    pub elapsed_seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_attempts: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_seconds: Option<f64>,
+}
+
+impl ProgressStats {
+    // One line of the --progress-log file, without its timestamp
+    pub fn describe(&self) -> String {
+        let elapsed = std::time::Duration::from_secs(self.elapsed_seconds as u64);
+        let mut line = format!(
+            "Attempts: {} | {:.0} keys/sec | elapsed {}",
+            self.attempts,
+            self.keys_per_sec,
+            humantime::format_duration(elapsed)
+        );
+        if let Some(expected) = self.expected_attempts {
+            line += &format!(" | ~{:.0}% of expected attempts", self.attempts as f64 / expected * 100.0);
+            if let Some(eta) = self.eta_seconds {
+                line += &format!(", ETA ~{}", format_duration(eta));
+            }
+        }
+        line
+    }
+}
+
+// Summaries of runs with more patterns than this leave out the ones that
+// found nothing
+pub const SUMMARY_LIST_ALL: usize = 50;
+
+// End-of-run totals, tallied from results as the output thread receives them.
+// Patterns are listed in search order, including ones that never matched.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub matches: u64,
+    pub per_pattern: Vec<(Pattern, u64)>,
+}
+
+impl RunSummary {
+    pub fn new(patterns: &[Pattern]) -> Self {
+        RunSummary {
+            matches: 0,
+            per_pattern: patterns.iter().map(|pattern| (pattern.clone(), 0)).collect(),
+        }
+    }
+    
+    pub fn record(&mut self, result: &VanityResult) {
+        self.matches += 1;
+        for matched in result.all_patterns() {
+            if let Some((_, count)) = self.per_pattern.iter_mut().find(|(pattern, _)| {
+                pattern.value == matched.value && pattern.match_type == matched.match_type
+            }) {
+                *count += 1;
+            }
+        }
+    }
+    
+    pub fn format(&self, attempts: u64, elapsed: std::time::Duration) -> String {
+        let seconds = elapsed.as_secs_f64();
+        let mut lines = vec![
+            "Summary:".to_string(),
+            format!("  Matches: {}", self.matches),
+        ];
+        // The type is only worth showing when the same text is searched two ways
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+        for (pattern, _) in &self.per_pattern {
+            *uses.entry(pattern.value.as_str()).or_default() += 1;
+        }
+        // Long lists only show the patterns that matched
+        let list_all = self.per_pattern.len() <= SUMMARY_LIST_ALL;
+        for (pattern, count) in self.per_pattern.iter().filter(|(_, count)| list_all || *count > 0) {
+            lines.push(match uses[pattern.value.as_str()] > 1 {
+                true => format!("    '{}' ({}): {}", pattern.value, pattern.match_type, count),
+                false => format!("    '{}': {}", pattern.value, count),
+            });
+        }
+        if !list_all {
+            let unmatched = self.per_pattern.iter().filter(|(_, count)| *count == 0).count();
+            let other = if unmatched < self.per_pattern.len() { "other " } else { "" };
+            lines.push(format!("    ({} {}patterns found nothing)", unmatched, other));
+        }
+        lines.push(format!("  Attempts: {}", attempts));
+        lines.push(format!("  Time: {:.2}s", seconds));
+        lines.push(format!("  Average: {:.0} keys/sec", attempts as f64 / seconds.max(0.1)));
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub attempts: u64,
+    pub elapsed_seconds: f64,
+}
+
+pub fn read_checkpoint(path: &Path) -> Result<Option<Checkpoint>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+// Writes to a temporary file first and renames it over the checkpoint, so a
+// crash mid-write never leaves a truncated checkpoint behind.
+pub fn write_checkpoint(checkpoint: &Checkpoint, path: &Path) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    
+    fs::write(&temp_path, serde_json::to_string(checkpoint)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+// Pattern files may hold at most this many patterns unless a larger limit is
+// given, so a wrong file can't eat all memory before anything is searched
+pub const DEFAULT_MAX_PATTERNS: usize = 1_000_000;
+
+pub fn read_patterns_from_file(path: &Path, limit: usize) -> Result<Vec<PatternSpec>> {
     let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    read_patterns(BufReader::new(file), &path.display().to_string(), limit)
+}
+
+// Look-alike groups for --fuzzy-chars, one group per line, e.g. `uv`. Blank
+// lines and `#` comments are skipped.
+pub fn read_char_classes(path: &Path) -> Result<CharClasses> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| VanityError::file("failed to read", path, e))?;
+    let groups: Vec<&str> = contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    groups
+        .join("\n")
+        .parse()
+        .map_err(|e: VanityError| VanityError::InvalidPattern(format!("{}: {}", path.display(), e)))
+}
+
+// Npubs for --denylist, one per line. Blank lines and `#` comments are
+// skipped; anything else has to be a valid npub, so a truncated or mistyped
+// entry is reported rather than quietly never matching.
+pub fn read_denylist(path: &Path) -> Result<HashSet<String>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| VanityError::file("failed to read", path, e))?;
+    parse_denylist(&contents, &path.display().to_string())
+}
+
+pub fn parse_denylist(contents: &str, source: &str) -> Result<HashSet<String>> {
+    let mut npubs = HashSet::new();
+    for (index, line) in contents.lines().enumerate() {
+        let npub = line.split('#').next().unwrap_or_default().trim().to_lowercase();
+        if npub.is_empty() {
+            continue;
+        }
+        decode_npub(&npub).map_err(|e| VanityError::InvalidInput(format!("{}:{}: {}", source, index + 1, e)))?;
+        npubs.insert(npub);
+    }
+    Ok(npubs)
+}
+
+pub fn read_patterns_from_stdin(limit: usize) -> Result<Vec<PatternSpec>> {
+    read_patterns(std::io::stdin().lock(), "<stdin>", limit)
+}
+
+// One pattern line per line of `reader`, skipping blank lines and `#`
+// comments. `source` names the input in error messages. Reading stops with
+// an error as soon as there are more than `limit` patterns.
+pub fn read_patterns<R: BufRead>(reader: R, source: &str, limit: usize) -> Result<Vec<PatternSpec>> {
     let mut patterns = Vec::new();
     
-    for line in reader.lines() {
+    for (index, line) in reader.lines().enumerate() {
         let line = line?;
         let trimmed = line.trim();
         if !trimmed.is_empty() && !trimmed.starts_with('#') {
-            patterns.push(trimmed.to_string());
+            if patterns.len() == limit {
+                return Err(VanityError::InvalidInput(format!(
+                    "{} has more than {} patterns, raise --max-patterns to load them all",
+                    source,
+                    limit
+                )));
+            }
+            let spec = parse_pattern_line(trimmed)
+                .map_err(|e| VanityError::InvalidPattern(format!("{}:{}: {}", source, index + 1, e)))?;
+            patterns.push(spec);
         }
     }
     
     Ok(patterns)
 }
 
-pub fn parse_patterns_string(input: &str) -> Vec<String> {
+// A search described in a TOML file for --config, for jobs with more
+// patterns and settings than are comfortable as flags. Every setting is
+// optional; the command line overrides any it repeats.
+#[derive(Debug, Clone, Default)]
+pub struct JobConfig {
+    pub threads: Option<usize>,
+    pub match_type: Option<MatchType>,
+    pub case_sensitive: Option<bool>,
+    pub target: Option<Target>,
+    pub count: Option<u64>,
+    pub continuous: Option<bool>,
+    pub match_all: Option<bool>,
+    // A duration such as `10m`, checked like --timeout
+    pub timeout: Option<String>,
+    pub max_attempts: Option<u64>,
+    pub exclude: Vec<String>,
+    pub output: Vec<PathBuf>,
+    pub patterns: Vec<PatternSpec>,
+}
+
+// The file as written. Match types and targets are kept as text with their
+// position so a misspelling can be reported where it is.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    threads: Option<usize>,
+    match_type: Option<toml::Spanned<String>>,
+    case_sensitive: Option<bool>,
+    target: Option<toml::Spanned<String>>,
+    count: Option<u64>,
+    continuous: Option<bool>,
+    match_all: Option<bool>,
+    timeout: Option<String>,
+    max_attempts: Option<u64>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    output: Vec<PathBuf>,
+    #[serde(default, rename = "pattern")]
+    patterns: Vec<JobPattern>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobPattern {
+    value: toml::Spanned<String>,
+    match_type: Option<toml::Spanned<String>>,
+    case_sensitive: Option<bool>,
+}
+
+pub fn read_job_config(path: &Path) -> Result<JobConfig> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| VanityError::file("failed to read", path, e))?;
+    parse_job_config(&contents, &path.display().to_string())
+}
+
+// Parses a --config file. `source` names it in error messages, which give
+// the line and column of the problem.
+pub fn parse_job_config(contents: &str, source: &str) -> Result<JobConfig> {
+    let file: JobFile = toml::from_str(contents)
+        .map_err(|e| VanityError::InvalidInput(format!("{}: {}", source, e)))?;
+    let located = |span: Range<usize>, message: String| {
+        let before = &contents[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+        VanityError::InvalidInput(format!("{}:{}:{}: {}", source, line, column, message))
+    };
+    let match_type = |value: &toml::Spanned<String>| {
+        value
+            .get_ref()
+            .parse::<MatchType>()
+            .map_err(|e| located(value.span(), e.to_string()))
+    };
+    let target = |value: &toml::Spanned<String>| match value.get_ref().to_lowercase().as_str() {
+        "npub" => Ok(Target::Npub),
+        "nsec" => Ok(Target::Nsec),
+        "hex" => Ok(Target::Hex),
+        other => Err(located(value.span(), format!("unknown target '{}', expected npub, nsec or hex", other))),
+    };
+    
+    let patterns = file
+        .patterns
+        .iter()
+        .map(|pattern| {
+            if pattern.value.get_ref().trim().is_empty() {
+                return Err(located(pattern.value.span(), "empty pattern".to_string()));
+            }
+            Ok(PatternSpec {
+                value: pattern.value.get_ref().trim().to_string(),
+                match_type: pattern.match_type.as_ref().map(match_type).transpose()?,
+                case_sensitive: pattern.case_sensitive,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(JobConfig {
+        threads: file.threads,
+        match_type: file.match_type.as_ref().map(match_type).transpose()?,
+        case_sensitive: file.case_sensitive,
+        target: file.target.as_ref().map(target).transpose()?,
+        count: file.count,
+        continuous: file.continuous,
+        match_all: file.match_all,
+        timeout: file.timeout,
+        max_attempts: file.max_attempts,
+        exclude: file.exclude,
+        output: file.output,
+        patterns,
+    })
+}
+
+// A pattern line is `value[,match_type[,case_sensitive]]`. Omitted or empty
+// columns fall back to the command line defaults.
+pub fn parse_pattern_line(line: &str) -> Result<PatternSpec> {
+    let mut columns = line.split(',').map(str::trim);
+    let mut spec = PatternSpec::new(columns.next().unwrap_or_default().to_string());
+    
+    if let Some(match_type) = columns.next().filter(|c| !c.is_empty()) {
+        spec.match_type = Some(match_type.parse()?);
+    }
+    
+    if let Some(case_sensitive) = columns.next().filter(|c| !c.is_empty()) {
+        spec.case_sensitive = Some(
+            case_sensitive
+                .parse()
+                .map_err(|_| {
+                    VanityError::InvalidPattern(format!("invalid case flag '{}', expected true or false", case_sensitive))
+                })?,
+        );
+    }
+    
+    if columns.next().is_some() {
+        return Err(VanityError::InvalidPattern(format!("too many columns in '{}'", line)));
+    }
+    
+    Ok(spec)
+}
+
+pub fn parse_patterns_string(input: &str, delimiter: char) -> Vec<String> {
     input
-        .split(',')
+        .split(delimiter)
         .map(|s| s.trim().to_string())
         .filter(|s| !s.is_empty())
         .collect()
 }
 
-pub fn estimate_time(pattern_length: usize, keys_per_sec: f64) -> String {
-    let possibilities = 32_f64.powi(pattern_length as i32);
-    let expected_attempts = possibilities / 2.0;
-    let seconds = expected_attempts / keys_per_sec;
+// Chance that a single random key satisfies the pattern. Each character is
+// one of 32 bech32 or 16 hex symbols, and case-sensitive bech32 letters count
+// double since each has two possible cases. Contains patterns can start at
+// any position in the body, and with a boundary need the boundary character
+// beside them except at the ends. Position patterns only constrain their
+// listed characters. Either patterns combine the prefix and suffix chances as
+// if independent, bookend patterns multiply them, and charclass patterns sum
+// the chances of every character in the class at each leading position. A `?`
+// wildcard constrains nothing, and the pieces between `*` wildcards are
+// estimated separately: the first piece of a prefix and the last of a suffix
+// stay anchored, the rest are treated as contains patterns in any order.
+// Regex and custom patterns can't be estimated.
+pub fn match_probability(pattern: &str, match_type: &MatchType, options: &MatchOptions) -> Option<f64> {
+    if let MatchType::Either = match_type {
+        let prefix = match_probability(pattern, &MatchType::Prefix, options)?;
+        let suffix = match_probability(pattern, &MatchType::Suffix, options)?;
+        return Some(prefix + suffix - prefix * suffix);
+    }
+    if let MatchType::Bookend = match_type {
+        // The ends fall on different characters, so they're independent
+        let (prefix, suffix) = parse_bookend(pattern).ok()?;
+        let body = prefix.chars().count() + suffix.chars().count();
+        let prefix_len = match options.include_prefix {
+            true => options.target.prefix_len(),
+            false => 0,
+        };
+        if body > options.body_len() + prefix_len {
+            return Some(0.0);
+        }
+        let prefix = match_probability(&prefix, &MatchType::Prefix, options)?;
+        let suffix = match_probability(&suffix, &MatchType::Suffix, options)?;
+        return Some(prefix * suffix);
+    }
+    
+    let target = options.target;
+    let pattern = match options.include_prefix {
+        true => pattern.strip_prefix(target.hrp_prefix()).unwrap_or(pattern),
+        false => pattern,
+    };
+    if pattern.contains(WILDCARD_ANY)
+        && matches!(match_type, MatchType::Prefix | MatchType::Suffix | MatchType::Contains)
+    {
+        let pieces: Vec<&str> = pattern.split(WILDCARD_ANY).collect();
+        let last = pieces.len() - 1;
+        // Contains patterns can't have both wildcards and a boundary, and
+        // the other types ignore it
+        let options = MatchOptions { boundary: None, ..options.clone() };
+        return pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, piece)| !piece.is_empty())
+            .map(|(i, piece)| {
+                let piece_type = match match_type {
+                    MatchType::Prefix if i == 0 => MatchType::Prefix,
+                    MatchType::Suffix if i == last => MatchType::Suffix,
+                    _ => MatchType::Contains,
+                };
+                match_probability(piece, &piece_type, &options)
+            })
+            .product();
+    }
+    if let MatchType::Charclass = match_type {
+        // Keys are lowercase, so case sensitivity doesn't narrow the class
+        let (kind, len) = parse_charclass(pattern).ok()?;
+        let options = MatchOptions { case_sensitive: false, ..options.clone() };
+        let alphabet = match target {
+            Target::Hex => "0123456789abcdef",
+            Target::Npub | Target::Nsec => BECH32_CHARS,
+        };
+        return Some(
+            (0..len)
+                .map(|i| {
+                    alphabet
+                        .chars()
+                        .filter(|&c| kind.contains(c))
+                        .map(|c| exact_char_probability(&options, Some(i), c))
+                        .sum::<f64>()
+                })
+                .product(),
+        );
+    }
+    let body_len = options.body_len();
+    let length = pattern.chars().count();
+    
+    // Body index of each constrained character, where it is fixed
+    let constrained: Vec<(Option<usize>, char)> = match match_type {
+        MatchType::Position => parse_positions(pattern)
+            .ok()?
+            .into_iter()
+            .map(|(index, c)| (Some(index), c))
+            .collect(),
+        MatchType::Prefix => pattern.chars().enumerate().map(|(i, c)| (Some(i), c)).collect(),
+        MatchType::Suffix => pattern
+            .chars()
+            .enumerate()
+            .map(|(i, c)| ((body_len + i).checked_sub(length), c))
+            .collect(),
+        MatchType::Contains => pattern.chars().map(|c| (None, c)).collect(),
+        MatchType::Repeat => {
+            let (c, min) = parse_repeat(pattern).ok()?;
+            (0..min).map(|i| (Some(i), c)).collect()
+        }
+        MatchType::Regex | MatchType::Custom => return None,
+        MatchType::Either | MatchType::Charclass | MatchType::Bookend => unreachable!(),
+    };
+    let probability: f64 = constrained
+        .iter()
+        .map(|&(index, c)| char_probability(options, index, c))
+        .product();
+    
+    match match_type {
+        MatchType::Contains => {
+            let positions = body_len.saturating_sub(length) + 1;
+            // A match needs the boundary on each side that isn't the end of
+            // the body
+            let bounded: f64 = match options.boundary {
+                Some(boundary) => {
+                    let side = char_probability(options, None, boundary);
+                    (0..positions)
+                        .map(|start| {
+                            let before = if start > 0 { side } else { 1.0 };
+                            let after = if start + length < body_len { side } else { 1.0 };
+                            before * after
+                        })
+                        .sum()
+                }
+                None => positions as f64,
+            };
+            Some((probability * bounded).min(1.0))
+        }
+        _ => Some(probability),
+    }
+}
+
+// Chance that the body character at `index` is `c`. Checksum characters are
+// effectively uniform, but the last bech32 data character is always `q` or
+// `s`, so a suffix reaching past the checksum is either impossible or only
+// half as hard for that character.
+//
+// With look-alike groups, a character matches any member of its group that
+// can appear in the target.
+fn char_probability(options: &MatchOptions, index: Option<usize>, c: char) -> f64 {
+    if c == WILDCARD_ONE {
+        return 1.0;
+    }
+    let class = options.fuzzy.as_ref().and_then(|classes| classes.class_of(c));
+    match class {
+        Some(class) => class
+            .chars()
+            .filter(|&member| match options.target {
+                Target::Hex => member.is_ascii_hexdigit(),
+                Target::Npub | Target::Nsec => BECH32_CHARS.contains(member),
+            })
+            .map(|member| exact_char_probability(options, index, member))
+            .sum(),
+        None => exact_char_probability(options, index, c),
+    }
+}
+
+fn exact_char_probability(options: &MatchOptions, index: Option<usize>, c: char) -> f64 {
+    let target = options.target;
+    if target == Target::Hex {
+        return 1.0 / target.alphabet_size() as f64;
+    }
     
+    // Indices count from after any skipped characters
+    let index = index.map(|index| index + options.skip);
+    let mut probability = if index == Some(BECH32_DATA_LEN - 1) {
+        if BECH32_PADDED_CHARS.contains(&c.to_ascii_lowercase()) { 0.5 } else { 0.0 }
+    } else {
+        1.0 / target.alphabet_size() as f64
+    };
+    if options.case_sensitive && c.is_ascii_alphabetic() {
+        probability /= 2.0;
+    }
+    probability
+}
+
+// Sums the chance over every variant of the pattern, which slightly
+// overcounts keys matching several variants at once.
+pub fn pattern_probability(pattern: &Pattern) -> Option<f64> {
+    pattern
+        .variants()
+        .iter()
+        .map(|variant| match_probability(variant, &pattern.match_type, &pattern.options))
+        .sum::<Option<f64>>()
+        .map(|probability| probability.min(1.0))
+}
+
+// Attempts by which a match is more likely than not, roughly
+pub fn expected_attempts(probability: f64) -> f64 {
+    1.0 / probability / 2.0
+}
+
+// Chances of success that estimates report a time for
+pub const ESTIMATE_CHANCES: [f64; 3] = [0.5, 0.9, 0.99];
+
+// Attempts after which a search has the given chance of having found a
+// match. Attempts are independent, so the chance after n of them is
+// 1 - (1 - p)^n; solving for n gives ln(1 - chance) / ln(1 - p).
+pub fn attempts_for_chance(probability: f64, chance: f64) -> f64 {
+    if probability >= 1.0 {
+        return 1.0;
+    }
+    ((-chance).ln_1p() / (-probability).ln_1p()).max(1.0)
+}
+
+// Expected seconds to find the pattern, or None when it can't be estimated
+pub fn estimate_seconds(pattern: &Pattern, keys_per_sec: f64) -> Option<f64> {
+    Some(expected_attempts(pattern_probability(pattern)?) / keys_per_sec)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    // Chance that one key matches, and attempts to more likely than not find
+    // a match. None for patterns that can't be estimated.
+    pub probability: Option<f64>,
+    pub expected_attempts: Option<f64>,
+    pub human_time: String,
+    // Seconds by which each of ESTIMATE_CHANCES is reached, paired with the
+    // chance. Empty when there's no estimate or the pattern can't occur.
+    pub quantiles: Vec<(f64, f64)>,
+}
+
+impl Estimate {
+    fn from_probability(probability: Option<f64>, keys_per_sec: f64) -> Self {
+        let expected = probability.map(expected_attempts);
+        let quantiles = match probability {
+            Some(p) if p > 0.0 => ESTIMATE_CHANCES
+                .iter()
+                .map(|&chance| (chance, attempts_for_chance(p, chance) / keys_per_sec))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let human_time = match expected.map(|attempts| attempts / keys_per_sec) {
+            Some(seconds) if seconds.is_infinite() => "never, the pattern can't occur".to_string(),
+            Some(seconds) => format_duration(seconds),
+            None => "unknown for regex patterns".to_string(),
+        };
+        Estimate {
+            probability,
+            expected_attempts: expected,
+            human_time,
+            quantiles,
+        }
+    }
+}
+
+pub fn estimate_time(pattern: &Pattern, keys_per_sec: f64) -> Estimate {
+    Estimate::from_probability(pattern_probability(pattern), keys_per_sec)
+}
+
+// Chance that a key matches at least one of `patterns`, treating them as
+// independent, which is what ends a search that stops at its first match
+pub fn combined_probability(patterns: &[Pattern]) -> Option<f64> {
+    let miss: f64 = patterns
+        .iter()
+        .map(|pattern| pattern_probability(pattern).map(|p| 1.0 - p))
+        .product::<Option<f64>>()?;
+    Some(1.0 - miss)
+}
+
+pub fn estimate_combined(patterns: &[Pattern], keys_per_sec: f64) -> Estimate {
+    Estimate::from_probability(combined_probability(patterns), keys_per_sec)
+}
+
+// Chance that a key matches every one of `patterns`, for --match-all. Also
+// treats them as independent, which overestimates the odds of patterns that
+// compete for the same characters, like two different prefixes.
+pub fn all_probability(patterns: &[Pattern]) -> Option<f64> {
+    patterns.iter().map(pattern_probability).product()
+}
+
+pub fn estimate_all(patterns: &[Pattern], keys_per_sec: f64) -> Estimate {
+    Estimate::from_probability(all_probability(patterns), keys_per_sec)
+}
+
+// A matched key's rarity: log2 of one over the chance that a random key
+// matches all of `patterns`, so each bit halves the odds. A 4-character
+// npub prefix is 20 bits whatever the characters, and rarities of different
+// match types and lengths compare directly.
+pub fn rarity_bits(patterns: &[Pattern]) -> Option<f64> {
+    all_probability(patterns)
+        .filter(|&probability| probability > 0.0)
+        .map(|probability| probability.recip().log2())
+}
+
+pub fn format_duration(seconds: f64) -> String {
     if seconds < 60.0 {
         format!("{:.1} seconds", seconds)
     } else if seconds < 3600.0 {
@@ -112,4 +1529,773 @@ pub fn estimate_time(pattern_length: usize, keys_per_sec: f64) -> String {
     } else {
         format!("{:.1} years", seconds / 31536000.0)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Substitutions;
+    
+    #[test]
+    fn test_parse_pattern_line() {
+        let spec = parse_pattern_line("satoshi").unwrap();
+        assert_eq!(spec.value, "satoshi");
+        assert!(spec.match_type.is_none());
+        assert!(spec.case_sensitive.is_none());
+        
+        let spec = parse_pattern_line("satoshi, suffix, true").unwrap();
+        assert!(matches!(spec.match_type, Some(MatchType::Suffix)));
+        assert_eq!(spec.case_sensitive, Some(true));
+        
+        let spec = parse_pattern_line("zap,,false").unwrap();
+        assert!(spec.match_type.is_none());
+        assert_eq!(spec.case_sensitive, Some(false));
+        
+        assert!(parse_pattern_line("zap,sideways").is_err());
+        assert!(parse_pattern_line("zap,prefix,maybe").is_err());
+        assert!(parse_pattern_line("zap,prefix,true,extra").is_err());
+    }
+    
+    #[test]
+    fn test_estimates() {
+        let pattern = |value: &str, match_type| {
+            Pattern::new(value.to_string(), match_type, MatchOptions::default()).unwrap()
+        };
+        let sat = pattern("sat", MatchType::Prefix);
+        let estimate = estimate_time(&sat, 1024.0);
+        assert_eq!(estimate.probability, Some(1.0 / 32768.0));
+        assert_eq!(estimate.expected_attempts, Some(16384.0));
+        assert_eq!(estimate.human_time, "16.0 seconds");
+        let chances: Vec<f64> = estimate.quantiles.iter().map(|&(chance, _)| chance).collect();
+        assert_eq!(chances, ESTIMATE_CHANCES);
+        let seconds: Vec<f64> = estimate.quantiles.iter().map(|&(_, seconds)| seconds).collect();
+        for (seconds, expected) in seconds.iter().zip([22.2, 73.7, 147.4]) {
+            assert!((seconds - expected).abs() < 0.1, "{} vs {}", seconds, expected);
+        }
+        
+        // The tail is long: 99% takes about seven times as long as 50%
+        let p = 1.0 / 32768.0;
+        assert!((attempts_for_chance(p, 0.99) / attempts_for_chance(p, 0.5) - 6.64).abs() < 0.01);
+        assert_eq!(attempts_for_chance(1.0, 0.99), 1.0);
+        assert_eq!(attempts_for_chance(0.9, 0.5), 1.0);
+        assert!(estimate_time(&pattern("aaaaaaa", MatchType::Suffix), 1024.0).quantiles.is_empty());
+        
+        let both = [sat.clone(), pattern("zap", MatchType::Prefix)];
+        assert_eq!(combined_probability(&both), Some(1.0 - (1.0 - p) * (1.0 - p)));
+        assert_eq!(all_probability(&both), Some(p * p));
+        assert_eq!(estimate_all(&both, 1024.0).expected_attempts, Some(expected_attempts(p * p)));
+        
+        let with_regex = [sat, pattern("^a", MatchType::Regex)];
+        assert_eq!(combined_probability(&with_regex), None);
+        assert_eq!(estimate_combined(&with_regex, 1024.0).human_time, "unknown for regex patterns");
+    }
+    
+    #[test]
+    fn test_read_patterns() {
+        let input = "sat\n# comment\n\n  zap, suffix\n";
+        let specs = read_patterns(input.as_bytes(), "<stdin>", DEFAULT_MAX_PATTERNS).unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[1].value, "zap");
+        assert!(matches!(specs[1].match_type, Some(MatchType::Suffix)));
+        
+        let error = read_patterns("sat\nzap,sideways\n".as_bytes(), "<stdin>", DEFAULT_MAX_PATTERNS).unwrap_err();
+        assert!(error.to_string().starts_with("<stdin>:2:"));
+        
+        // Comments and blank lines don't count towards the limit
+        assert_eq!(read_patterns(input.as_bytes(), "<stdin>", 2).unwrap().len(), 2);
+        let error = read_patterns(input.as_bytes(), "<stdin>", 1).unwrap_err();
+        assert!(error.to_string().starts_with("<stdin> has more than 1 patterns"));
+    }
+    
+    #[test]
+    fn test_parse_denylist() {
+        let keypair = NostrKeyPair::generate().unwrap();
+        let input = format!("# handed out\n\n{}  # alice\n{}\n", keypair.npub, keypair.npub.to_uppercase());
+        let denylist = parse_denylist(&input, "known.txt").unwrap();
+        assert_eq!(denylist.len(), 1);
+        assert!(denylist.contains(&keypair.npub));
+        
+        let truncated = format!("{}\n{}\n", keypair.npub, &keypair.npub[..40]);
+        let error = parse_denylist(&truncated, "known.txt").unwrap_err();
+        assert!(error.to_string().starts_with("known.txt:2: "), "{}", error);
+        assert!(parse_denylist(&keypair.nsec, "known.txt").is_err());
+    }
+    
+    #[test]
+    fn test_parse_job_config() {
+        let input = r#"
+threads = 4
+match_type = "suffix"
+target = "hex"
+exclude = ["dead"]
+
+[[pattern]]
+value = "sat"
+
+[[pattern]]
+value = "zap"
+match_type = "prefix"
+case_sensitive = true
+"#;
+        let config = parse_job_config(input, "job.toml").unwrap();
+        assert_eq!(config.threads, Some(4));
+        assert!(matches!(config.match_type, Some(MatchType::Suffix)));
+        assert!(matches!(config.target, Some(Target::Hex)));
+        assert_eq!(config.exclude, vec!["dead"]);
+        assert_eq!(config.patterns.len(), 2);
+        assert!(config.patterns[0].match_type.is_none());
+        assert!(matches!(config.patterns[1].match_type, Some(MatchType::Prefix)));
+        assert_eq!(config.patterns[1].case_sensitive, Some(true));
+        
+        // Bad values are reported at their line and column
+        let error = parse_job_config("[[pattern]]\nvalue = \"sat\"\nmatch_type = \"sideways\"\n", "job.toml").unwrap_err();
+        assert!(error.to_string().starts_with("job.toml:3:14: unknown match type"), "{}", error);
+        let error = parse_job_config("target = \"npib\"\n", "job.toml").unwrap_err();
+        assert!(error.to_string().starts_with("job.toml:1:10: unknown target"), "{}", error);
+        let error = parse_job_config("[[pattern]]\nvalue = \" \"\n", "job.toml").unwrap_err();
+        assert!(error.to_string().starts_with("job.toml:2:9: empty pattern"), "{}", error);
+        
+        // So are misspelt settings
+        let error = parse_job_config("thread = 4\n", "job.toml").unwrap_err();
+        assert!(error.to_string().contains("unknown field `thread`"), "{}", error);
+    }
+    
+    #[test]
+    fn test_progress_stats_describe() {
+        let mut stats = ProgressStats {
+            attempts: 1500,
+            keys_per_sec: 500.4,
+            elapsed_seconds: 3.7,
+            expected_attempts: None,
+            eta_seconds: None,
+        };
+        assert_eq!(stats.describe(), "Attempts: 1500 | 500 keys/sec | elapsed 3s");
+        
+        stats.expected_attempts = Some(6000.0);
+        stats.eta_seconds = Some(9.0);
+        assert_eq!(
+            stats.describe(),
+            "Attempts: 1500 | 500 keys/sec | elapsed 3s | ~25% of expected attempts, ETA ~9.0 seconds"
+        );
+    }
+    
+    #[test]
+    fn test_match_probability() {
+        let options = MatchOptions::default();
+        let prefix = match_probability("sat", &MatchType::Prefix, &options).unwrap();
+        assert_eq!(prefix, 1.0 / 32768.0);
+        assert_eq!(match_probability("sat", &MatchType::Suffix, &options), Some(prefix));
+        
+        let contains = match_probability("sat", &MatchType::Contains, &options).unwrap();
+        assert_eq!(contains, prefix * 56.0);
+        
+        let either = match_probability("sat", &MatchType::Either, &options).unwrap();
+        assert_eq!(either, 2.0 * prefix - prefix * prefix);
+        
+        let sensitive = MatchOptions { case_sensitive: true, ..MatchOptions::default() };
+        let sensitive = match_probability("sa7", &MatchType::Prefix, &sensitive).unwrap();
+        assert_eq!(sensitive, prefix / 4.0);
+        
+        let position = match_probability("0:s,4:a,9:t", &MatchType::Position, &options).unwrap();
+        assert_eq!(position, prefix);
+        
+        let hex = MatchOptions { target: Target::Hex, ..MatchOptions::default() };
+        assert_eq!(match_probability("000", &MatchType::Prefix, &hex), Some(1.0 / 4096.0));
+        assert_eq!(match_probability("000", &MatchType::Contains, &hex), Some(62.0 / 4096.0));
+        
+        let full = MatchOptions { include_prefix: true, ..MatchOptions::default() };
+        assert_eq!(match_probability("npub1sat", &MatchType::Prefix, &full), Some(prefix));
+        
+        // The 7th character from the end is the padded last data character
+        let suffix = match_probability("qsatsat", &MatchType::Suffix, &options).unwrap();
+        assert_eq!(suffix, 0.5 * (1.0 / 32.0_f64).powi(6));
+        assert_eq!(match_probability("xsatsat", &MatchType::Suffix, &options), Some(0.0));
+        let data = MatchOptions { exclude_checksum: true, ..MatchOptions::default() };
+        assert_eq!(match_probability("xsatsat", &MatchType::Suffix, &data), Some((1.0 / 32.0_f64).powi(7)));
+        
+        assert!(match_probability("^sat", &MatchType::Regex, &options).is_none());
+        
+        // A boundary is needed on both sides of the 54 inner positions, on
+        // one side at the two ends
+        let bounded = MatchOptions { boundary: Some('0'), ..MatchOptions::default() };
+        let side = 1.0 / 32.0;
+        assert_eq!(
+            match_probability("sat", &MatchType::Contains, &bounded),
+            Some(prefix * (2.0 * side + 54.0 * side * side))
+        );
+        assert_eq!(match_probability("sat", &MatchType::Prefix, &bounded), Some(prefix));
+        
+        // Bech32 has 23 letters and 9 digits, hex 6 and 10, and case doesn't matter
+        let letter = 23.0 / 32.0;
+        assert_eq!(match_probability("alpha:3", &MatchType::Charclass, &options), Some(letter * letter * letter));
+        let sensitive = MatchOptions { case_sensitive: true, ..MatchOptions::default() };
+        assert_eq!(match_probability("alpha:3", &MatchType::Charclass, &sensitive), Some(letter * letter * letter));
+        assert_eq!(match_probability("numeric:2", &MatchType::Charclass, &options), Some(9.0 / 32.0 * (9.0 / 32.0)));
+        assert_eq!(match_probability("numeric:2", &MatchType::Charclass, &hex), Some(10.0 / 16.0 * (10.0 / 16.0)));
+        
+        let leet = MatchOptions { substitutions: Some(Substitutions::leet()), ..MatchOptions::default() };
+        let pattern = Pattern::new("sit".to_string(), MatchType::Prefix, leet).unwrap();
+        assert_eq!(pattern_probability(&pattern), Some(prefix * 2.0));
+        
+        // A `?` is free, and pieces after a `*` may fall anywhere
+        assert_eq!(match_probability("s?at", &MatchType::Prefix, &options), Some(prefix));
+        assert_eq!(match_probability("s?at", &MatchType::Contains, &options), Some(prefix * 55.0));
+        let sa = match_probability("sa", &MatchType::Prefix, &options).unwrap();
+        let t = match_probability("t", &MatchType::Contains, &options).unwrap();
+        assert_eq!(match_probability("sa*t", &MatchType::Prefix, &options), Some(sa * t));
+        assert_eq!(match_probability("sa*", &MatchType::Prefix, &options), Some(sa));
+        assert_eq!(match_probability("*", &MatchType::Contains, &options), Some(1.0));
+        
+        // Look-alikes widen each character to its group
+        let fuzzy = MatchOptions { fuzzy: Some(CharClasses::lookalikes()), ..MatchOptions::default() };
+        assert_eq!(match_probability("sat", &MatchType::Prefix, &fuzzy), Some(prefix * 2.0));
+        assert_eq!(match_probability("gat", &MatchType::Prefix, &fuzzy), Some(prefix * 3.0));
+        let fuzzy_hex = MatchOptions { target: Target::Hex, ..fuzzy };
+        assert_eq!(match_probability("5", &MatchType::Prefix, &fuzzy_hex), Some(1.0 / 16.0));
+        
+        // Bookends need both ends, and can't be longer than the body
+        let shi = match_probability("shi", &MatchType::Suffix, &options).unwrap();
+        assert_eq!(match_probability("sat...shi", &MatchType::Bookend, &options), Some(prefix * shi));
+        let too_long = format!("{}...{}", "q".repeat(30), "s".repeat(30));
+        assert_eq!(match_probability(&too_long, &MatchType::Bookend, &options), Some(0.0));
+    }
+    
+    #[test]
+    fn test_rarity() {
+        let pattern = |value: &str, match_type| {
+            Pattern::new(value.to_string(), match_type, MatchOptions::default()).unwrap()
+        };
+        let sat = pattern("sats", MatchType::Prefix);
+        assert!((rarity_bits(std::slice::from_ref(&sat)).unwrap() - 20.0).abs() < 1e-9);
+        assert!((rarity_bits(&[pattern("zaps", MatchType::Suffix)]).unwrap() - 20.0).abs() < 1e-9);
+        // Contains matches have more places to land, so the same length is less rare
+        assert!(rarity_bits(&[pattern("sats", MatchType::Contains)]).unwrap() < 20.0);
+        // Every reported pattern adds its bits
+        let both = rarity_bits(&[sat.clone(), pattern("zaps", MatchType::Suffix)]).unwrap();
+        assert!((both - 40.0).abs() < 1e-9);
+        
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: sat,
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: Some(20.0),
+        };
+        assert!(result.format_output(false, SecretOutput::Full).contains("Rarity: 20.0 bits\n"));
+        assert!(result.csv_header(false, SecretOutput::Full).ends_with(",rarity"));
+        assert!(result.format_csv(false, SecretOutput::Full).ends_with(",20.0"));
+        assert!(result.format_json(SecretOutput::Full).unwrap().contains(r#""rarity":20.0"#));
+        assert_eq!(result.provenance().rarity, Some(20.0));
+    }
+    
+    #[test]
+    fn test_format_keypair_csv() {
+        let keypair = NostrKeyPair::generate().unwrap();
+        assert_eq!(keypair_csv_header(&keypair), "npub,nsec,hex_pubkey");
+        assert_eq!(format_keypair_csv(&keypair).split(',').count(), 3);
+        
+        let keypair = NostrKeyPair::generate_mnemonic_with_rng(&mut rand::rng()).unwrap();
+        assert_eq!(keypair_csv_header(&keypair), "npub,nsec,hex_pubkey,mnemonic");
+        assert!(format_keypair_csv(&keypair).ends_with(keypair.mnemonic.as_deref().unwrap()));
+    }
+    
+    #[test]
+    fn test_extended_csv() {
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86400),
+            threads: 4,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        let full = SecretOutput::Full;
+        assert_eq!(result.csv_header(false, full).split(',').count(), result.format_csv(false, full).split(',').count());
+        let header = result.csv_header(true, SecretOutput::Full);
+        let line = result.format_csv(true, SecretOutput::Full);
+        assert_eq!(header.split(',').count(), line.split(',').count());
+        assert!(header.ends_with(",found_at,match_type,case_sensitive,threads"));
+        assert!(line.ends_with(",1970-01-02T00:00:00Z,contains,false,4"));
+    }
+    
+    #[test]
+    fn test_secret_output() {
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate_mnemonic_with_rng(&mut rand::rng()).unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        let nsec = result.keypair.nsec.as_str();
+        let phrase = result.keypair.mnemonic.as_deref().unwrap();
+        let fingerprint = nsec_fingerprint(nsec);
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(fingerprint, nsec_fingerprint(nsec));
+        
+        // Neither the nsec nor the phrase is written anywhere
+        for secrets in [SecretOutput::Omit, SecretOutput::Fingerprint] {
+            let text = result.format_output(false, secrets);
+            let csv = result.csv_header(false, secrets) + &result.format_csv(false, secrets);
+            let json = result.format_json(secrets).unwrap();
+            for output in [&text, &csv, &json] {
+                assert!(!output.contains(nsec) && !output.contains(phrase), "{}", output);
+                assert!(output.contains(&result.keypair.npub));
+            }
+            assert_eq!(
+                result.csv_header(false, secrets).split(',').count(),
+                result.format_csv(false, secrets).split(',').count()
+            );
+        }
+        
+        assert!(result.format_output(false, SecretOutput::Omit).contains("nsec: omitted\n"));
+        assert!(result.csv_header(false, SecretOutput::Omit).starts_with("pattern,npub,hex_pubkey,"));
+        assert!(result.format_output(false, SecretOutput::Fingerprint).contains(&format!("nsec fingerprint: {}\n", fingerprint)));
+        assert!(result.csv_header(false, SecretOutput::Fingerprint).starts_with("pattern,npub,nsec_fingerprint,"));
+        let json: serde_json::Value = serde_json::from_str(&result.format_json(SecretOutput::Fingerprint).unwrap()).unwrap();
+        assert_eq!(json["nsec_fingerprint"], fingerprint.as_str());
+        let json: serde_json::Value = serde_json::from_str(&result.format_json(SecretOutput::Full).unwrap()).unwrap();
+        assert_eq!(json["nsec"], nsec);
+        assert_eq!(json["mnemonic"], phrase);
+    }
+    
+    #[test]
+    fn test_parse_patterns_string() {
+        assert_eq!(parse_patterns_string("sat, q,,abc ", ','), ["sat", "q", "abc"]);
+        assert_eq!(parse_patterns_string("sat q  abc", ' '), ["sat", "q", "abc"]);
+        assert_eq!(parse_patterns_string("sat;q,x;", ';'), ["sat", "q,x"]);
+    }
+    
+    #[test]
+    fn test_run_summary() {
+        let pattern = |value: &str, match_type| {
+            Pattern::new(value.to_string(), match_type, MatchOptions::default()).unwrap()
+        };
+        let patterns = vec![
+            pattern("sat", MatchType::Prefix),
+            pattern("sat", MatchType::Suffix),
+            pattern("q", MatchType::Contains),
+        ];
+        let result = |matched_pattern| VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern,
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        let mut summary = RunSummary::new(&patterns);
+        summary.record(&result(patterns[2].clone()));
+        summary.record(&result(patterns[2].clone()));
+        summary.record(&result(patterns[1].clone()));
+        assert_eq!(summary.matches, 3);
+        assert_eq!(summary.per_pattern.iter().map(|(_, n)| *n).collect::<Vec<_>>(), [0, 1, 2]);
+        
+        let text = summary.format(5000, std::time::Duration::from_secs(2));
+        assert!(text.contains("  Matches: 3\n"), "{}", text);
+        assert!(text.contains("    'sat' (prefix): 0\n    'sat' (suffix): 1\n    'q': 2\n"), "{}", text);
+        assert!(text.contains("  Attempts: 5000\n  Time: 2.00s\n  Average: 2500 keys/sec"), "{}", text);
+        
+        // A --match-all key counts for every pattern
+        let mut summary = RunSummary::new(&patterns);
+        summary.record(&VanityResult { also_matched: patterns[1..].to_vec(), ..result(patterns[0].clone()) });
+        assert_eq!(summary.matches, 1);
+        assert_eq!(summary.per_pattern.iter().map(|(_, n)| *n).collect::<Vec<_>>(), [1, 1, 1]);
+        
+        let many: Vec<Pattern> = (0..=SUMMARY_LIST_ALL).map(|i| pattern(&format!("q{}", i), MatchType::Prefix)).collect();
+        let mut summary = RunSummary::new(&many);
+        summary.record(&result(many[7].clone()));
+        let text = summary.format(5000, std::time::Duration::from_secs(2));
+        assert!(text.contains("  Matches: 1\n    'q7': 1\n    (50 other patterns found nothing)\n"), "{}", text);
+    }
+    
+    #[test]
+    fn test_output_format_from_path() {
+        assert_eq!(OutputFormat::from_path(Path::new("keys.csv")), Some(OutputFormat::Csv));
+        assert_eq!(OutputFormat::from_path(Path::new("keys.JSONL")), Some(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_path(Path::new("dir/keys.txt")), Some(OutputFormat::Text));
+        assert_eq!(OutputFormat::from_path(Path::new("keys.log")), None);
+        assert_eq!(OutputFormat::from_path(Path::new("keys")), None);
+    }
+    
+    #[test]
+    fn test_pattern_output_path() {
+        let dir = Path::new("harvest");
+        assert_eq!(pattern_output_path(dir, "sat", OutputFormat::Csv), dir.join("sat.csv"));
+        assert_eq!(pattern_output_path(dir, "^sat.*/..", OutputFormat::Text), dir.join("_sat_____.txt"));
+        for format in [OutputFormat::Text, OutputFormat::Csv, OutputFormat::Json] {
+            assert_eq!(OutputFormat::from_path(&pattern_output_path(dir, "zap", format)), Some(format));
+        }
+    }
+    
+    #[test]
+    fn test_visible_when_short() {
+        let keypair = NostrKeyPair::from_nsec(
+            "nsec10allq0gjx7fddtzef0ax00mdps9t2kmtrldkyjfs8l5xruwvh2dq0lhhkp",
+        ).unwrap();
+        let short = NostrKeyPair {
+            npub: "npub1zutzeysac".to_string(),
+            nsec: String::new(),
+            hex_pubkey: String::new(),
+            mnemonic: None,
+        };
+        let visible_in = |keypair: &NostrKeyPair, value: &str, match_type: MatchType, options: MatchOptions| {
+            VanityResult {
+                keypair: keypair.clone(),
+                matched_pattern: Pattern::new(value.to_string(), match_type, options).unwrap(),
+                attempts: 1,
+                time_elapsed: std::time::Duration::from_secs(1),
+                nprofile: None,
+                found_at: SystemTime::now(),
+                threads: 1,
+                also_matched: Vec::new(),
+                seeded: false,
+                rarity: None,
+            }
+            .visible_when_short()
+        };
+        let visible = |value: &str, match_type: MatchType, options: MatchOptions| {
+            visible_in(&keypair, value, match_type, options)
+        };
+        
+        assert_eq!(visible("zutzeysa", MatchType::Prefix, MatchOptions::default()), Some(true));
+        assert_eq!(visible("zutzeysac", MatchType::Prefix, MatchOptions::default()), Some(false));
+        assert_eq!(visible("x7nu", MatchType::Suffix, MatchOptions::default()), Some(true));
+        assert_eq!(visible("mud0", MatchType::Contains, MatchOptions::default()), Some(false));
+        let full = MatchOptions { include_prefix: true, ..MatchOptions::default() };
+        assert_eq!(visible("npub1zut", MatchType::Prefix, full), Some(true));
+        let nsec = MatchOptions { target: Target::Nsec, ..MatchOptions::default() };
+        assert_eq!(visible("0all", MatchType::Prefix, nsec), None);
+        assert_eq!(visible_in(&short, "zutzeysac", MatchType::Prefix, MatchOptions::default()), Some(true));
+    }
+    
+    #[test]
+    fn test_highlighted() {
+        let keypair = NostrKeyPair::from_nsec(
+            "nsec10allq0gjx7fddtzef0ax00mdps9t2kmtrldkyjfs8l5xruwvh2dq0lhhkp",
+        ).unwrap();
+        let highlighted = |value: &str, match_type: MatchType, options: MatchOptions| {
+            VanityResult {
+                keypair: keypair.clone(),
+                matched_pattern: Pattern::new(value.to_string(), match_type, options).unwrap(),
+                attempts: 1,
+                time_elapsed: std::time::Duration::from_secs(1),
+                nprofile: None,
+                found_at: SystemTime::now(),
+                threads: 1,
+                also_matched: Vec::new(),
+                seeded: false,
+                rarity: None,
+            }
+            .highlighted(false)
+        };
+        let npub = &keypair.npub;
+        let at = npub.find("mud0").unwrap();
+        let expected = format!("{}[mud0]{}", &npub[..at], &npub[at + 4..]);
+        
+        assert_eq!(highlighted("MUD0", MatchType::Contains, MatchOptions::default()), Some(expected.clone()));
+        let skip = MatchOptions { skip: 2, ..MatchOptions::default() };
+        assert_eq!(highlighted("mud0", MatchType::Contains, skip), Some(expected));
+        assert!(highlighted("zut", MatchType::Prefix, MatchOptions::default()).unwrap().starts_with("npub1[zut]"));
+        let full = MatchOptions { include_prefix: true, ..MatchOptions::default() };
+        assert!(highlighted("npub1z", MatchType::Prefix, full).unwrap().starts_with("[npub1z]ut"));
+        let nsec = MatchOptions { target: Target::Nsec, ..MatchOptions::default() };
+        assert!(highlighted("0all", MatchType::Prefix, nsec).unwrap().starts_with("nsec1[0all]q0gj"));
+        assert_eq!(highlighted("1:u", MatchType::Position, MatchOptions::default()), None);
+        let bookend = format!("zut...{}", &npub[npub.len() - 2..]);
+        assert_eq!(
+            highlighted(&bookend, MatchType::Bookend, MatchOptions::default()),
+            Some(format!("npub1[zut]{}[{}]", &npub[8..npub.len() - 2], &npub[npub.len() - 2..]))
+        );
+        
+        let result = VanityResult {
+            keypair: keypair.clone(),
+            matched_pattern: Pattern::new("zut".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        assert!(result.highlighted(true).unwrap().starts_with("npub1\x1b[1;32mzut\x1b[0m"));
+        assert!(!result.format_output(false, SecretOutput::Full).contains('\x1b'));
+        assert!(result.format_output(true, SecretOutput::Full).contains("\x1b[1;32mzut"));
+        
+        // --match-all marks every pattern, merging parts that overlap
+        let contains = |value: &str| Pattern::new(value.to_string(), MatchType::Contains, MatchOptions::default()).unwrap();
+        let result = VanityResult {
+            also_matched: vec![contains("mud0"), contains(&npub[at + 2..at + 6])],
+            ..result
+        };
+        let highlighted = result.highlighted(false).unwrap();
+        assert!(highlighted.starts_with("npub1[zut]"));
+        assert_eq!(highlighted.matches('[').count(), 2);
+        assert!(highlighted.contains(&format!("[mud0{}]", &npub[at + 4..at + 6])));
+        let output = result.format_output(false, SecretOutput::Full);
+        assert!(output.contains(&format!("Pattern: zut+mud0+{}\n", &npub[at + 2..at + 6])), "{}", output);
+        assert!(output.contains(&format!("Matched: zut (position 0), mud0 (position {}), ", at - 5)), "{}", output);
+        
+        // --sample marks whichever patterns the key happens to have
+        let patterns = [contains("mud0"), Pattern::new("sat".to_string(), MatchType::Prefix, MatchOptions::default()).unwrap()];
+        assert_eq!(
+            highlight_matches(&patterns, &keypair, Target::Npub, false),
+            Some(format!("{}[mud0]{}", &npub[..at], &npub[at + 4..]))
+        );
+        assert_eq!(highlight_matches(&patterns[1..], &keypair, Target::Npub, false), None);
+        assert_eq!(highlight_matches(&patterns, &keypair, Target::Hex, false), None);
+    }
+    
+    #[test]
+    fn test_write_qr_pngs() {
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("a*c".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        let dir = std::env::temp_dir().join(format!("nostr_vanity_qr_{}", std::process::id()));
+        let stem = format!("a_c-{}", result.keypair.npub);
+        
+        write_qr_pngs(&result, &dir, true, SecretOutput::Full).unwrap();
+        assert!(dir.join(format!("{}.png", stem)).exists());
+        assert!(dir.join(format!("{}-nsec.png", stem)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+        
+        // --qr-nsec can't put the nsec on disk past --no-nsec or --fingerprint
+        for secrets in [SecretOutput::Omit, SecretOutput::Fingerprint] {
+            write_qr_pngs(&result, &dir, true, secrets).unwrap();
+            assert!(dir.join(format!("{}.png", stem)).exists());
+            assert!(!dir.join(format!("{}-nsec.png", stem)).exists());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+    
+    #[test]
+    fn test_check_writable() {
+        let dir = std::env::temp_dir().join(format!("nostr_vanity_writable_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        
+        // Checking a directory creates it but leaves nothing inside
+        check_writable_dir(&dir).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        
+        // A new file is removed again, an existing one is left as it was
+        let path = dir.join("keys.csv");
+        check_writable(&path).unwrap();
+        assert!(!path.exists());
+        fs::write(&path, "npub,nsec\n").unwrap();
+        check_writable(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "npub,nsec\n");
+        
+        assert!(check_writable(&dir.join("missing").join("keys.txt")).is_err());
+        assert!(check_writable_dir(&path.join("qr")).is_err());
+        assert!(check_fifo(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_writer() {
+        let path = std::env::temp_dir().join(format!("nostr_vanity_fifo_{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let made = std::process::Command::new("mkfifo").arg(&path).status();
+        if !made.is_ok_and(|status| status.success()) {
+            return;
+        }
+        check_fifo(&path).unwrap();
+        assert!(check_writable(&path).is_err());
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        // The reader takes the header and one result, then goes away
+        let reader = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut lines = BufReader::new(File::open(path).unwrap()).lines();
+                (lines.next().unwrap().unwrap(), lines.next().unwrap().unwrap())
+            }
+        });
+        let mut writer = ResultWriter::open_fifo(path.clone(), OutputFormat::Csv, false).unwrap();
+        writer.write(&result).unwrap();
+        let (header, line) = reader.join().unwrap();
+        assert_eq!(header, result.csv_header(false, SecretOutput::Full));
+        assert_eq!(line, result.format_csv(false, SecretOutput::Full));
+        
+        // Writing without a reader fails once, then the pipe is left closed
+        assert!(writer.write(&result).is_err());
+        assert!(!writer.is_open());
+        writer.write(&result).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_write_json_file() {
+        let path = std::env::temp_dir().join(format!("nostr_vanity_test_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        for expected in 1..=3 {
+            write_json_file(&result, &path, SecretOutput::Full).unwrap();
+            let parsed: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(parsed.as_array().unwrap().len(), expected);
+        }
+        
+        write_keypair_json_file(&result.keypair, &path).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(parsed[3]["npub"], result.keypair.npub.as_str());
+        
+        fs::write(&path, "{}").unwrap();
+        assert!(write_json_file(&result, &path, SecretOutput::Full).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_seeded_result() {
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        assert!(!result.format_output(false, SecretOutput::Full).contains("Seeded"));
+        assert!(!result.format_json(SecretOutput::Full).unwrap().contains("seeded"));
+        
+        // Seeded keys are flagged everywhere they're reported
+        let result = VanityResult { seeded: true, ..result };
+        assert!(result.format_output(false, SecretOutput::Full).contains("⚠️ Seeded: "));
+        assert!(result.format_json(SecretOutput::Full).unwrap().contains(r#""seeded":true"#));
+        assert!(result.provenance().seeded);
+    }
+    
+    #[test]
+    fn test_result_writer() {
+        let path = std::env::temp_dir().join(format!("nostr_vanity_writer_{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 1,
+            time_elapsed: std::time::Duration::from_secs(1),
+            nprofile: None,
+            found_at: SystemTime::now(),
+            threads: 1,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        // Nothing is created until there's a result, and each result is on
+        // disk as soon as it's written
+        let mut writer = ResultWriter::new(path.clone(), OutputFormat::Csv, false);
+        assert!(!path.exists());
+        writer.write(&result).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        writer.write(&result).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        drop(writer);
+        
+        // A later run continues the file without a second header
+        let mut writer = ResultWriter::new(path.clone(), OutputFormat::Csv, false);
+        writer.write(&result).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+        assert_eq!(contents.matches(&result.csv_header(false, SecretOutput::Full)).count(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_write_meta_file() {
+        let output = std::env::temp_dir().join(format!("nostr_vanity_meta_{}.txt", std::process::id()));
+        let path = meta_path(&output);
+        assert_eq!(path.file_name().unwrap().to_str().unwrap(), format!("nostr_vanity_meta_{}.txt.meta.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let result = VanityResult {
+            keypair: NostrKeyPair::generate().unwrap(),
+            matched_pattern: Pattern::new("q".to_string(), MatchType::Contains, MatchOptions::default()).unwrap(),
+            attempts: 42,
+            time_elapsed: std::time::Duration::from_secs(3),
+            nprofile: None,
+            found_at: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86400),
+            threads: 4,
+            also_matched: Vec::new(),
+            seeded: false,
+            rarity: None,
+        };
+        
+        write_meta_file(&result, &path).unwrap();
+        write_meta_file(&result, &path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        let meta = &parsed[0];
+        assert_eq!(meta["tool"], "nostr_vanity");
+        assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(meta["npub"], result.keypair.npub.as_str());
+        assert_eq!(meta["pattern"], "q");
+        assert_eq!(meta["match_type"], "contains");
+        assert_eq!(meta["target"], "npub");
+        assert_eq!(meta["attempts"], 42);
+        assert_eq!(meta["threads"], 4);
+        assert_eq!(meta["found_at"], "1970-01-02T00:00:00Z");
+        assert_eq!(meta["seeded"], false);
+        // Provenance never carries the secret key
+        assert!(!contents.contains(&result.keypair.nsec));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+// Bindings for running key generation and matching in a browser, built with
+// the `wasm` feature. There are no threads there, so a search is a loop of
+// `generateOne` and `matches` driven from JavaScript, ideally in a Web
+// Worker so the page stays responsive.
+use wasm_bindgen::prelude::*;
+
+use crate::generator::NostrKeyPair;
+use crate::matcher::{MatchOptions, Pattern};
+
+// A generated key as JavaScript sees it
+#[wasm_bindgen(getter_with_clone)]
+pub struct KeyPair {
+    pub npub: String,
+    pub nsec: String,
+    #[wasm_bindgen(js_name = hexPubkey)]
+    pub hex_pubkey: String,
+}
+
+// One fresh key, drawn from the browser's crypto.getRandomValues
+#[wasm_bindgen(js_name = generateOne)]
+pub fn generate_one() -> Result<KeyPair, JsError> {
+    let keypair = NostrKeyPair::generate()?;
+    Ok(KeyPair {
+        npub: keypair.npub.clone(),
+        nsec: keypair.nsec.clone(),
+        hex_pubkey: keypair.hex_pubkey.clone(),
+    })
+}
+
+// Whether `npub` matches `pattern` as `match_type` (prefix, suffix,
+// contains, either, regex and so on), the same way the command line
+// matches it. The pattern is checked and built on every call, so an invalid
+// one throws.
+#[wasm_bindgen]
+pub fn matches(npub: &str, pattern: &str, match_type: &str, case_sensitive: bool) -> Result<bool, JsError> {
+    let options = MatchOptions {
+        case_sensitive,
+        ..MatchOptions::default()
+    };
+    let pattern = Pattern::new(pattern.to_string(), match_type.parse()?, options)?;
+    Ok(pattern.matches(npub))
+}